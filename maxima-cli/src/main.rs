@@ -19,8 +19,16 @@ use maxima::{
 
 use maxima::{
     content::{
-        downloader::ZipDownloader,
+        builds::InstalledBuilds,
+        concurrency::Concurrency,
+        dedup::DedupMode,
+        downloader::{BuildSource, ZipDownloader},
+        filter::{InstallFilter, InstallFilterBuilder},
+        installed::InstalledGame,
         manager::{QueuedGame, QueuedGameBuilder},
+        progress::DownloadProgress,
+        retry::RetryPolicy,
+        schedule::{DownloadSchedule, DownloadWindow},
         ContentService,
     },
     core::{
@@ -33,6 +41,7 @@ use maxima::{
         cloudsync::CloudSyncLockMode,
         launch::{self, LaunchMode, LaunchOptions},
         library::OwnedTitle,
+        locale::Locale,
         manifest::{self, MANIFEST_RELATIVE_PATH},
         service_layer::{
             ServiceGetBasicPlayerRequestBuilder, ServiceGetLegacyCatalogDefsRequestBuilder,
@@ -124,6 +133,175 @@ enum Mode {
         /// Absolute path to install the game to
         #[arg(long)]
         path: String,
+
+        /// Only install these languages (e.g. en_US). Can be passed multiple times
+        #[arg(long = "language")]
+        languages: Vec<String>,
+
+        /// Language to play the game in (e.g. fr_FR). Defaults to Maxima's locale
+        #[arg(long)]
+        locale: Option<String>,
+
+        /// Only install these optional components. Can be passed multiple times
+        #[arg(long = "component")]
+        components: Vec<String>,
+
+        /// Install from a build zip or an extracted build on disk instead of the CDN
+        #[arg(long)]
+        source: Option<String>,
+
+        /// Build ID of the local source. Defaults to the live build
+        #[arg(long)]
+        build: Option<String>,
+
+        /// Download this many files at once instead of the configured amount
+        #[arg(long)]
+        connections: Option<usize>,
+
+        /// Scale the number of files downloaded at once with throughput, up to --connections
+        #[arg(long)]
+        adaptive: bool,
+    },
+    /// Update an installed game to the live build, only downloading changed files
+    Update {
+        /// Game slug (from list-games output)
+        slug: String,
+    },
+    /// Check an installed game's files against its build
+    Verify {
+        /// Game slug (from list-games output)
+        slug: String,
+    },
+    /// Verify an installed game and download anything missing or corrupt
+    Repair {
+        /// Game slug (from list-games output)
+        slug: String,
+    },
+    /// Uninstall a game. Saves and other files the game created are kept unless --purge is passed
+    Uninstall {
+        /// Game slug (from list-games output)
+        slug: String,
+
+        /// Remove the whole install folder, including files not part of the build
+        #[arg(long)]
+        purge: bool,
+    },
+    /// Pack an installed game into a zip laid out like its build on the CDN, for installing
+    /// later with `install --source`
+    Export {
+        /// Game slug (from list-games output)
+        slug: String,
+
+        /// Path of the zip to write
+        out: String,
+    },
+    /// Switch an installed game to another language, downloading its files if they're missing
+    ChangeLanguage {
+        /// Game slug (from list-games output)
+        slug: String,
+
+        /// Language to switch to (e.g. fr_FR)
+        locale: String,
+    },
+    /// Move an installed game to another library folder
+    Move {
+        /// Game slug (from list-games output)
+        slug: String,
+
+        /// Folder to move the game's install folder into
+        library: String,
+    },
+    /// Install another build of a game next to the active one, without touching it
+    AddBuild {
+        /// Game slug (from list-games output)
+        slug: String,
+
+        /// Build ID to install. Defaults to the live build
+        #[arg(long)]
+        build: Option<String>,
+
+        /// Absolute path to install the build to. Defaults to a folder next to the active build
+        #[arg(long)]
+        path: Option<String>,
+    },
+    /// List the installed builds of a game
+    Builds {
+        /// Game slug (from list-games output)
+        slug: String,
+    },
+    /// Make an installed build the one the game launches
+    ActivateBuild {
+        /// Game slug (from list-games output)
+        slug: String,
+
+        /// Build ID (from builds output)
+        build: String,
+    },
+    /// Go back to the build that was active before the current one
+    Rollback {
+        /// Game slug (from list-games output)
+        slug: String,
+    },
+    /// Delete a build installed next to the active one
+    RemoveBuild {
+        /// Game slug (from list-games output)
+        slug: String,
+
+        /// Build ID (from builds output)
+        build: String,
+    },
+    /// Limit download speed for all games. Omit the limit (or pass 0) to remove it
+    DownloadLimit {
+        /// Speed limit in KiB/s
+        limit: Option<u64>,
+    },
+    /// Set how many files are downloaded at once for all games. Omit the count to reset it
+    Connections {
+        count: Option<usize>,
+
+        /// Scale the number of files downloaded at once with throughput, up to the count
+        #[arg(long)]
+        adaptive: bool,
+    },
+    /// Set how failed files are downloaded again for all games. Omitted values are reset
+    Retries {
+        /// Tries per file before the download fails, including the first
+        attempts: Option<u32>,
+
+        /// Milliseconds before the first retry, doubling with every retry after it
+        #[arg(long)]
+        base_delay: Option<u64>,
+
+        /// Most milliseconds between two tries
+        #[arg(long)]
+        max_delay: Option<u64>,
+    },
+    /// Share identical files between installs instead of downloading them again
+    Dedup {
+        /// `off`, `reflink` for copy-on-write clones only, or `hardlink` to fall back to
+        /// hardlinks where clones aren't supported
+        #[arg(value_parser = ["off", "reflink", "hardlink"])]
+        mode: String,
+    },
+    /// Only download in a window of local time. Omit the window to download any time
+    Schedule {
+        /// `HH:MM-HH:MM`, e.g. `23:00-07:00` for overnight only
+        window: Option<String>,
+
+        /// Pause downloads while a game launched through Maxima is running
+        #[arg(long)]
+        pause_while_playing: bool,
+    },
+    /// Set the language games, store and library text are in. Omit the locale to detect it again
+    Locale {
+        /// Locale to use (e.g. de_DE)
+        locale: Option<String>,
+    },
+    /// Add every installed build of a game to the content store, so new installs can link
+    /// its files
+    IndexBuilds {
+        /// Game slug (from list-games output)
+        slug: String,
     },
     /// Get game info (offer_id, installed status) by slug
     GameInfo {
@@ -351,9 +529,68 @@ async fn startup() -> Result<()> {
             build_id,
             file,
         } => download_specific_file(maxima_arc.clone(), &offer_id, &build_id, &file).await,
-        Mode::Install { slug, path } => {
-            install_game(maxima_arc.clone(), &slug, &path).await
+        Mode::Install {
+            slug,
+            path,
+            languages,
+            locale,
+            components,
+            source,
+            build,
+            connections,
+            adaptive,
+        } => {
+            let mut game = QueuedGameBuilder::default();
+            game.filter(install_filter(languages, components)?);
+            if let Some(concurrency) = concurrency_arg(connections, adaptive) {
+                game.concurrency(concurrency);
+            }
+
+            install_game(
+                maxima_arc.clone(),
+                &slug,
+                &path,
+                game,
+                locale,
+                source,
+                build,
+            )
+            .await
+        }
+        Mode::Update { slug } => update_game(maxima_arc.clone(), &slug).await,
+        Mode::Verify { slug } => verify_game(maxima_arc.clone(), &slug, false).await,
+        Mode::Repair { slug } => verify_game(maxima_arc.clone(), &slug, true).await,
+        Mode::Export { slug, out } => export_game(maxima_arc.clone(), &slug, &out).await,
+        Mode::ChangeLanguage { slug, locale } => {
+            change_language(maxima_arc.clone(), &slug, &locale).await
+        }
+        Mode::Move { slug, library } => move_game(maxima_arc.clone(), &slug, &library).await,
+        Mode::AddBuild { slug, build, path } => {
+            add_build(maxima_arc.clone(), &slug, build, path).await
+        }
+        Mode::Builds { slug } => list_builds(maxima_arc.clone(), &slug).await,
+        Mode::ActivateBuild { slug, build } => {
+            activate_build(maxima_arc.clone(), &slug, &build).await
         }
+        Mode::Rollback { slug } => rollback_build(maxima_arc.clone(), &slug).await,
+        Mode::RemoveBuild { slug, build } => remove_build(maxima_arc.clone(), &slug, &build).await,
+        Mode::Uninstall { slug, purge } => uninstall_game(maxima_arc.clone(), &slug, !purge).await,
+        Mode::DownloadLimit { limit } => set_download_limit(maxima_arc.clone(), limit).await,
+        Mode::Connections { count, adaptive } => {
+            set_concurrency(maxima_arc.clone(), concurrency_arg(count, adaptive)).await
+        }
+        Mode::Retries {
+            attempts,
+            base_delay,
+            max_delay,
+        } => set_retry_policy(maxima_arc.clone(), attempts, base_delay, max_delay).await,
+        Mode::Dedup { mode } => set_dedup(maxima_arc.clone(), &mode).await,
+        Mode::Schedule {
+            window,
+            pause_while_playing,
+        } => set_schedule(maxima_arc.clone(), window, pause_while_playing).await,
+        Mode::Locale { locale } => set_locale(maxima_arc.clone(), locale).await,
+        Mode::IndexBuilds { slug } => index_builds(maxima_arc.clone(), &slug).await,
         Mode::GameInfo { slug } => {
             game_info(maxima_arc.clone(), &slug).await
         }
@@ -469,6 +706,7 @@ async fn interactive_install_game(maxima_arc: LockedMaxima) -> Result<()> {
         .offer_id(offer_id)
         .build_id(build.build_id().to_owned())
         .path(path.clone())
+        .locale(maxima.locale().full_str())
         .build()?;
 
     let start_time = Instant::now();
@@ -478,19 +716,23 @@ async fn interactive_install_game(maxima_arc: LockedMaxima) -> Result<()> {
 
     loop {
         let mut maxima = maxima_arc.lock().await;
+        maxima.update().await;
 
+        let mut progress = None;
         for event in maxima.consume_pending_events() {
             match event {
                 MaximaEvent::ReceivedLSXRequest(_pid, _request) => (),
+                MaximaEvent::DownloadProgress(update) => progress = Some(update),
+                MaximaEvent::InstallFailed(_, reason) => bail!("Install failed: {}", reason),
                 _ => {}
             }
         }
 
-        maxima.update().await;
+        if let Some(progress) = progress {
+            log_progress(&progress);
+        }
 
-        if let Some(downloader) = maxima.content_manager().current() {
-            info!("Downloading: {}%/100%", downloader.percentage_done());
-        } else {
+        if maxima.content_manager().current().is_none() {
             break;
         }
 
@@ -510,7 +752,15 @@ async fn interactive_install_game(maxima_arc: LockedMaxima) -> Result<()> {
     Ok(())
 }
 
-async fn install_game(maxima_arc: LockedMaxima, slug: &str, path: &str) -> Result<()> {
+async fn install_game(
+    maxima_arc: LockedMaxima,
+    slug: &str,
+    path: &str,
+    mut game: QueuedGameBuilder,
+    locale: Option<String>,
+    source: Option<String>,
+    build: Option<String>,
+) -> Result<()> {
     let mut maxima = maxima_arc.lock().await;
 
     // Resolve slug to offer_id
@@ -524,7 +774,67 @@ async fn install_game(maxima_arc: LockedMaxima, slug: &str, path: &str) -> Resul
 
     info!("Installing {} ({})", game_name, offer_id);
 
-    // Get available builds and pick the live one
+    let build_id = match build {
+        Some(build_id) => build_id,
+        None => {
+            // Get available builds and pick the live one
+            let builds = maxima
+                .content_manager()
+                .service()
+                .available_builds(&offer_id)
+                .await?;
+            let build = builds.live_build();
+            if build.is_none() {
+                bail!("No suitable build found for '{}'", slug);
+            }
+
+            let build = build.unwrap();
+            info!("Build: {}", build.to_string());
+            build.build_id().to_owned()
+        }
+    };
+
+    let install_path = PathBuf::from(path);
+    if !install_path.is_absolute() {
+        bail!("Path '{}' is not absolute", path);
+    }
+
+    let locale = locale.unwrap_or_else(|| maxima.locale().full_str().to_owned());
+    game.offer_id(offer_id)
+        .build_id(build_id)
+        .path(install_path)
+        .locale(locale);
+
+    if let Some(source) = source {
+        let source = PathBuf::from(source);
+        if !source.exists() {
+            bail!("Source '{}' does not exist", source.display());
+        }
+
+        info!("Installing from {}", source.display());
+        game.source(BuildSource::local(source));
+    }
+
+    let start_time = Instant::now();
+    maxima
+        .content_manager()
+        .install_now(game.build()?)
+        .await?;
+
+    drop(maxima);
+
+    wait_for_install(maxima_arc, start_time).await
+}
+
+async fn update_game(maxima_arc: LockedMaxima, slug: &str) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = maxima.mut_library().game_by_base_slug(slug).await?;
+    if offer.is_none() {
+        bail!("No owned game found for slug '{}'", slug);
+    }
+    let offer_id = offer.unwrap().offer_id().to_owned();
+
     let builds = maxima
         .content_manager()
         .service()
@@ -536,54 +846,556 @@ async fn install_game(maxima_arc: LockedMaxima, slug: &str, path: &str) -> Resul
     }
 
     let build = build.unwrap();
-    info!("Build: {}", build.to_string());
+    info!("Updating {} to build {}", offer_id, build.to_string());
 
-    let install_path = PathBuf::from(path);
-    if !install_path.is_absolute() {
-        bail!("Path '{}' is not absolute", path);
+    // Games installed before Maxima kept install records can't be updated, nothing says
+    // which of their files the new build dropped
+    let start_time = Instant::now();
+    maxima
+        .content_manager()
+        .add_update(&offer_id, build.build_id())
+        .await?;
+
+    drop(maxima);
+
+    wait_for_install(maxima_arc, start_time).await
+}
+
+async fn uninstall_game(maxima_arc: LockedMaxima, slug: &str, keep_user_files: bool) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = maxima.mut_library().game_by_base_slug(slug).await?;
+    let offer = match offer {
+        Some(offer) => offer.clone(),
+        None => bail!("No owned game found for slug '{}'", slug),
+    };
+
+    if !offer.is_installed().await {
+        bail!("{} is not installed", offer.offer().display_name());
     }
 
-    let game = QueuedGameBuilder::default()
-        .offer_id(offer_id)
-        .build_id(build.build_id().to_owned())
-        .path(install_path)
-        .build()?;
+    offer.uninstall(maxima.content_manager(), keep_user_files).await?;
+    info!("Uninstalled {}", offer.offer().display_name());
+    Ok(())
+}
+
+async fn verify_game(maxima_arc: LockedMaxima, slug: &str, repair: bool) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = maxima.mut_library().game_by_base_slug(slug).await?;
+    if offer.is_none() {
+        bail!("No owned game found for slug '{}'", slug);
+    }
+    let offer_id = offer.unwrap().offer_id().to_owned();
 
     let start_time = Instant::now();
-    maxima.content_manager().install_now(game).await?;
+    let report = if repair {
+        maxima.content_manager().repair(&offer_id).await?
+    } else {
+        maxima.content_manager().verify(&offer_id).await?
+    };
+
+    for file in report.missing() {
+        warn!("Missing: {}", file);
+    }
+    for file in report.corrupt() {
+        warn!("Corrupt: {}", file);
+    }
+    for file in report.extra() {
+        info!("Extra: {}", file);
+    }
+
+    info!(
+        "Checked {} files: {} missing, {} corrupt, {} extra",
+        report.checked(),
+        report.missing().len(),
+        report.corrupt().len(),
+        report.extra().len()
+    );
+
+    if !repair || report.is_intact() {
+        return Ok(());
+    }
+
+    drop(maxima);
+
+    info!("Downloading {} files", report.bad_entries().len());
+    wait_for_install(maxima_arc, start_time).await
+}
+
+async fn export_game(maxima_arc: LockedMaxima, slug: &str, out: &str) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = maxima.mut_library().game_by_base_slug(slug).await?;
+    if offer.is_none() {
+        bail!("No owned game found for slug '{}'", slug);
+    }
+    let offer_id = offer.unwrap().offer_id().to_owned();
+
+    let out = PathBuf::from(out);
+    if out.exists() {
+        bail!("'{}' already exists", out.display());
+    }
+
+    let start_time = Instant::now();
+    maxima.content_manager().export(&offer_id, &out).await?;
+
+    info!(
+        "Exported {} to {} in {}s",
+        slug,
+        out.display(),
+        start_time.elapsed().as_secs()
+    );
+    Ok(())
+}
+
+async fn change_language(maxima_arc: LockedMaxima, slug: &str, locale: &str) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = maxima.mut_library().game_by_base_slug(slug).await?;
+    if offer.is_none() {
+        bail!("No owned game found for slug '{}'", slug);
+    }
+    let offer_id = offer.unwrap().offer_id().to_owned();
+
+    let start_time = Instant::now();
+    let queued = maxima
+        .content_manager()
+        .change_language(&offer_id, locale)
+        .await?;
+    if !queued {
+        info!("Switched {} to {}", slug, locale);
+        return Ok(());
+    }
+
+    drop(maxima);
+
+    info!("Downloading {} files", locale);
+    wait_for_install(maxima_arc, start_time).await
+}
+
+async fn move_game(maxima_arc: LockedMaxima, slug: &str, library: &str) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = maxima.mut_library().game_by_base_slug(slug).await?;
+    if offer.is_none() {
+        bail!("No owned game found for slug '{}'", slug);
+    }
+    let offer_id = offer.unwrap().offer_id().to_owned();
+
+    maxima
+        .content_manager()
+        .move_install(&offer_id, &PathBuf::from(library))
+        .await?;
+    drop(maxima);
+
+    let start_time = Instant::now();
+    loop {
+        let mut maxima = maxima_arc.lock().await;
+        maxima.update().await;
+
+        let mut progress = None;
+        for event in maxima.consume_pending_events() {
+            match event {
+                MaximaEvent::DownloadProgress(update) if update.offer_id() == &offer_id => {
+                    progress = Some(update)
+                }
+                MaximaEvent::InstallMoveFailed(_, reason) => bail!("Move failed: {}", reason),
+                MaximaEvent::InstallMoved(_) => {
+                    let elapsed = start_time.elapsed();
+                    info!("Moved {} in {}s", slug, elapsed.as_secs());
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(progress) = progress {
+            log_progress(&progress);
+        }
+
+        drop(maxima);
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+}
+
+async fn add_build(
+    maxima_arc: LockedMaxima,
+    slug: &str,
+    build: Option<String>,
+    path: Option<String>,
+) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = maxima.mut_library().game_by_base_slug(slug).await?;
+    if offer.is_none() {
+        bail!("No owned game found for slug '{}'", slug);
+    }
+    let offer_id = offer.unwrap().offer_id().to_owned();
+
+    let build_id = match build {
+        Some(build) => build,
+        None => {
+            let builds = maxima
+                .content_manager()
+                .service()
+                .available_builds(&offer_id)
+                .await?;
+            match builds.live_build() {
+                Some(build) => build.build_id().to_owned(),
+                None => bail!("No suitable build found for '{}'", slug),
+            }
+        }
+    };
+
+    let path = match path {
+        Some(path) if !PathBuf::from(&path).is_absolute() => {
+            bail!("Path '{}' is not absolute", path)
+        }
+        path => path.map(PathBuf::from),
+    };
+
+    info!("Installing build {} of {} side by side", build_id, offer_id);
+    let start_time = Instant::now();
+    maxima
+        .content_manager()
+        .add_build(&offer_id, &build_id, path)
+        .await?;
 
     drop(maxima);
 
+    wait_for_install(maxima_arc, start_time).await?;
+    info!("Run `activate-build {} {}` to switch to it", slug, build_id);
+    Ok(())
+}
+
+async fn list_builds(maxima_arc: LockedMaxima, slug: &str) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = maxima.mut_library().game_by_base_slug(slug).await?;
+    if offer.is_none() {
+        bail!("No owned game found for slug '{}'", slug);
+    }
+    let offer_id = offer.unwrap().offer_id().to_owned();
+
+    let active = InstalledGame::load(&offer_id).await?;
+    let builds = InstalledBuilds::load(&offer_id).await?;
+    if active.is_none() && builds.inactive().is_empty() {
+        bail!("{} is not installed", slug);
+    }
+
+    if let Some(active) = active {
+        info!(
+            "{} (active): {}",
+            active.build_id(),
+            active.path().display()
+        );
+    }
+
+    for build in builds.inactive() {
+        let previous = builds.previous().as_ref() == Some(build.build_id());
+        info!(
+            "{}{}: {}",
+            build.build_id(),
+            if previous { " (previous)" } else { "" },
+            build.path().display()
+        );
+    }
+
+    Ok(())
+}
+
+async fn activate_build(maxima_arc: LockedMaxima, slug: &str, build_id: &str) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = maxima.mut_library().game_by_base_slug(slug).await?;
+    if offer.is_none() {
+        bail!("No owned game found for slug '{}'", slug);
+    }
+    let offer = offer.unwrap().clone();
+    let offer_id = offer.offer_id().to_owned();
+
+    maxima
+        .content_manager()
+        .activate_build(&offer_id, build_id)
+        .await?;
+    // Points the registry at legacy builds, they have no touchup doing it
+    maxima.update().await;
+    info!("Activated build {} of {}", build_id, slug);
+    Ok(())
+}
+
+async fn rollback_build(maxima_arc: LockedMaxima, slug: &str) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = maxima.mut_library().game_by_base_slug(slug).await?;
+    if offer.is_none() {
+        bail!("No owned game found for slug '{}'", slug);
+    }
+    let offer = offer.unwrap().clone();
+    let offer_id = offer.offer_id().to_owned();
+
+    let build_id = maxima.content_manager().rollback(&offer_id).await?;
+    maxima.update().await;
+    info!("Rolled {} back to build {}", slug, build_id);
+    Ok(())
+}
+
+async fn remove_build(maxima_arc: LockedMaxima, slug: &str, build_id: &str) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = maxima.mut_library().game_by_base_slug(slug).await?;
+    if offer.is_none() {
+        bail!("No owned game found for slug '{}'", slug);
+    }
+    let offer_id = offer.unwrap().offer_id().to_owned();
+
+    maxima
+        .content_manager()
+        .remove_build(&offer_id, build_id)
+        .await?;
+    info!("Removed build {} of {}", build_id, slug);
+    Ok(())
+}
+
+fn install_filter(languages: Vec<String>, components: Vec<String>) -> Result<InstallFilter> {
+    let mut filter = InstallFilterBuilder::default();
+    if !languages.is_empty() {
+        filter.languages(languages);
+    }
+    if !components.is_empty() {
+        filter.components(components);
+    }
+
+    Ok(filter.build()?)
+}
+
+/// `None` when neither option was passed, leaving the configured concurrency
+fn concurrency_arg(count: Option<usize>, adaptive: bool) -> Option<Concurrency> {
+    if !adaptive && count.unwrap_or(0) == 0 {
+        return None;
+    }
+
+    Some(Concurrency::from_count(count, adaptive))
+}
+
+async fn set_concurrency(maxima_arc: LockedMaxima, concurrency: Option<Concurrency>) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let concurrency = concurrency.unwrap_or_default();
+    maxima
+        .content_manager()
+        .set_concurrency(concurrency)
+        .await?;
+
+    match concurrency {
+        Concurrency::Fixed(count) => info!("Downloading {} files at once", count),
+        Concurrency::Adaptive { max, .. } => info!(
+            "Downloading up to {} files at once, depending on throughput",
+            max
+        ),
+    }
+
+    Ok(())
+}
+
+async fn set_retry_policy(
+    maxima_arc: LockedMaxima,
+    attempts: Option<u32>,
+    base_delay: Option<u64>,
+    max_delay: Option<u64>,
+) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let default = RetryPolicy::default();
+    let retry = RetryPolicy::new(
+        attempts.unwrap_or(*default.max_attempts()),
+        std::time::Duration::from_millis(base_delay.unwrap_or(*default.base_delay_ms())),
+        std::time::Duration::from_millis(max_delay.unwrap_or(*default.max_delay_ms())),
+    );
+    maxima
+        .content_manager()
+        .set_retry_policy(retry.clone())
+        .await?;
+
+    info!(
+        "Trying failed files up to {} times, waiting {}ms at first and at most {}ms between tries",
+        retry.max_attempts(),
+        retry.base_delay_ms(),
+        retry.max_delay_ms()
+    );
+    Ok(())
+}
+
+async fn set_dedup(maxima_arc: LockedMaxima, mode: &str) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let mode = match mode {
+        "reflink" => DedupMode::Reflink,
+        "hardlink" => DedupMode::Hardlink,
+        _ => DedupMode::Off,
+    };
+    maxima.content_manager().set_dedup(mode).await?;
+
+    match mode {
+        DedupMode::Off => info!("Installs no longer share files"),
+        DedupMode::Reflink => info!("Installs share identical files as copy-on-write clones"),
+        DedupMode::Hardlink => info!("Installs share identical files as clones or hardlinks"),
+    }
+
+    Ok(())
+}
+
+async fn set_locale(maxima_arc: LockedMaxima, locale: Option<String>) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let locale = match locale {
+        Some(code) => match Locale::from_code(&code) {
+            Some(locale) => Some(locale),
+            None => {
+                let supported: Vec<&str> = Locale::ALL.iter().map(|x| x.full_str()).collect();
+                bail!(
+                    "Unknown locale '{}', expected one of {}",
+                    code,
+                    supported.join(", ")
+                );
+            }
+        },
+        None => None,
+    };
+    let detected = locale.is_none();
+    maxima.set_locale(locale).await?;
+
+    if detected {
+        info!("Using detected locale {}", maxima.locale());
+    } else {
+        info!("Using {}", maxima.locale());
+    }
+
+    Ok(())
+}
+
+async fn set_schedule(
+    maxima_arc: LockedMaxima,
+    window: Option<String>,
+    pause_while_playing: bool,
+) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let window = match window {
+        Some(window) => match DownloadWindow::parse(&window) {
+            Some(window) => Some(window),
+            None => bail!("Invalid download window '{}', expected HH:MM-HH:MM", window),
+        },
+        None => None,
+    };
+    let schedule = DownloadSchedule::new(window, pause_while_playing);
+    maxima.content_manager().set_schedule(schedule).await?;
+
+    match window {
+        Some(window) => info!("Downloading only from {}", window),
+        None => info!("Downloading at any time"),
+    }
+    if pause_while_playing {
+        info!("Downloads pause while a game is running");
+    }
+
+    Ok(())
+}
+
+async fn index_builds(maxima_arc: LockedMaxima, slug: &str) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let offer = maxima.mut_library().game_by_base_slug(slug).await?;
+    if offer.is_none() {
+        bail!("No owned game found for slug '{}'", slug);
+    }
+    let offer_id = offer.unwrap().offer_id().to_owned();
+
+    let start_time = Instant::now();
+    maxima.content_manager().index_installs(&offer_id).await?;
+    info!(
+        "Indexed the builds of {} in {}s",
+        slug,
+        start_time.elapsed().as_secs()
+    );
+    Ok(())
+}
+
+async fn set_download_limit(maxima_arc: LockedMaxima, limit: Option<u64>) -> Result<()> {
+    let mut maxima = maxima_arc.lock().await;
+
+    let limit = limit.filter(|x| *x > 0);
+    maxima
+        .content_manager()
+        .set_bandwidth_limit(limit.map(|x| x * 1024))
+        .await?;
+
+    match limit {
+        Some(limit) => info!("Download speed limited to {} KiB/s", limit),
+        None => info!("Download speed limit removed"),
+    }
+
+    Ok(())
+}
+
+fn log_progress(progress: &DownloadProgress) {
+    let downloaded_mib = *progress.bytes_downloaded() as f64 / (1024.0 * 1024.0);
+    let total_mib = *progress.bytes_total() as f64 / (1024.0 * 1024.0);
+    let speed_mib = progress.bytes_per_second() / (1024.0 * 1024.0);
+
+    // One line per tick, so the log stays readable
+    let mut line = format!(
+        "{}: {:.2}% ({:.2}/{:.2} MiB, {:.2} MiB/s, {}/{} files",
+        progress.phase(),
+        progress.percentage_done(),
+        downloaded_mib,
+        total_mib,
+        speed_mib,
+        progress.files_done(),
+        progress.files_total()
+    );
+
+    if let Some(eta) = progress.eta() {
+        line += &format!(", {}s left", eta.as_secs());
+    }
+
+    if !progress.failed_files().is_empty() {
+        line += &format!(", {} failed", progress.failed_files().len());
+    }
+
+    line += ")";
+    if let Some(file) = progress.current_file() {
+        line += &format!(" {}", file);
+    }
+
+    info!("{}", line);
+}
+
+async fn wait_for_install(maxima_arc: LockedMaxima, start_time: Instant) -> Result<()> {
     // Progress polling loop
     loop {
         let mut maxima = maxima_arc.lock().await;
+        maxima.update().await;
 
+        let mut progress = None;
         for event in maxima.consume_pending_events() {
             match event {
                 MaximaEvent::ReceivedLSXRequest(_pid, _request) => (),
                 MaximaEvent::InstallFinished(ref _oid) => {
                     info!("Download Complete");
                 }
+                MaximaEvent::DownloadProgress(update) => progress = Some(update),
+                MaximaEvent::InstallFailed(_, reason) => bail!("Install failed: {}", reason),
                 _ => {}
             }
         }
 
-        maxima.update().await;
-
-        if let Some(downloader) = maxima.content_manager().current() {
-            let percent = downloader.percentage_done();
-            let downloaded_bytes = downloader.bytes_downloaded();
-            let total_bytes = downloader.bytes_total();
-            let downloaded_mib = downloaded_bytes as f64 / (1024.0 * 1024.0);
-            let total_mib = total_bytes as f64 / (1024.0 * 1024.0);
+        if let Some(progress) = progress {
+            log_progress(&progress);
+        }
 
-            info!(
-                "Progress: {:.2} ",
-                if percent >= 100.0 { 99.99 } else { percent }
-            );
-            info!("Downloaded: {:.2} MiB", downloaded_mib);
-            info!("Total: {:.2} MiB", total_mib);
-        } else {
+        if maxima.content_manager().current().is_none() {
             break;
         }
 
@@ -979,7 +1791,7 @@ async fn get_legacy_catalog_def(maxima_arc: LockedMaxima, offer_id: &str) -> Res
             SERVICE_REQUEST_GETLEGACYCATALOGDEFS,
             ServiceGetLegacyCatalogDefsRequestBuilder::default()
                 .offer_ids(vec![offer_id.to_owned()])
-                .locale(maxima.locale().clone())
+                .locale(maxima.locale())
                 .build()?,
         )
         .await?;
@@ -1021,9 +1833,10 @@ async fn list_games(maxima_arc: LockedMaxima) -> Result<()> {
 }
 
 async fn locate_game(maxima_arc: LockedMaxima, path: &str) -> Result<()> {
+    let locale = maxima_arc.lock().await.locale().full_str();
     let path = PathBuf::from(path);
     let manifest = manifest::read(path.join(MANIFEST_RELATIVE_PATH)).await?;
-    manifest.run_touchup(&path).await?;
+    manifest.run_touchup(&path, locale).await?;
     info!("Installed!");
     Ok(())
 }
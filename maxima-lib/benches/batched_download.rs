@@ -0,0 +1,206 @@
+//! Downloads a synthetic build of many small files from a local server, once with a range
+//! request per entry and once with small entries batched. The server waits a bit before
+//! answering each request, standing in for the round trip to a CDN.
+//!
+//! `cargo bench -p maxima-lib --bench batched_download -- [files] [latency ms]`
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use futures::StreamExt;
+use maxima::content::{
+    batch::DownloadUnit,
+    downloader::ZipDownloader,
+    export::{archive_directory, ArchiveMetadata},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+const CONCURRENCY: usize = 16;
+const RUNS: usize = 3;
+
+struct Server {
+    archive: Vec<u8>,
+    latency: Duration,
+    requests: AtomicUsize,
+}
+
+/// Enough HTTP/1.1 for the downloader: HEAD, and GET with or without a single range
+async fn serve(server: Arc<Server>, mut socket: TcpStream) -> std::io::Result<()> {
+    let mut buffer = Vec::new();
+    loop {
+        let head_end = loop {
+            if let Some(end) = buffer.windows(4).position(|x| x == b"\r\n\r\n") {
+                break end + 4;
+            }
+
+            let mut chunk = [0; 4096];
+            let read = socket.read(&mut chunk).await?;
+            if read == 0 {
+                return Ok(());
+            }
+            buffer.extend_from_slice(&chunk[..read]);
+        };
+
+        let head = String::from_utf8_lossy(&buffer[..head_end]).to_string();
+        buffer.drain(..head_end);
+        server.requests.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(server.latency).await;
+
+        let total = server.archive.len();
+        let range = head.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            if !name.eq_ignore_ascii_case("range") {
+                return None;
+            }
+
+            let (start, end) = value.trim().strip_prefix("bytes=")?.split_once('-')?;
+            let start: usize = start.parse().ok()?;
+            let end: usize = end.parse().ok()?;
+            Some((start, end.min(total - 1)))
+        });
+
+        let (status, body) = match range {
+            Some((start, end)) => ("206 Partial Content", &server.archive[start..=end]),
+            None => ("200 OK", &server.archive[..]),
+        };
+
+        let mut response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\n", status, body.len());
+        if let Some((start, end)) = range {
+            response += &format!("Content-Range: bytes {}-{}/{}\r\n", start, end, total);
+        }
+        response += "\r\n";
+
+        socket.write_all(response.as_bytes()).await?;
+        if !head.starts_with("HEAD") {
+            socket.write_all(body).await?;
+        }
+    }
+}
+
+async fn start_server(archive: Vec<u8>, latency: Duration) -> (String, Arc<Server>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/build.zip", listener.local_addr().unwrap());
+    let server = Arc::new(Server {
+        archive,
+        latency,
+        requests: AtomicUsize::new(0),
+    });
+
+    let accepting = server.clone();
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            tokio::spawn(serve(accepting.clone(), socket));
+        }
+    });
+
+    (url, server)
+}
+
+/// Writes `files` files of 1 to 8 KiB under `root`, with contents that don't compress
+fn generate_build(root: &Path, files: usize) {
+    let mut seed: u64 = 0x2545F4914F6CDD1D;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+
+    for i in 0..files {
+        let dir = root.join(format!("data/{:03}", i / 256));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let size = 1024 + (next() % (7 * 1024)) as usize;
+        let data: Vec<u8> = (0..size).map(|_| next() as u8).collect();
+        std::fs::write(dir.join(format!("file_{:05}.bin", i)), data).unwrap();
+    }
+}
+
+async fn download(url: &str, out: &Path, id: &str, batched: bool) {
+    let _ = std::fs::remove_dir_all(out);
+    std::fs::create_dir_all(out).unwrap();
+
+    let downloader = ZipDownloader::new(id, url, out).await.unwrap();
+    let entries = downloader.manifest().entries().clone();
+    let units = if batched {
+        downloader.plan_downloads(entries)
+    } else {
+        entries.into_iter().map(DownloadUnit::Single).collect()
+    };
+
+    let downloader = &downloader;
+    futures::stream::iter(units)
+        .map(|unit| async move {
+            let leftover = match unit {
+                DownloadUnit::Single(entry) => vec![entry],
+                DownloadUnit::Batch(entries) => downloader.download_batch(&entries, None).await,
+            };
+
+            for entry in leftover {
+                downloader.download_single_file(&entry, None).await.unwrap();
+            }
+        })
+        .buffer_unordered(CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+}
+
+#[tokio::main]
+async fn main() {
+    // `cargo bench` passes `--bench` along
+    let args: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|x| !x.starts_with("--"))
+        .collect();
+    let files = args.first().and_then(|x| x.parse().ok()).unwrap_or(20_000);
+    let latency = Duration::from_millis(args.get(1).and_then(|x| x.parse().ok()).unwrap_or(20));
+
+    let work = std::env::temp_dir().join("maxima-bench-batched-download");
+    let _ = std::fs::remove_dir_all(&work);
+    let source = work.join("source");
+    let archive = work.join("build.zip");
+    let out: PathBuf = work.join("out");
+
+    println!("Generating {} files", files);
+    generate_build(&source, files);
+    let metadata = ArchiveMetadata::new("bench", "bench", None);
+    archive_directory(&source, &metadata, &archive).unwrap();
+
+    let (url, server) = start_server(std::fs::read(&archive).unwrap(), latency).await;
+    println!(
+        "Serving {} bytes with {:?} latency per request",
+        server.archive.len(),
+        latency
+    );
+
+    for (name, batched) in [("per entry", false), ("batched", true)] {
+        for run in 0..RUNS {
+            server.requests.store(0, Ordering::SeqCst);
+            let id = format!("bench-{}-{}", batched, run);
+
+            let started = Instant::now();
+            download(&url, &out, &id, batched).await;
+            let elapsed = started.elapsed();
+
+            println!(
+                "{:>9}: {:>8.2?}, {:>6} requests, {:>7.1} files/s",
+                name,
+                elapsed,
+                server.requests.load(Ordering::SeqCst),
+                files as f64 / elapsed.as_secs_f64()
+            );
+        }
+    }
+
+    let _ = std::fs::remove_dir_all(&work);
+}
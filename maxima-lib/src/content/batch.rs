@@ -0,0 +1,160 @@
+use crate::content::zip::ZipFileEntry;
+
+/// Entries up to this size get batched, bigger ones spend most of their time transferring
+/// rather than waiting on the request
+const SMALL_ENTRY_SIZE: i64 = 256 * 1024;
+/// Caps a batch's range, so a dropped connection doesn't throw away too much
+const MAX_BATCH_SIZE: i64 = 8 * 1024 * 1024;
+const MAX_BATCH_ENTRIES: usize = 512;
+/// Bytes between two entries that are downloaded and thrown away rather than starting another
+/// request. Fits the local header of an entry with a long name, or a few skipped entries.
+const MAX_GAP: i64 = 16 * 1024;
+
+/// What one request of a download covers
+#[derive(Debug, Clone)]
+pub enum DownloadUnit {
+    Single(ZipFileEntry),
+    /// Small entries lying close together in the build zip, in order
+    Batch(Vec<ZipFileEntry>),
+}
+
+impl DownloadUnit {
+    pub fn entries(&self) -> &[ZipFileEntry] {
+        match self {
+            DownloadUnit::Single(entry) => std::slice::from_ref(entry),
+            DownloadUnit::Batch(entries) => entries,
+        }
+    }
+}
+
+fn is_batchable(entry: &ZipFileEntry) -> bool {
+    // Directories and empty files don't need any request at all
+    *entry.compressed_size() > 0 && *entry.compressed_size() <= SMALL_ENTRY_SIZE
+}
+
+fn fits(batch: &[ZipFileEntry], entry: &ZipFileEntry) -> bool {
+    let (first, last) = match (batch.first(), batch.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return true,
+    };
+
+    let gap = entry.data_offset() - (last.data_offset() + last.compressed_size());
+    let size = entry.data_offset() + entry.compressed_size() - first.data_offset();
+    (0..=MAX_GAP).contains(&gap) && size <= MAX_BATCH_SIZE && batch.len() < MAX_BATCH_ENTRIES
+}
+
+fn flush(units: &mut Vec<DownloadUnit>, batch: &mut Vec<ZipFileEntry>) {
+    match batch.len() {
+        0 => {}
+        1 => units.push(DownloadUnit::Single(batch.remove(0))),
+        _ => units.push(DownloadUnit::Batch(std::mem::take(batch))),
+    }
+}
+
+/// Groups runs of small entries that sit next to each other in the build zip, so each run
+/// takes one range request instead of one per file. Large entries keep their own requests.
+pub fn plan(mut entries: Vec<ZipFileEntry>) -> Vec<DownloadUnit> {
+    entries.sort_by_key(|x| *x.data_offset());
+
+    let mut units = Vec::new();
+    let mut batch = Vec::new();
+    for entry in entries {
+        if !is_batchable(&entry) {
+            units.push(DownloadUnit::Single(entry));
+            continue;
+        }
+
+        if !fits(&batch, &entry) {
+            flush(&mut units, &mut batch);
+        }
+        batch.push(entry);
+    }
+
+    flush(&mut units, &mut batch);
+    units
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `count` entries of `size` bytes each, back to back from `start` and named by index
+    fn contiguous(start: i64, count: usize, size: i64) -> Vec<ZipFileEntry> {
+        (0..count)
+            .map(|x| ZipFileEntry::stored_at(&x.to_string(), start + x as i64 * size, size))
+            .collect()
+    }
+
+    /// A plan written out, batches in brackets
+    fn summary(units: &[DownloadUnit]) -> Vec<String> {
+        units
+            .iter()
+            .map(|unit| match unit {
+                DownloadUnit::Single(entry) => entry.name().clone(),
+                DownloadUnit::Batch(entries) => {
+                    let names: Vec<&str> = entries.iter().map(|x| x.name().as_str()).collect();
+                    format!("[{}]", names.join(" "))
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn adjacent_entries() {
+        let units = plan(contiguous(100, 3, 1000));
+        assert_eq!(summary(&units), ["[0 1 2]"]);
+    }
+
+    #[test]
+    fn gap() {
+        let entries = vec![
+            ZipFileEntry::stored_at("a", 0, 100),
+            ZipFileEntry::stored_at("b", 100 + MAX_GAP, 100),
+            ZipFileEntry::stored_at("c", 200 + MAX_GAP * 2 + 1, 100),
+        ];
+        assert_eq!(summary(&plan(entries)), ["[a b]", "c"]);
+    }
+
+    #[test]
+    fn batch_size_cap() {
+        let count = (MAX_BATCH_SIZE / SMALL_ENTRY_SIZE) as usize;
+        let units = plan(contiguous(0, count + 1, SMALL_ENTRY_SIZE));
+
+        // The last one doesn't fit, and a batch of one is just a single entry
+        assert_eq!(units.len(), 2);
+        assert_eq!(units[0].entries().len(), count);
+        assert!(matches!(units[0], DownloadUnit::Batch(_)));
+        assert_eq!(summary(&units)[1], count.to_string());
+    }
+
+    #[test]
+    fn batch_entries_cap() {
+        let units = plan(contiguous(0, MAX_BATCH_ENTRIES + 2, 10));
+        assert_eq!(units.len(), 2);
+        assert_eq!(units[0].entries().len(), MAX_BATCH_ENTRIES);
+        assert_eq!(units[1].entries().len(), 2);
+        assert!(units.iter().all(|x| matches!(x, DownloadUnit::Batch(_))));
+    }
+
+    #[test]
+    fn unbatchable_entries() {
+        let entries = vec![
+            ZipFileEntry::stored_at("a", 0, 10),
+            ZipFileEntry::stored_at("Data/", 10, 0),
+            ZipFileEntry::stored_at("b", 10, 10),
+            ZipFileEntry::stored_at("large", 20, SMALL_ENTRY_SIZE + 1),
+            ZipFileEntry::stored_at("c", 20 + SMALL_ENTRY_SIZE + 1, 10),
+        ];
+
+        // Empty entries don't break up a run, large ones leave too big a gap
+        assert_eq!(summary(&plan(entries)), ["Data/", "large", "[a b]", "c"]);
+    }
+
+    #[test]
+    fn unsorted_entries() {
+        let mut entries = contiguous(0, 4, 10);
+        entries.reverse();
+        entries.swap(0, 2);
+        assert_eq!(summary(&plan(entries)), ["[0 1 2 3]"]);
+    }
+}
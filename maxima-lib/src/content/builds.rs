@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    content::{installed::InstalledGame, manager::ContentManagerError},
+    util::native::{maxima_dir, NativeError},
+};
+
+const INSTALLS_DIR: &str = "installs";
+
+/// Builds of a game installed next to the active one. The active build keeps its
+/// [`InstalledGame`] record, so updates, repairs and launching never look in here.
+#[derive(Default, Debug, Getters, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InstalledBuilds {
+    offer_id: String,
+    /// Every installed build but the active one
+    #[serde(default)]
+    inactive: Vec<InstalledGame>,
+    /// The build that was active before the current one, what a rollback goes back to
+    #[serde(default)]
+    previous: Option<String>,
+}
+
+fn record_path(offer_id: &str) -> Result<PathBuf, NativeError> {
+    Ok(maxima_dir()?
+        .join(INSTALLS_DIR)
+        .join(format!("{}.builds.json", offer_id)))
+}
+
+/// Where a build goes by default when it's installed next to the one at `active`
+pub fn side_by_side_path(active: &Path, build_id: &str) -> PathBuf {
+    let name = active
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    active.with_file_name(format!("{}-{}", name, build_id))
+}
+
+impl InstalledBuilds {
+    pub async fn load(offer_id: &str) -> Result<InstalledBuilds, ContentManagerError> {
+        let file = record_path(offer_id)?;
+        if !file.exists() {
+            return Ok(InstalledBuilds {
+                offer_id: offer_id.to_owned(),
+                ..Default::default()
+            });
+        }
+
+        let data = fs::read_to_string(file).await?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub(crate) async fn save(&self) -> Result<(), ContentManagerError> {
+        let file = record_path(&self.offer_id)?;
+        fs::create_dir_all(maxima_dir()?.join(INSTALLS_DIR)).await?;
+        fs::write(file, serde_json::to_string(&self)?).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn delete(offer_id: &str) -> Result<(), ContentManagerError> {
+        match fs::remove_file(record_path(offer_id)?).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn get(&self, build_id: &str) -> Option<&InstalledGame> {
+        self.inactive.iter().find(|x| x.build_id() == build_id)
+    }
+
+    /// Keeps track of a build installed next to the active one, replacing whatever
+    /// was recorded for the same build or folder
+    pub(crate) fn add(&mut self, build: InstalledGame) {
+        self.inactive
+            .retain(|x| x.build_id() != build.build_id() && x.path() != build.path());
+        self.inactive.push(build);
+    }
+
+    /// Swaps `build_id` in for `active`, returning its record. `active` is kept as an
+    /// inactive build to roll back to.
+    pub(crate) fn activate(
+        &mut self,
+        build_id: &str,
+        active: Option<InstalledGame>,
+    ) -> Option<InstalledGame> {
+        let idx = self
+            .inactive
+            .iter()
+            .position(|x| x.build_id() == build_id)?;
+        let build = self.inactive.remove(idx);
+
+        self.previous = active.as_ref().map(|x| x.build_id().to_owned());
+        if let Some(active) = active {
+            self.add(active);
+        }
+
+        Some(build)
+    }
+
+    pub(crate) fn remove(&mut self, build_id: &str) -> Option<InstalledGame> {
+        let idx = self
+            .inactive
+            .iter()
+            .position(|x| x.build_id() == build_id)?;
+        if self.previous.as_deref() == Some(build_id) {
+            self.previous = None;
+        }
+        Some(self.inactive.remove(idx))
+    }
+}
@@ -0,0 +1,87 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use log::info;
+
+use crate::content::{manager::DownloaderError, ContentService};
+
+/// Gets a build's CDN URL signed anew, for when the one in use expired
+#[async_trait]
+pub trait UrlRefresher: Send + Sync {
+    async fn refresh(&self) -> Result<String, DownloaderError>;
+}
+
+/// Asks the service layer for a new download URL, skipping its cache
+pub struct ServiceUrlRefresher {
+    service: ContentService,
+    offer_id: String,
+    build_id: String,
+}
+
+impl ServiceUrlRefresher {
+    pub fn new(service: ContentService, offer_id: &str, build_id: &str) -> Self {
+        Self {
+            service,
+            offer_id: offer_id.to_owned(),
+            build_id: build_id.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl UrlRefresher for ServiceUrlRefresher {
+    async fn refresh(&self) -> Result<String, DownloaderError> {
+        let url = self
+            .service
+            .refresh_download_url(&self.offer_id, Some(&self.build_id))
+            .await?;
+        Ok(url.url().to_owned())
+    }
+}
+
+/// The URL a build is downloaded from. Entries report it when it gets rejected as expired,
+/// and it's signed anew.
+pub(crate) struct SignedUrl {
+    /// The URL, and its generation. That's bumped whenever the URL changes, so entries that
+    /// failed on an older one don't refresh it again.
+    state: Mutex<(String, u64)>,
+    refresher: Option<Arc<dyn UrlRefresher>>,
+    /// Only one entry asks for a new URL at a time, the rest use what it got
+    refresh_lock: tokio::sync::Mutex<()>,
+}
+
+impl SignedUrl {
+    pub(crate) fn new(url: String) -> Self {
+        Self {
+            state: Mutex::new((url, 0)),
+            refresher: None,
+            refresh_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn set_refresher(&mut self, refresher: Arc<dyn UrlRefresher>) {
+        self.refresher = Some(refresher);
+    }
+
+    /// The URL to use, and its generation to report it expired with
+    pub(crate) fn current(&self) -> (String, u64) {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Gets the URL signed anew after the one of `generation` got rejected as expired
+    pub(crate) async fn refresh(&self, generation: u64) -> Result<(), DownloaderError> {
+        let _guard = self.refresh_lock.lock().await;
+        if self.state.lock().unwrap().1 != generation {
+            // Someone else already got a new one while we waited
+            return Ok(());
+        }
+
+        let refresher = self.refresher.as_ref().ok_or(DownloaderError::UrlExpired)?;
+        let url = refresher.refresh().await?;
+        info!("Download URL expired, got a new one");
+
+        let mut state = self.state.lock().unwrap();
+        *state = (url, generation + 1);
+        Ok(())
+    }
+}
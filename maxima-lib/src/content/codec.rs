@@ -0,0 +1,592 @@
+use std::{
+    io::{self, Cursor, Read, SeekFrom},
+    pin::Pin,
+    task,
+};
+
+use async_compression::tokio::write::{
+    Deflate64Decoder, DeflateDecoder, LzmaDecoder, XzDecoder, ZstdDecoder,
+};
+use async_trait::async_trait;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use flate2::bufread::DeflateDecoder as BufreadDeflateDecoder;
+use tokio::{
+    fs::File,
+    io::{AsyncSeekExt, AsyncWrite, BufWriter},
+};
+
+use crate::content::{
+    manager::DownloaderError,
+    zip::{CompressionType, ZipFileEntry},
+    zlib::{restore_zlib_state, write_zlib_state},
+};
+
+/// Writes an entry's data to its file, decompressing it on the way. Decoders own their file,
+/// so writing never waits on anything but the file itself.
+#[async_trait]
+pub(crate) trait DownloadDecoder: AsyncWrite + Unpin + Send {
+    /// Adds what it takes to continue from here to `buf`, once everything written so far is
+    /// flushed. False when there's nothing usable to save right now.
+    fn save_state(&mut self, buf: &mut BytesMut) -> bool;
+    /// Continues from what [`DownloadDecoder::save_state`] saved. False if that's unusable,
+    /// the decoder is still at the start of the entry then.
+    fn restore_state(&mut self, buf: &mut Bytes) -> bool;
+
+    /// Moves the file's cursor, returning where it ended up
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64, DownloaderError>;
+}
+
+type DecoderFn = fn(&ZipFileEntry, BufWriter<File>) -> Box<dyn DownloadDecoder>;
+type ReaderFn = for<'a> fn(&ZipFileEntry, &'a [u8]) -> io::Result<Box<dyn Read + 'a>>;
+
+/// How entries using one compression method get decompressed
+pub(crate) struct Codec {
+    method: CompressionType,
+    /// Decompresses an entry while it downloads, straight into its file
+    decoder: DecoderFn,
+    /// Decompresses an entry that's already in memory
+    reader: ReaderFn,
+    /// Whether its decoders can save their state, for entries to continue where they left
+    /// off. Entries using anything else start over every time.
+    resumable: bool,
+}
+
+/// Every compression method we can install entries of
+static CODECS: &[Codec] = &[
+    Codec {
+        method: CompressionType::None,
+        decoder: stored_decoder,
+        reader: stored_reader,
+        resumable: true,
+    },
+    Codec {
+        method: CompressionType::Deflate,
+        decoder: deflate_decoder,
+        reader: deflate_reader,
+        resumable: true,
+    },
+    Codec {
+        method: CompressionType::Deflate64,
+        decoder: deflate64_decoder,
+        reader: deflate64_reader,
+        resumable: false,
+    },
+    Codec {
+        method: CompressionType::Lzma,
+        decoder: lzma_decoder,
+        reader: lzma_reader,
+        resumable: false,
+    },
+    Codec {
+        method: CompressionType::Zstd,
+        decoder: zstd_decoder,
+        reader: zstd_reader,
+        resumable: false,
+    },
+    Codec {
+        method: CompressionType::Xz,
+        decoder: xz_decoder,
+        reader: xz_reader,
+        resumable: false,
+    },
+];
+
+impl Codec {
+    pub(crate) fn for_method(method: &CompressionType) -> Result<&'static Codec, DownloaderError> {
+        CODECS
+            .iter()
+            .find(|x| &x.method == method)
+            .ok_or_else(|| DownloaderError::CompressionType(method.clone()))
+    }
+
+    pub(crate) fn decoder(&self, entry: &ZipFileEntry, file: File) -> Box<dyn DownloadDecoder> {
+        (self.decoder)(entry, BufWriter::new(file))
+    }
+
+    pub(crate) fn reader<'a>(
+        &self,
+        entry: &ZipFileEntry,
+        data: &'a [u8],
+    ) -> io::Result<Box<dyn Read + 'a>> {
+        (self.reader)(entry, data)
+    }
+
+    pub(crate) fn resumable(&self) -> bool {
+        self.resumable
+    }
+}
+
+/// A decoder for `entry`, writing to `file`
+pub(crate) fn decoder(
+    entry: &ZipFileEntry,
+    file: File,
+) -> Result<Box<dyn DownloadDecoder>, DownloaderError> {
+    Ok(Codec::for_method(entry.compression_type())?.decoder(entry, file))
+}
+
+/// Fails on the first entry we can't decompress, so a build using an unknown method is
+/// rejected before anything gets downloaded rather than installed as garbage
+pub(crate) fn check_supported<'a>(
+    entries: impl IntoIterator<Item = &'a ZipFileEntry>,
+) -> Result<(), DownloaderError> {
+    for entry in entries {
+        Codec::for_method(entry.compression_type())?;
+    }
+
+    Ok(())
+}
+
+fn stored_decoder(_: &ZipFileEntry, writer: BufWriter<File>) -> Box<dyn DownloadDecoder> {
+    Box::new(NoopDecoder::new(writer))
+}
+
+fn stored_reader<'a>(_: &ZipFileEntry, data: &'a [u8]) -> io::Result<Box<dyn Read + 'a>> {
+    Ok(Box::new(data))
+}
+
+fn deflate_decoder(_: &ZipFileEntry, writer: BufWriter<File>) -> Box<dyn DownloadDecoder> {
+    Box::new(ZLibDeflateDecoder::new(writer))
+}
+
+fn deflate_reader<'a>(_: &ZipFileEntry, data: &'a [u8]) -> io::Result<Box<dyn Read + 'a>> {
+    Ok(Box::new(BufreadDeflateDecoder::new(data)))
+}
+
+fn deflate64_decoder(_: &ZipFileEntry, writer: BufWriter<File>) -> Box<dyn DownloadDecoder> {
+    Box::new(StreamDecoder::new(Deflate64Decoder::new(writer)))
+}
+
+fn deflate64_reader<'a>(_: &ZipFileEntry, data: &'a [u8]) -> io::Result<Box<dyn Read + 'a>> {
+    Ok(Box::new(deflate64::Deflate64Decoder::with_buffer(data)))
+}
+
+fn lzma_decoder(entry: &ZipFileEntry, writer: BufWriter<File>) -> Box<dyn DownloadDecoder> {
+    let size = *entry.uncompressed_size() as u64;
+    Box::new(StreamDecoder::new(ZipLzmaHeader::new(
+        LzmaDecoder::new(writer),
+        size,
+    )))
+}
+
+fn lzma_reader<'a>(entry: &ZipFileEntry, data: &'a [u8]) -> io::Result<Box<dyn Read + 'a>> {
+    let len = zip_lzma_header_len(data)
+        .filter(|x| *x <= data.len())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated LZMA header"))?;
+
+    let header = lzma_alone_header(&data[..len], *entry.uncompressed_size() as u64);
+    let stream = xz2::stream::Stream::new_lzma_decoder(u64::MAX)?;
+    Ok(Box::new(xz2::bufread::XzDecoder::new_stream(
+        Cursor::new(header).chain(&data[len..]),
+        stream,
+    )))
+}
+
+fn zstd_decoder(_: &ZipFileEntry, writer: BufWriter<File>) -> Box<dyn DownloadDecoder> {
+    Box::new(StreamDecoder::new(ZstdDecoder::new(writer)))
+}
+
+fn zstd_reader<'a>(_: &ZipFileEntry, data: &'a [u8]) -> io::Result<Box<dyn Read + 'a>> {
+    Ok(Box::new(zstd::stream::read::Decoder::with_buffer(data)?))
+}
+
+fn xz_decoder(_: &ZipFileEntry, writer: BufWriter<File>) -> Box<dyn DownloadDecoder> {
+    Box::new(StreamDecoder::new(XzDecoder::new(writer)))
+}
+
+fn xz_reader<'a>(_: &ZipFileEntry, data: &'a [u8]) -> io::Result<Box<dyn Read + 'a>> {
+    Ok(Box::new(xz2::bufread::XzDecoder::new(data)))
+}
+
+struct ZLibDeflateDecoder {
+    decoder: DeflateDecoder<BufWriter<File>>,
+}
+
+impl ZLibDeflateDecoder {
+    fn new(writer: BufWriter<File>) -> Self {
+        Self {
+            decoder: DeflateDecoder::new(writer),
+        }
+    }
+}
+
+#[async_trait]
+impl DownloadDecoder for ZLibDeflateDecoder {
+    fn save_state(&mut self, buf: &mut BytesMut) -> bool {
+        let zstream = self
+            .decoder
+            .inner_mut()
+            .decoder_mut()
+            .inner
+            .decompress
+            .get_raw();
+        write_zlib_state(buf, zstream)
+    }
+
+    fn restore_state(&mut self, buf: &mut Bytes) -> bool {
+        let decompress = &mut self.decoder.inner_mut().decoder_mut().inner.decompress;
+        decompress.reset(false);
+        restore_zlib_state(buf, decompress.get_raw())
+    }
+
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64, DownloaderError> {
+        Ok(self.decoder.get_mut().seek(pos).await?)
+    }
+}
+
+impl AsyncWrite for ZLibDeflateDecoder {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> task::Poll<io::Result<usize>> {
+        Pin::new(&mut self.decoder).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<io::Result<()>> {
+        Pin::new(&mut self.decoder).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<io::Result<()>> {
+        Pin::new(&mut self.decoder).poll_shutdown(cx)
+    }
+}
+
+struct NoopDecoder {
+    writer: BufWriter<File>,
+    pos: u64,
+}
+
+impl NoopDecoder {
+    pub fn new(writer: BufWriter<File>) -> Self {
+        Self { writer, pos: 0 }
+    }
+}
+
+#[async_trait]
+impl DownloadDecoder for NoopDecoder {
+    fn save_state(&mut self, buf: &mut BytesMut) -> bool {
+        buf.put_u64(self.pos);
+        true
+    }
+
+    fn restore_state(&mut self, buf: &mut Bytes) -> bool {
+        if buf.remaining() < 8 {
+            return false;
+        }
+
+        self.pos = buf.get_u64();
+        true
+    }
+
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64, DownloaderError> {
+        self.pos = self.writer.seek(pos).await?;
+        Ok(self.pos)
+    }
+}
+
+impl AsyncWrite for NoopDecoder {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> task::Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.writer).poll_write(cx, buf);
+        if let task::Poll::Ready(Ok(written)) = poll {
+            self.pos += written as u64;
+        }
+        poll
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+/// The file under one of async-compression's decoders
+trait DecoderOutput {
+    fn output(&mut self) -> &mut BufWriter<File>;
+}
+
+macro_rules! decoder_output {
+    ($($decoder:ident),*) => {
+        $(
+            impl DecoderOutput for $decoder<BufWriter<File>> {
+                fn output(&mut self) -> &mut BufWriter<File> {
+                    self.get_mut()
+                }
+            }
+        )*
+    };
+}
+
+decoder_output!(Deflate64Decoder, LzmaDecoder, XzDecoder, ZstdDecoder);
+
+/// Decoders whose state we can't get at. They still stream, but an interrupted entry has to
+/// be downloaded again from the start.
+struct StreamDecoder<D> {
+    decoder: D,
+}
+
+impl<D> StreamDecoder<D> {
+    fn new(decoder: D) -> Self {
+        Self { decoder }
+    }
+}
+
+#[async_trait]
+impl<D: DecoderOutput + AsyncWrite + Unpin + Send> DownloadDecoder for StreamDecoder<D> {
+    fn save_state(&mut self, _: &mut BytesMut) -> bool {
+        false
+    }
+
+    fn restore_state(&mut self, _: &mut Bytes) -> bool {
+        false
+    }
+
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64, DownloaderError> {
+        Ok(self.decoder.output().seek(pos).await?)
+    }
+}
+
+impl<D: AsyncWrite + Unpin> AsyncWrite for StreamDecoder<D> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> task::Poll<io::Result<usize>> {
+        Pin::new(&mut self.decoder).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<io::Result<()>> {
+        Pin::new(&mut self.decoder).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<io::Result<()>> {
+        Pin::new(&mut self.decoder).poll_shutdown(cx)
+    }
+}
+
+/// Length of zip's LZMA header, a version and the size of the properties that follow it
+fn zip_lzma_header_len(data: &[u8]) -> Option<usize> {
+    let size = data.get(2..4)?;
+    Some(4 + u16::from_le_bytes([size[0], size[1]]) as usize)
+}
+
+/// The `.lzma` header liblzma wants for data that came with zip's `header`
+fn lzma_alone_header(header: &[u8], uncompressed_size: u64) -> Vec<u8> {
+    let mut alone = header[4..].to_vec();
+    alone.extend_from_slice(&uncompressed_size.to_le_bytes());
+    alone
+}
+
+/// Swaps zip's LZMA header for the `.lzma` one on the way into `inner`
+struct ZipLzmaHeader<W> {
+    inner: W,
+    uncompressed_size: u64,
+    /// Zip's header, for as long as it isn't complete
+    header: Option<Vec<u8>>,
+    /// The `.lzma` header, or what `inner` didn't take of it yet
+    pending: Vec<u8>,
+}
+
+impl<W: AsyncWrite + Unpin> ZipLzmaHeader<W> {
+    fn new(inner: W, uncompressed_size: u64) -> Self {
+        Self {
+            inner,
+            uncompressed_size,
+            header: Some(Vec::new()),
+            pending: Vec::new(),
+        }
+    }
+
+    fn poll_pending(&mut self, cx: &mut task::Context<'_>) -> task::Poll<io::Result<()>> {
+        while !self.pending.is_empty() {
+            let written = task::ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending))?;
+            if written == 0 {
+                return task::Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending.drain(..written);
+        }
+
+        task::Poll::Ready(Ok(()))
+    }
+}
+
+impl DecoderOutput for ZipLzmaHeader<LzmaDecoder<BufWriter<File>>> {
+    fn output(&mut self) -> &mut BufWriter<File> {
+        self.inner.output()
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for ZipLzmaHeader<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> task::Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if let Some(header) = &mut this.header {
+            // The properties' size comes first, so we only know the full length after it
+            let len = zip_lzma_header_len(header).unwrap_or(4);
+            let taken = (len - header.len()).min(buf.len());
+            header.extend_from_slice(&buf[..taken]);
+
+            if zip_lzma_header_len(header) == Some(header.len()) {
+                this.pending = lzma_alone_header(header, this.uncompressed_size);
+                this.header = None;
+            }
+            return task::Poll::Ready(Ok(taken));
+        }
+
+        task::ready!(this.poll_pending(cx))?;
+        Pin::new(&mut this.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<io::Result<()>> {
+        task::ready!(self.poll_pending(cx))?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<io::Result<()>> {
+        task::ready!(self.poll_pending(cx))?;
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::util::testing::{fixtures, TempDir};
+
+    /// What `testdata/codecs/lzma.bin` holds
+    fn bottles() -> Vec<u8> {
+        (0..2000)
+            .flat_map(|x| format!("{} bottles of beer\n", x).into_bytes())
+            .collect()
+    }
+
+    /// Decompresses `data` both while "downloading" it, written in `writes`, and in memory
+    async fn decode(method: CompressionType, writes: &[&[u8]], expected: &[u8]) {
+        let data = writes.concat();
+        let entry = ZipFileEntry::compressed(method, &data, expected.len() as i64);
+
+        let dir = TempDir::new(&format!("codec-{:?}", entry.compression_type()));
+        let path = dir.path().join("entry");
+        let file = File::create(&path).await.unwrap();
+        let mut decoder = decoder(&entry, file).unwrap();
+        for write in writes {
+            decoder.write_all(write).await.unwrap();
+        }
+        decoder.shutdown().await.unwrap();
+        assert!(std::fs::read(&path).unwrap() == expected);
+
+        let codec = Codec::for_method(entry.compression_type()).unwrap();
+        let mut read = Vec::new();
+        codec
+            .reader(&entry, &data)
+            .unwrap()
+            .read_to_end(&mut read)
+            .unwrap();
+        assert!(read == expected);
+    }
+
+    #[tokio::test]
+    async fn lzma() {
+        // Zip's header (LZMA SDK 9.20, five bytes of properties) and raw LZMA with no end marker
+        let (_, data) = fixtures("codecs")
+            .into_iter()
+            .find(|(path, _)| path.ends_with("lzma.bin"))
+            .unwrap();
+        decode(CompressionType::Lzma, &[&data], &bottles()).await;
+
+        // The header has to be put back together when it comes in pieces
+        let (header, rest) = data.split_at(9);
+        let writes = [
+            &header[..1],
+            &header[1..3],
+            &header[3..6],
+            &header[6..],
+            rest,
+        ];
+        decode(CompressionType::Lzma, &writes, &bottles()).await;
+    }
+
+    #[tokio::test]
+    async fn zstd() {
+        let data = zstd::encode_all(&bottles()[..], 3).unwrap();
+        let writes: Vec<&[u8]> = data.chunks(100).collect();
+        decode(CompressionType::Zstd, &writes, &bottles()).await;
+    }
+
+    #[tokio::test]
+    async fn xz() {
+        let mut data = Vec::new();
+        xz2::read::XzEncoder::new(&bottles()[..], 6)
+            .read_to_end(&mut data)
+            .unwrap();
+        let writes: Vec<&[u8]> = data.chunks(100).collect();
+        decode(CompressionType::Xz, &writes, &bottles()).await;
+    }
+
+    #[tokio::test]
+    async fn deflate64() {
+        // One fixed Huffman block: "abc", then 40000 bytes from 3 back and 258 from 39999
+        // back. Deflate has neither a length code that long nor a distance that far.
+        let data = [
+            0x4b, 0x4c, 0x4a, 0x1e, 0xed, 0xe1, 0x44, 0xa3, 0xff, 0x00, 0xcf, 0x87, 0x03, 0x00,
+        ];
+        let expected: Vec<u8> = b"abc".iter().copied().cycle().take(40_261).collect();
+        decode(
+            CompressionType::Deflate64,
+            &[&data[..7], &data[7..]],
+            &expected,
+        )
+        .await;
+    }
+
+    #[test]
+    fn unknown_method() {
+        let data = [0; 16];
+        let entries = [
+            ZipFileEntry::compressed(CompressionType::Deflate, &data, 16),
+            ZipFileEntry::compressed(CompressionType::Unknown(98), &data, 16),
+        ];
+        assert!(matches!(
+            check_supported(&entries),
+            Err(DownloaderError::CompressionType(CompressionType::Unknown(
+                98
+            )))
+        ));
+        assert!(check_supported(&entries[..1]).is_ok());
+    }
+}
@@ -0,0 +1,183 @@
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+/// Downloads running at once unless configured otherwise
+pub const DEFAULT_CONCURRENCY: usize = 16;
+/// A sensible upper bound for [`Concurrency::adaptive`], past this the CDN tends to push back
+pub const MAX_ADAPTIVE_CONCURRENCY: usize = 64;
+/// Where adaptive concurrency starts, before it knows anything about the connection
+const ADAPTIVE_START: usize = 4;
+/// Throughput has to change by this much between two windows to count as better or worse
+const ADAPTIVE_THRESHOLD: f64 = 0.05;
+
+/// How many files of a game are downloaded at once
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Concurrency {
+    Fixed(usize),
+    /// Settles somewhere between `min` and `max` on whatever gets the most throughput,
+    /// backing off when downloads start failing
+    Adaptive {
+        min: usize,
+        max: usize,
+    },
+}
+
+impl Default for Concurrency {
+    fn default() -> Self {
+        Concurrency::Fixed(DEFAULT_CONCURRENCY)
+    }
+}
+
+impl Concurrency {
+    /// Adaptive, from a single connection up to `max`
+    pub fn adaptive(max: usize) -> Self {
+        Concurrency::Adaptive { min: 1, max }
+    }
+
+    /// The way settings take it, a count of files and whether to adapt up to it instead.
+    /// A count of 0 or none is the default.
+    pub fn from_count(count: Option<usize>, adaptive: bool) -> Self {
+        let count = count.filter(|x| *x > 0);
+        if adaptive {
+            return Concurrency::adaptive(count.unwrap_or(MAX_ADAPTIVE_CONCURRENCY));
+        }
+
+        count.map(Concurrency::Fixed).unwrap_or_default()
+    }
+
+    fn bounds(&self) -> (usize, usize) {
+        match *self {
+            Concurrency::Fixed(count) => (count.max(1), count.max(1)),
+            Concurrency::Adaptive { min, max } => (min.max(1), max.max(min.max(1))),
+        }
+    }
+
+    /// The most downloads that may ever run at once
+    pub(crate) fn max(&self) -> usize {
+        self.bounds().1
+    }
+
+    pub(crate) fn initial(&self) -> usize {
+        let (min, max) = self.bounds();
+        match self {
+            Concurrency::Fixed(_) => max,
+            Concurrency::Adaptive { .. } => ADAPTIVE_START.clamp(min, max),
+        }
+    }
+
+    pub(crate) fn tuner(&self) -> Option<AdaptiveTuner> {
+        let (min, max) = self.bounds();
+        match self {
+            Concurrency::Fixed(_) => None,
+            Concurrency::Adaptive { .. } => Some(AdaptiveTuner {
+                min,
+                max,
+                last_rate: None,
+                growing: true,
+            }),
+        }
+    }
+}
+
+struct GateState {
+    limit: usize,
+    active: usize,
+}
+
+/// Lets a number of downloads run at once that can change while they do. Lowering it doesn't
+/// interrupt anything, new downloads just wait until enough of the running ones are done.
+pub(crate) struct ConcurrencyGate {
+    state: Mutex<GateState>,
+    released: Notify,
+}
+
+/// Held for as long as a download runs
+pub(crate) struct GatePermit {
+    gate: Arc<ConcurrencyGate>,
+}
+
+impl Drop for GatePermit {
+    fn drop(&mut self) {
+        self.gate.state.lock().unwrap().active -= 1;
+        self.gate.released.notify_waiters();
+    }
+}
+
+impl ConcurrencyGate {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            state: Mutex::new(GateState {
+                limit: limit.max(1),
+                active: 0,
+            }),
+            released: Notify::new(),
+        }
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    pub(crate) fn set_limit(&self, limit: usize) {
+        self.state.lock().unwrap().limit = limit.max(1);
+        self.released.notify_waiters();
+    }
+
+    pub(crate) async fn acquire(self: &Arc<Self>) -> GatePermit {
+        loop {
+            // Created before checking, so a release in between still wakes us
+            let released = self.released.notified();
+
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.active < state.limit {
+                    state.active += 1;
+                    return GatePermit { gate: self.clone() };
+                }
+            }
+
+            released.await;
+        }
+    }
+}
+
+/// Hill climbs toward the concurrency with the best throughput. Keeps going the same way
+/// while throughput improves, turns around when it drops, and backs off hard on failures.
+pub(crate) struct AdaptiveTuner {
+    min: usize,
+    max: usize,
+    /// Bytes per second over the previous window
+    last_rate: Option<f64>,
+    growing: bool,
+}
+
+impl AdaptiveTuner {
+    /// The concurrency for the next window, after the last one ran at `current` and got
+    /// `rate` bytes per second with `failures` failed attempts
+    pub(crate) fn next(&mut self, current: usize, rate: f64, failures: usize) -> usize {
+        let last_rate = self.last_rate.replace(rate);
+
+        if failures > 0 {
+            // Failures mean the connection or the CDN is overwhelmed, throughput can't tell
+            self.growing = false;
+            return (current * 3 / 4).max(self.min);
+        }
+
+        let better = last_rate.map_or(true, |x| rate > x * (1.0 + ADAPTIVE_THRESHOLD));
+        let worse = last_rate.is_some_and(|x| rate < x * (1.0 - ADAPTIVE_THRESHOLD));
+        if worse {
+            self.growing = !self.growing;
+        } else if !better {
+            return current;
+        }
+
+        let step = (current / 4).max(1);
+        if self.growing {
+            (current + step).min(self.max)
+        } else {
+            current.saturating_sub(step).max(self.min)
+        }
+    }
+}
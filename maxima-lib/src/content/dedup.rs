@@ -0,0 +1,190 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use futures::StreamExt;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    content::{manager::ContentManagerError, zip::ZipFileEntry},
+    util::{hash::hash_file_crc32_sha256, native::maxima_dir},
+};
+
+const STORE_FILE: &str = "content_store.json";
+/// Smaller files aren't worth an index entry, downloading them again is cheap
+const MIN_SIZE: i64 = 64 * 1024;
+/// How many files get hashed at once
+const HASH_CONCURRENCY: usize = 4;
+
+/// Whether installs share identical files with other installs instead of downloading them
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DedupMode {
+    #[default]
+    Off,
+    /// Copy-on-write clones only, on filesystems that support them (Btrfs, XFS, APFS, ReFS).
+    /// Every install keeps its own copy as soon as one changes.
+    Reflink,
+    /// Clones where supported, hardlinks everywhere else. Hardlinked files are the same file,
+    /// so a game changing one of its files in place changes it for every install sharing it.
+    Hardlink,
+}
+
+/// A file in some install, and the SHA-256 it had when it was added
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredFile {
+    path: PathBuf,
+    sha256: String,
+}
+
+/// Index of installed files by content, so installs can link files other installs already
+/// have. Files are looked up by the CRC32 and size the central directory gives for an entry,
+/// and only linked if they still have the SHA-256 they were added with.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct ContentStore {
+    files: Mutex<HashMap<String, Vec<StoredFile>>>,
+}
+
+fn key(entry: &ZipFileEntry) -> String {
+    format!("{:08x}-{}", entry.crc32(), entry.uncompressed_size())
+}
+
+fn is_storable(entry: &ZipFileEntry) -> bool {
+    !entry.name().ends_with('/') && *entry.uncompressed_size() >= MIN_SIZE
+}
+
+async fn hash_file(path: PathBuf) -> std::io::Result<(u32, String)> {
+    tokio::task::spawn_blocking(move || hash_file_crc32_sha256(path))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+fn link_file(from: &Path, to: &Path, mode: DedupMode) -> std::io::Result<()> {
+    match reflink_copy::reflink(from, to) {
+        Err(_) if mode == DedupMode::Hardlink => std::fs::hard_link(from, to),
+        result => result,
+    }
+}
+
+impl ContentStore {
+    pub async fn load() -> Result<ContentStore, ContentManagerError> {
+        let file = maxima_dir()?.join(STORE_FILE);
+        if !file.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = fs::read_to_string(file).await?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub(crate) async fn save(&self) -> Result<(), ContentManagerError> {
+        let data = serde_json::to_string(&self)?;
+        fs::write(maxima_dir()?.join(STORE_FILE), data).await?;
+        Ok(())
+    }
+
+    /// Adds the files of `entries` under `root`. Files that don't match their entry's CRC32
+    /// are left out, they aren't what the build shipped.
+    pub(crate) async fn add(&self, root: &Path, entries: &[ZipFileEntry]) {
+        futures::stream::iter(entries.iter().filter(|x| is_storable(x)))
+            .for_each_concurrent(HASH_CONCURRENCY, |entry| async move {
+                let path = root.join(entry.name());
+                match hash_file(path.clone()).await {
+                    Ok((crc32, sha256)) if crc32 == *entry.crc32() => {
+                        self.insert(key(entry), StoredFile { path, sha256 })
+                    }
+                    Ok(_) => debug!("{} doesn't match its entry, not storing it", entry.name()),
+                    Err(err) => warn!("Failed to hash {}: {}", entry.name(), err),
+                }
+            })
+            .await;
+    }
+
+    fn insert(&self, key: String, file: StoredFile) {
+        let mut files = self.files.lock().unwrap();
+        let stored = files.entry(key).or_default();
+        stored.retain(|x| x.path != file.path);
+        stored.push(file);
+    }
+
+    /// Drops every stored file under `root`, for when an install goes away
+    pub(crate) async fn forget(root: &Path) -> Result<(), ContentManagerError> {
+        if !maxima_dir()?.join(STORE_FILE).exists() {
+            return Ok(());
+        }
+
+        let store = Self::load().await?;
+        {
+            let mut files = store.files.lock().unwrap();
+            for stored in files.values_mut() {
+                stored.retain(|x| !x.path.starts_with(root));
+            }
+            files.retain(|_, x| !x.is_empty());
+        }
+
+        store.save().await
+    }
+
+    /// Links a stored copy of `entry` to `target`. Returns false when there is none, or
+    /// `target` already exists. Copies that changed since they were added are dropped.
+    pub(crate) async fn link(
+        &self,
+        entry: &ZipFileEntry,
+        target: &Path,
+        mode: DedupMode,
+    ) -> Result<bool, ContentManagerError> {
+        if mode == DedupMode::Off || !is_storable(entry) || target.exists() {
+            return Ok(false);
+        }
+
+        let key = key(entry);
+        let candidates = match self.files.lock().unwrap().get(&key) {
+            Some(candidates) => candidates.clone(),
+            None => return Ok(false),
+        };
+
+        for candidate in candidates {
+            let unchanged = match hash_file(candidate.path.clone()).await {
+                Ok((crc32, sha256)) => crc32 == *entry.crc32() && sha256 == candidate.sha256,
+                Err(_) => false,
+            };
+
+            if !unchanged {
+                debug!("{:?} changed or is gone, dropping it", candidate.path);
+                if let Some(stored) = self.files.lock().unwrap().get_mut(&key) {
+                    stored.retain(|x| x.path != candidate.path);
+                }
+                continue;
+            }
+
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+
+            let (from, to) = (candidate.path.clone(), target.to_path_buf());
+            let linked = tokio::task::spawn_blocking(move || link_file(&from, &to, mode))
+                .await
+                .map_err(std::io::Error::other)?;
+            match linked {
+                Ok(()) => {
+                    debug!("Linked {} from {:?}", entry.name(), candidate.path);
+                    self.insert(
+                        key,
+                        StoredFile {
+                            path: target.to_path_buf(),
+                            sha256: candidate.sha256,
+                        },
+                    );
+                    return Ok(true);
+                }
+                // Most likely another filesystem, or one without clones. Other copies may work.
+                Err(err) => debug!("Failed to link {:?}: {}", candidate.path, err),
+            }
+        }
+
+        Ok(false)
+    }
+}
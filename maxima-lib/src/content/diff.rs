@@ -0,0 +1,118 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use log::{debug, warn};
+use tokio::fs;
+
+use crate::{
+    content::{installed::InstalledGame, manager::DownloaderError, zip::ZipFileEntry},
+    util::hash::hash_file_crc32,
+};
+
+/// Whether the installed copy of `entry` matches the central directory. `recorded_crc`
+/// is what the previous install wrote there, letting us skip hashing unchanged files.
+pub(crate) async fn entry_matches(
+    root: &Path,
+    entry: &ZipFileEntry,
+    recorded_crc: Option<u32>,
+) -> Result<bool, DownloaderError> {
+    let path = root.join(entry.name());
+    if entry.name().ends_with('/') {
+        return Ok(path.is_dir());
+    }
+
+    let metadata = match fs::metadata(&path).await {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(false),
+    };
+
+    if metadata.len() as i64 != *entry.uncompressed_size() {
+        return Ok(false);
+    }
+
+    if recorded_crc == Some(*entry.crc32()) {
+        return Ok(true);
+    }
+
+    let hash = tokio::task::spawn_blocking(move || hash_file_crc32(path))
+        .await
+        .map_err(std::io::Error::other)??;
+    Ok(hash == *entry.crc32())
+}
+
+/// Entries that have to be (re)downloaded to bring `root` up to date
+pub(crate) async fn changed_entries(
+    root: &Path,
+    entries: &[ZipFileEntry],
+    previous: Option<&InstalledGame>,
+) -> Result<Vec<ZipFileEntry>, DownloaderError> {
+    let recorded: HashMap<&str, u32> = previous
+        .map(|x| {
+            x.entries()
+                .iter()
+                .map(|e| (e.name().as_str(), *e.crc32()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut changed = Vec::new();
+    for entry in entries {
+        let recorded_crc = recorded.get(entry.name().as_str()).copied();
+        if !entry_matches(root, entry, recorded_crc).await? {
+            debug!("{} changed", entry.name());
+            changed.push(entry.clone());
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Files the previous install had that aren't part of `entries` anymore
+pub(crate) fn removed_files(previous: &InstalledGame, entries: &[ZipFileEntry]) -> Vec<String> {
+    let current: HashSet<&str> = entries.iter().map(|x| x.name().as_str()).collect();
+    previous
+        .entries()
+        .iter()
+        .map(|x| x.name())
+        .filter(|x| !x.ends_with('/') && !current.contains(x.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Deletes `files` from `root`, and any directories left empty behind them
+pub(crate) async fn remove_files(root: &Path, files: &[String]) {
+    for file in files {
+        let path = root.join(file);
+        if let Err(err) = fs::remove_file(&path).await {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove {}: {}", file, err);
+            }
+            continue;
+        }
+
+        let mut parent = path.parent();
+        while let Some(dir) = parent {
+            if dir == root || fs::remove_dir(dir).await.is_err() {
+                break;
+            }
+
+            parent = dir.parent();
+        }
+    }
+}
+
+/// Drops the installed copy of `entry` so the downloader starts it from scratch. Copies
+/// that already match the target build stay, e.g. ones an earlier run of the same update
+/// or repair got to before it was paused.
+pub(crate) async fn reset_entry(root: &Path, entry: &ZipFileEntry) -> Result<(), DownloaderError> {
+    if entry.name().ends_with('/') || entry_matches(root, entry, None).await? {
+        return Ok(());
+    }
+
+    match fs::remove_file(root.join(entry.name())).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
@@ -1,249 +1,302 @@
 use std::{
     cmp,
-    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    io::{self, Read, SeekFrom},
     path::{Path, PathBuf},
     pin::Pin,
-    prelude,
-    sync::{Arc, Mutex},
-    task,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
 use crate::{
     content::{
+        batch::{self, DownloadUnit},
+        cdn::{SignedUrl, UrlRefresher},
+        codec::{self, Codec, DownloadDecoder},
         manager::DownloaderError,
-        zip::{CompressionType, ZipFile, ZipFileEntry},
-        zlib::{restore_zlib_state, write_zlib_state},
+        retry::RetryPolicy,
+        throttle::RateLimiter,
+        zip::{ZipFile, ZipFileEntry},
     },
     util::{
         hash::hash_file_crc32,
-        native::{maxima_dir, NativeError, SafeParent, SafeStr},
+        native::{maxima_dir, reserve_space, NativeError, SafeParent, SafeStr},
     },
 };
-use async_compression::tokio::write::DeflateDecoder;
-use async_trait::async_trait;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use derive_getters::Getters;
-use flate2::bufread::DeflateDecoder as BufreadDeflateDecoder;
 use futures::{Stream, StreamExt, TryStreamExt};
 use log::{debug, error, info, warn};
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
 use strum_macros::Display;
 use thiserror::Error;
 use tokio::{
     fs::{create_dir, create_dir_all, File, OpenOptions},
-    io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, BufReader, BufWriter},
-    runtime::Handle,
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader},
 };
-use tokio_util::compat::FuturesAsyncReadCompatExt;
+use tokio_util::{compat::FuturesAsyncReadCompatExt, io::ReaderStream};
 
-fn zstate_path(id: &str, path: &str) -> Result<PathBuf, DownloaderError> {
+async fn zstate_path(id: &str, path: &str) -> Result<PathBuf, DownloaderError> {
     let mut path = maxima_dir()?.join("temp/downloader").join(id).join(path);
     path.set_extension("eazstate");
-    std::fs::create_dir_all(path.safe_parent()?)?;
+    create_dir_all(path.safe_parent()?).await?;
     Ok(path)
 }
 
-#[async_trait]
-trait DownloadDecoder: Send {
-    fn save_state(&mut self, buf: &mut BytesMut);
-    fn restore_state(&mut self, buf: &mut Bytes);
-
-    fn seek(&mut self, pos: SeekFrom) -> Result<(), DownloaderError>;
-
-    fn write_in_pos(&self) -> u64;
-    fn write_out_pos(&self) -> u64;
-
-    fn get_mut<'b>(&mut self) -> Arc<Mutex<dyn AsyncWriteWrapper>>;
+/// Exists while a preallocated entry's file is written in place, so a file of the right size
+/// isn't mistaken for a finished one. Files that grow as they're written don't need one.
+async fn partial_marker_path(id: &str, path: &str) -> Result<PathBuf, DownloaderError> {
+    let path = maxima_dir()?
+        .join("temp/downloader")
+        .join(id)
+        .join(format!("{}.eapartial", path));
+    create_dir_all(path.safe_parent()?).await?;
+    Ok(path)
 }
 
-struct ZLibDeflateDecoder {
-    decoder: Arc<Mutex<DeflateDecoder<BufWriter<File>>>>,
+/// Makes `file` `size` bytes long with its blocks set aside up front. Filesystems that can't
+/// reserve them only get the length, leaving a sparse file.
+async fn preallocate(file: &File, size: u64) -> Result<(), DownloaderError> {
+    let file = file.try_clone().await?.into_std().await;
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = reserve_space(&file, size) {
+            debug!("Couldn't reserve {} bytes, only extending: {}", size, err);
+        }
+        file.set_len(size)
+    })
+    .await
+    .map_err(io::Error::other)??;
+    Ok(())
 }
 
-impl ZLibDeflateDecoder {
-    fn new(writer: BufWriter<File>) -> Self {
-        Self {
-            decoder: Arc::new(Mutex::new(DeflateDecoder::new(writer))),
-        }
+/// Drops every saved decoder state of `id`, so its next download starts from scratch
+pub(crate) async fn clear_state(id: &str) -> Result<(), DownloaderError> {
+    let dir = maxima_dir()?.join("temp/downloader").join(id);
+    match tokio::fs::remove_dir_all(dir).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
     }
 }
 
-#[async_trait]
-impl DownloadDecoder for ZLibDeflateDecoder {
-    fn save_state(&mut self, buf: &mut BytesMut) {
-        let mut decoder = self.decoder.lock().unwrap();
-        let zstream = decoder.inner_mut().decoder_mut().inner.decompress.get_raw();
-        write_zlib_state(buf, zstream);
+/// Input bytes between two checkpoints of an entry's decoder state
+const CHECKPOINT_INTERVAL: u64 = 4 * 1024 * 1024;
+
+/// Flushes `decoder` and saves its state for the entry to continue from, with `read` bytes of
+/// its data decoded. Written next to the last checkpoint and moved over it, so there's never
+/// half of one.
+async fn save_checkpoint(
+    id: &str,
+    entry: &ZipFileEntry,
+    decoder: &mut Box<dyn DownloadDecoder>,
+    read: u64,
+) -> Result<(), DownloaderError> {
+    decoder.flush().await?;
+
+    let mut state = BytesMut::new();
+    if !decoder.save_state(&mut state) {
+        return Ok(());
     }
 
-    fn restore_state(&mut self, buf: &mut Bytes) {
-        let mut decoder = self.decoder.lock().unwrap();
-        let decompress = &mut decoder.inner_mut().decoder_mut().inner.decompress;
-        decompress.reset(false);
-        let zstream = decompress.get_raw();
-        restore_zlib_state(buf, zstream);
+    // The entry it belongs to, so another build's file of the same name doesn't continue it
+    let mut checkpoint = BytesMut::new();
+    checkpoint.put_u32(*entry.crc32());
+    checkpoint.put_u64(*entry.compressed_size() as u64);
+    checkpoint.put_u64(read);
+    checkpoint.put_u64(decoder.seek(SeekFrom::Current(0)).await?);
+    checkpoint.put(state);
+
+    let path = zstate_path(id, entry.name()).await?;
+    let temp = path.with_extension("eazstate.part");
+    tokio::fs::write(&temp, &checkpoint).await?;
+    tokio::fs::rename(&temp, &path).await?;
+    Ok(())
+}
+
+/// Drops `entry`'s checkpoint, once it's done or starts over
+async fn remove_checkpoint(id: &str, entry: &ZipFileEntry) -> Result<(), DownloaderError> {
+    match tokio::fs::remove_file(zstate_path(id, entry.name()).await?).await {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
     }
+}
 
-    fn seek(&mut self, pos: SeekFrom) -> Result<(), DownloaderError> {
-        let mut decoder = self.decoder.lock().unwrap();
-        let file = decoder.get_mut();
+/// Where an entry's download got to, from [`save_checkpoint`]
+struct Checkpoint {
+    /// Bytes of the entry's data that were decoded
+    read: u64,
+    /// Bytes the decoder wrote to the file
+    written: u64,
+    /// The decoder's own state
+    state: Bytes,
+}
 
-        let handle = Handle::current();
-        let _ = handle.enter();
-        futures::executor::block_on(file.seek(pos))?;
+/// The entry a checkpoint belongs to, and where it got to
+const CHECKPOINT_HEADER_LEN: usize = 28;
 
-        Ok(())
+/// `entry`'s last checkpoint, if it has one and its compression can continue from it
+async fn load_checkpoint(
+    id: &str,
+    entry: &ZipFileEntry,
+) -> Result<Option<Checkpoint>, DownloaderError> {
+    if !Codec::for_method(entry.compression_type())?.resumable() {
+        return Ok(None);
     }
 
-    fn write_in_pos(&self) -> u64 {
-        let mut decoder = self.decoder.lock().unwrap();
-        let decompress = &mut decoder.inner_mut().decoder_mut().inner.decompress;
-        let zstream = decompress.get_raw();
-        zstream.total_in as u64
-    }
+    let mut buf = match tokio::fs::read(zstate_path(id, entry.name()).await?).await {
+        Ok(data) => Bytes::from(data),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
 
-    fn write_out_pos(&self) -> u64 {
-        let mut decoder = self.decoder.lock().unwrap();
-        let decompress = &mut decoder.inner_mut().decoder_mut().inner.decompress;
-        let zstream = decompress.get_raw();
-        zstream.total_out as u64
+    if buf.len() < CHECKPOINT_HEADER_LEN
+        || buf.get_u32() != *entry.crc32()
+        || buf.get_u64() != *entry.compressed_size() as u64
+    {
+        debug!(
+            "Ignoring a checkpoint of {} from another build",
+            entry.name()
+        );
+        return Ok(None);
     }
 
-    fn get_mut(&mut self) -> Arc<Mutex<dyn AsyncWriteWrapper>> {
-        self.decoder.clone()
-    }
+    Ok(Some(Checkpoint {
+        read: buf.get_u64(),
+        written: buf.get_u64(),
+        state: buf,
+    }))
 }
 
-struct NoopDecoder {
-    writer: Arc<Mutex<BufWriter<File>>>,
-    pos: u64,
+#[derive(Error, Debug)]
+pub enum DownloadError {
+    #[error("download failed ({0} bytes")]
+    DownloadFailed(usize),
+    #[error("failed to download chunk `{entry}`: {error}")]
+    ChunkDownload {
+        entry: String,
+        error: reqwest::Error,
+    },
+    #[error("failed to copy chunk `{entry}`: {error}")]
+    ChunkCopy {
+        entry: String,
+        error: std::io::Error,
+    },
 }
 
-impl NoopDecoder {
-    pub fn new(writer: BufWriter<File>) -> Self {
-        Self {
-            writer: Arc::new(Mutex::new(writer)),
-            pos: 0,
-        }
-    }
+/// Where a build's files come from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BuildSource {
+    /// The build zip on the CDN
+    Remote(String),
+    /// A build zip on disk, for installing a build kept around after it left the CDN
+    Archive(PathBuf),
+    /// An already extracted build, its files are copied as they are
+    Directory(PathBuf),
 }
 
-#[async_trait]
-impl DownloadDecoder for NoopDecoder {
-    fn save_state(&mut self, buf: &mut BytesMut) {
-        self.pos = self.writer.lock().unwrap().buffer().len() as u64;
-        buf.put_u64(self.pos);
+impl BuildSource {
+    /// A zip or an extracted build, depending on what's at `path`
+    pub fn local(path: PathBuf) -> Self {
+        if path.is_dir() {
+            BuildSource::Directory(path)
+        } else {
+            BuildSource::Archive(path)
+        }
     }
 
-    fn restore_state(&mut self, buf: &mut Bytes) {
-        self.pos = buf.get_u64();
+    /// `build` is only used by extracted builds, see [`ZipFile::from_directory`]
+    async fn read_manifest(&self, build: Option<&ZipFile>) -> Result<ZipFile, DownloaderError> {
+        Ok(match self {
+            BuildSource::Remote(url) => ZipFile::fetch(url).await?,
+            BuildSource::Archive(path) => ZipFile::open(path).await?,
+            BuildSource::Directory(path) => ZipFile::from_directory(path, build).await?,
+        })
     }
+}
 
-    fn seek(&mut self, pos: SeekFrom) -> Result<(), DownloaderError> {
-        let mut file = self.writer.lock().unwrap();
-
-        let handle = Handle::current();
-        let _ = handle.enter();
-        futures::executor::block_on(file.seek(pos))?;
+/// Where one attempt at an entry reads its data from
+enum EntryData {
+    /// The URL in use when the attempt started
+    Remote(String),
+    /// The file holding the entry's data, and where in it the data starts
+    Local(PathBuf, u64),
+}
 
-        Ok(())
-    }
+/// How many times an entry gets new URLs signed before giving up, the CDN rejecting fresh
+/// ones too means something else is wrong
+const MAX_URL_REFRESHES: usize = 3;
 
-    fn write_in_pos(&self) -> u64 {
-        self.pos
-    }
+type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, io::Error>> + Send>>;
 
-    fn write_out_pos(&self) -> u64 {
-        self.pos
-    }
+/// Chunk size when reading a local source, the default is tuned for sockets
+const LOCAL_READ_CHUNK: usize = 256 * 1024;
 
-    fn get_mut<'b>(&mut self) -> Arc<Mutex<dyn AsyncWriteWrapper>> {
-        self.writer.clone()
-    }
+/// Streams `len` bytes of `path`, starting at `start`
+async fn local_range(path: &Path, start: u64, len: u64) -> Result<ByteStream, DownloaderError> {
+    let mut file = File::open(path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+    Ok(Box::pin(ReaderStream::with_capacity(
+        file.take(len),
+        LOCAL_READ_CHUNK,
+    )))
 }
 
-trait AsyncWriteWrapper: AsyncWrite + Unpin + Send {}
-impl<T: AsyncWrite + Unpin + Send> AsyncWriteWrapper for T {}
-
-struct AsyncWriterWrapper<'a> {
-    id: String,
-    path: String,
-    zlib_state_file: std::fs::File,
-    decoder: &'a mut Box<dyn DownloadDecoder>,
-    inner: Arc<Mutex<dyn AsyncWriteWrapper>>,
+/// Holds back every chunk of `stream` until `limiter` lets it through
+fn throttle(stream: ByteStream, limiter: Option<Arc<RateLimiter>>) -> ByteStream {
+    Box::pin(stream.then(move |chunk| {
+        let limiter = limiter.clone();
+        async move {
+            if let (Some(limiter), Ok(chunk)) = (&limiter, &chunk) {
+                limiter.acquire(chunk.len()).await;
+            }
+            chunk
+        }
+    }))
 }
 
-impl<'a> AsyncWriterWrapper<'a> {
-    async fn new(
-        id: String,
-        path: String,
-        decoder: &'a mut Box<dyn DownloadDecoder>,
-    ) -> Result<Self, DownloaderError> {
-        let inner = decoder.get_mut();
-        Ok(AsyncWriterWrapper {
-            id: id.to_owned(),
-            path: path.to_owned(),
-            zlib_state_file: std::fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(zstate_path(&id, &path)?)?,
-            decoder,
-            inner,
+/// Decodes everything `reader` yields into `entry`'s file, returning how many bytes were read.
+/// `checkpoints` is the download's ID and where in the entry's data `reader` starts, for
+/// saving the decoder's state every [`CHECKPOINT_INTERVAL`].
+async fn decode_entry(
+    entry: &ZipFileEntry,
+    decoder: &mut Box<dyn DownloadDecoder>,
+    mut reader: impl AsyncBufRead + Unpin,
+    checkpoints: Option<(&str, u64)>,
+) -> Result<u64, DownloaderError> {
+    let copy_error = |error: io::Error| {
+        DownloaderError::Download(DownloadError::ChunkCopy {
+            entry: entry.name().clone(),
+            error,
         })
-    }
-}
-
-impl<'a> AsyncWrite for AsyncWriterWrapper<'a> {
-    fn poll_write(
-        mut self: Pin<&mut Self>,
-        cx: &mut task::Context<'_>,
-        buf: &[u8],
-    ) -> task::Poll<prelude::v1::Result<usize, io::Error>> {
-        let poll_result = {
-            let mut binding = self.inner.lock().unwrap();
-            let inner = Pin::new(&mut *binding);
-            inner.poll_write(cx, buf)
-        };
-
-        // State serialization is disabled for now.
-        // let mut bytes = BytesMut::new();
-        // self.decoder.save_state(&mut bytes);
-
-        // self.zlib_state_file.seek(SeekFrom::Start(0))?;
-        // self.zlib_state_file.write(&bytes)?;
+    };
+
+    let mut read = 0;
+    let mut checkpointed = 0;
+    loop {
+        let chunk = reader.fill_buf().await.map_err(copy_error)?;
+        if chunk.is_empty() {
+            break;
+        }
 
-        poll_result
-    }
+        let len = chunk.len();
+        decoder.write_all(chunk).await.map_err(copy_error)?;
+        reader.consume(len);
+        read += len as u64;
 
-    fn poll_flush(
-        self: Pin<&mut Self>,
-        cx: &mut task::Context<'_>,
-    ) -> task::Poll<prelude::v1::Result<(), io::Error>> {
-        Pin::new(&mut *self.inner.lock().unwrap()).poll_flush(cx)
-    }
-
-    fn poll_shutdown(
-        self: Pin<&mut Self>,
-        cx: &mut task::Context<'_>,
-    ) -> task::Poll<prelude::v1::Result<(), io::Error>> {
-        Pin::new(&mut *self.inner.lock().unwrap()).poll_shutdown(cx)
+        if let Some((id, start)) = checkpoints {
+            if read - checkpointed >= CHECKPOINT_INTERVAL {
+                // The entry just continues from the checkpoint before
+                if let Err(err) = save_checkpoint(id, entry, decoder, start + read).await {
+                    warn!("Failed to save where {} got to: {}", entry.name(), err);
+                }
+                checkpointed = read;
+            }
+        }
     }
-}
 
-#[derive(Error, Debug)]
-pub enum DownloadError {
-    #[error("download failed ({0} bytes")]
-    DownloadFailed(usize),
-    #[error("failed to download chunk `{entry}`: {error}")]
-    ChunkDownload {
-        entry: String,
-        error: reqwest::Error,
-    },
-    #[error("failed to copy chunk `{entry}`: {error}")]
-    ChunkCopy {
-        entry: String,
-        error: std::io::Error,
-    },
+    decoder.flush().await.map_err(copy_error)?;
+    Ok(read)
 }
 
 #[derive(PartialEq, Debug)]
@@ -259,33 +312,56 @@ struct DownloadContext {
     path: PathBuf,
 }
 
-type BytesDownloadedCallback = Box<dyn Fn(usize) + Send + Sync>;
+pub type BytesDownloadedCallback = Box<dyn Fn(usize) + Send + Sync>;
+
+/// Wraps `callback` for one download attempt, so bytes that are downloaded again after a
+/// retry aren't counted twice. `reported` is shared by every attempt of the entry.
+fn attempt_callback(
+    callback: &Arc<BytesDownloadedCallback>,
+    reported: &Arc<AtomicUsize>,
+) -> BytesDownloadedCallback {
+    let callback = callback.clone();
+    let reported = reported.clone();
+    let downloaded = AtomicUsize::new(0);
+
+    Box::new(move |bytes| {
+        let total = downloaded.fetch_add(bytes, Ordering::SeqCst) + bytes;
+        let previous = reported.fetch_max(total, Ordering::SeqCst);
+        if total > previous {
+            callback(total - previous);
+        }
+    })
+}
 
 struct EntryDownloadRequest<'a> {
-    context: &'a DownloadContext,
-    url: &'a str,
+    data: EntryData,
     entry: &'a ZipFileEntry,
     client: Client,
     decoder: Box<dyn DownloadDecoder>,
     callback: Option<BytesDownloadedCallback>,
+    limiter: Option<Arc<RateLimiter>>,
+    /// The download's ID, when the decoder's state is saved as it goes
+    checkpoints: Option<&'a str>,
 }
 
 impl<'a> EntryDownloadRequest<'a> {
     pub fn new(
-        context: &'a DownloadContext,
-        url: &'a str,
+        data: EntryData,
         entry: &'a ZipFileEntry,
         client: Client,
         decoder: Box<dyn DownloadDecoder>,
         callback: Option<BytesDownloadedCallback>,
+        limiter: Option<Arc<RateLimiter>>,
+        checkpoints: Option<&'a str>,
     ) -> Self {
         Self {
-            context,
-            url,
+            data,
             entry,
             client,
             decoder,
             callback,
+            limiter,
+            checkpoints,
         }
     }
 
@@ -294,9 +370,20 @@ impl<'a> EntryDownloadRequest<'a> {
         entry: &ZipFileEntry,
     ) -> Result<EntryDownloadState, DownloaderError> {
         let path = context.path.join(entry.name());
-
         let file_size = File::open(&path).await?.metadata().await?.len() as i64;
 
+        // Before anything else, a file that's continued is partial whatever its size
+        if let Some(checkpoint) = load_checkpoint(&context.id, entry).await? {
+            if file_size as u64 >= checkpoint.written {
+                return Ok(EntryDownloadState::Resumable);
+            }
+        }
+
+        let marker = partial_marker_path(&context.id, entry.name()).await?;
+        if tokio::fs::try_exists(marker).await? {
+            return Ok(EntryDownloadState::Borked);
+        }
+
         if file_size == 0 {
             return Ok(EntryDownloadState::Fresh);
         }
@@ -331,46 +418,55 @@ impl<'a> EntryDownloadRequest<'a> {
         Ok(EntryDownloadState::Complete)
     }
 
-    async fn download(&mut self) -> Result<(), DownloadError> {
-        let mut tries = 0;
-        while tries < 5 {
-            // State serialization is disabled for now.
-            //let start = self.decoder.write_in_pos() as i64;
-
-            let start = 0;
+    /// A single attempt from `start` in the entry's data, where the decoder is at. Retries
+    /// are up to the caller since the decoder can't be rewound.
+    async fn download(&mut self, start: i64) -> Result<(), DownloaderError> {
+        debug!(
+            "Downloading {} from {} to {} ({})",
+            self.entry.name(),
+            start,
+            self.entry.compressed_size(),
+            self.entry.uncompressed_size()
+        );
+        let end = *self.entry.compressed_size();
 
-            debug!(
-                "Downloading {} from {} to {} ({})",
-                self.entry.name(),
-                start,
-                self.entry.compressed_size(),
-                self.entry.uncompressed_size()
-            );
-            let end = *self.entry.compressed_size();
+        self.download_range(start, end).await
+    }
 
-            let result = self.download_range(start, end).await;
-            if result.is_ok() {
-                break;
-            }
+    /// End is not inclusive
+    pub async fn download_range(&mut self, start: i64, end: i64) -> Result<(), DownloaderError> {
+        let (stream, limiter) = match &self.data {
+            // Nothing to throttle when reading from disk
+            EntryData::Local(path, offset) => (
+                local_range(path, offset + start as u64, (end - start) as u64).await?,
+                None,
+            ),
+            EntryData::Remote(url) => (
+                self.remote_range(url, start, end).await?,
+                self.limiter.clone(),
+            ),
+        };
 
-            tries += 1;
-        }
+        let stream = throttle(stream, limiter);
+        let counting_stream = ByteCountingStream::new(stream, self.callback.as_ref());
+        let stream = counting_stream.into_async_read();
+        let stream_reader = BufReader::new(stream.compat());
 
+        let checkpoints = self.checkpoints.map(|id| (id, start as u64));
+        decode_entry(self.entry, &mut self.decoder, stream_reader, checkpoints).await?;
         Ok(())
     }
 
-    /// End is not inclusive
-    pub async fn download_range(&mut self, start: i64, end: i64) -> Result<(), DownloaderError> {
+    async fn remote_range(
+        &self,
+        url: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<ByteStream, DownloaderError> {
         let offset = self.entry.data_offset();
         let range = format!("bytes={}-{}", offset + start as i64, offset + end - 1);
 
-        let data = match self
-            .client
-            .get(self.url)
-            .header("range", range)
-            .send()
-            .await
-        {
+        let data = match self.client.get(url).header("range", range).send().await {
             Ok(res) => res,
             Err(err) => {
                 error!("Failed to download ({}): {}", self.entry.name(), err);
@@ -381,41 +477,30 @@ impl<'a> EntryDownloadRequest<'a> {
             }
         };
 
-        let stream = data.bytes_stream();
-        let counting_stream = ByteCountingStream::new(stream, self.callback.as_ref());
-        let stream = counting_stream.into_async_read();
-        let mut stream_reader = BufReader::new(stream.compat());
-
-        // State deserialization is disabled for now.
-        // let out_pos = self.decoder.write_out_pos();
-        // self.decoder.seek(SeekFrom::Start(out_pos));
-
-        let mut wrapper = AsyncWriterWrapper::new(
-            self.context.id.to_owned(),
-            self.entry.name().to_owned(),
-            &mut self.decoder,
-        )
-        .await?;
-
-        let result = tokio::io::copy(&mut stream_reader, &mut wrapper).await;
-        if let Err(err) = result {
-            return Err(DownloaderError::Download(DownloadError::ChunkCopy {
-                entry: self.entry.name().clone(),
-                error: err,
-            }));
+        if !data.status().is_success() {
+            return Err(DownloaderError::Http(data.status()));
         }
 
-        Ok(())
+        Ok(Box::pin(data.bytes_stream().map_err(io::Error::other)))
     }
 }
 
 #[derive(Getters)]
 pub struct ZipDownloader {
     id: String,
-    url: String,
+    source: BuildSource,
+    /// Where remote builds are downloaded from right now, see [`BuildSource::Remote`]
+    #[getter(skip)]
+    signed_url: Option<SignedUrl>,
     path: PathBuf,
     client: Client,
     manifest: ZipFile,
+    limiter: Option<Arc<RateLimiter>>,
+    retry: RetryPolicy,
+    preallocate: bool,
+    /// Attempts that failed and got retried, for tuning concurrency
+    #[getter(skip)]
+    failures: AtomicUsize,
 }
 
 impl ZipDownloader {
@@ -424,6 +509,20 @@ impl ZipDownloader {
         zip_url: &str,
         path: P,
     ) -> Result<Self, DownloaderError>
+    where
+        PathBuf: From<P>,
+    {
+        Self::from_source(id, BuildSource::Remote(zip_url.to_owned()), path, None).await
+    }
+
+    /// Like [`ZipDownloader::new`], but the build may also come from disk. `build` is the
+    /// build's own central directory, which extracted builds get their CRCs from.
+    pub async fn from_source<P: AsRef<Path>>(
+        id: &str,
+        source: BuildSource,
+        path: P,
+        build: Option<&ZipFile>,
+    ) -> Result<Self, DownloaderError>
     where
         PathBuf: From<P>,
     {
@@ -432,30 +531,115 @@ impl ZipDownloader {
             return Err(DownloaderError::PathNotAbsolute(path));
         }
 
-        let manifest = ZipFile::fetch(zip_url).await?;
+        let manifest = source.read_manifest(build).await?;
+        let signed_url = match &source {
+            BuildSource::Remote(url) => Some(SignedUrl::new(url.to_owned())),
+            _ => None,
+        };
 
         Ok(Self {
             id: id.to_owned(),
-            url: zip_url.to_owned(),
+            source,
+            signed_url,
             path,
             client: Client::builder().build()?,
             manifest,
+            limiter: None,
+            retry: RetryPolicy::default(),
+            preallocate: false,
+            failures: AtomicUsize::new(0),
         })
     }
 
+    /// Throttles every entry download through `limiter`
+    pub fn set_limiter(&mut self, limiter: Arc<RateLimiter>) {
+        self.limiter = Some(limiter);
+    }
+
+    /// Lets entries get new URLs signed when the CDN starts rejecting the current one.
+    /// Does nothing for local sources.
+    pub fn set_url_refresher(&mut self, refresher: Arc<dyn UrlRefresher>) {
+        if let Some(signed_url) = &mut self.signed_url {
+            signed_url.set_refresher(refresher);
+        }
+    }
+
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    pub fn set_preallocate(&mut self, preallocate: bool) {
+        self.preallocate = preallocate;
+    }
+
+    /// How many entry downloads failed and were retried so far
+    pub fn failure_count(&self) -> usize {
+        self.failures.load(Ordering::SeqCst)
+    }
+
     pub async fn read_zip_entry_bytes(
         &self,
         entry: &ZipFileEntry,
         length: u64,
     ) -> Result<Bytes, DownloaderError> {
+        let compressed_data = self.read_compressed(entry).await?;
+        let codec = Codec::for_method(entry.compression_type())?;
+
+        let mut decompressed_data = Vec::new();
+        codec
+            .reader(entry, &compressed_data)?
+            .take(length)
+            .read_to_end(&mut decompressed_data)?;
+
+        let requested = cmp::min(length, *entry.uncompressed_size() as u64);
+        if requested > decompressed_data.len() as u64 {
+            return Err(DownloaderError::EntrySize {
+                requested,
+                entry: decompressed_data.len(),
+            });
+        }
+
+        Ok(Bytes::from(decompressed_data))
+    }
+
+    /// Where `entry` is read from, and the generation of the URL for reporting it expired
+    fn entry_data(&self, entry: &ZipFileEntry) -> (EntryData, u64) {
+        if let Some(signed_url) = &self.signed_url {
+            let (url, generation) = signed_url.current();
+            return (EntryData::Remote(url), generation);
+        }
+
+        let data = match &self.source {
+            BuildSource::Archive(path) => {
+                EntryData::Local(path.clone(), *entry.data_offset() as u64)
+            }
+            BuildSource::Directory(path) => EntryData::Local(path.join(entry.name()), 0),
+            BuildSource::Remote(_) => unreachable!("remote sources always have URLs"),
+        };
+        (data, 0)
+    }
+
+    async fn read_compressed(&self, entry: &ZipFileEntry) -> Result<Bytes, DownloaderError> {
         let offset = entry.data_offset();
         let compressed_size = *entry.compressed_size();
 
+        let url = match self.entry_data(entry).0 {
+            EntryData::Remote(url) => url,
+            EntryData::Local(path, offset) => {
+                let mut file = File::open(path).await?;
+                file.seek(SeekFrom::Start(offset)).await?;
+
+                let mut data = vec![0; compressed_size as usize];
+                file.read_exact(&mut data).await?;
+                return Ok(Bytes::from(data));
+            }
+        };
+
         let range_header = format!("bytes={}-{}", offset, offset + compressed_size - 1);
 
         let response = self
             .client
-            .get(&self.url)
+            .get(&url)
             .header("Range", range_header)
             .send()
             .await?;
@@ -466,42 +650,58 @@ impl ZipDownloader {
             return Err(DownloaderError::Http(response.status()));
         }
 
-        let compressed_data = response.bytes().await?;
-        let decompressed_data = match entry.compression_type() {
-            CompressionType::None => {
-                let entry_size = *entry.uncompressed_size() as u64;
-                let available_length = std::cmp::min(length, entry_size);
-
-                if available_length > compressed_data.len() as u64 {
-                    return Err(DownloaderError::EntrySize {
-                        requested: available_length,
-                        entry: compressed_data.len(),
-                    });
-                }
+        Ok(response.bytes().await?)
+    }
 
-                Bytes::copy_from_slice(&compressed_data[..available_length as usize])
-            }
-            CompressionType::Deflate => {
-                let mut decoder = BufreadDeflateDecoder::new(Cursor::new(&compressed_data));
-                let mut limited_reader = decoder.take(length);
-                let mut decompressed_data = Vec::with_capacity(length as usize);
-                limited_reader.read_to_end(&mut decompressed_data)?;
+    /// Whether `entry`'s file got its full size before being written and wasn't finished
+    pub(crate) async fn is_preallocated(
+        &self,
+        entry: &ZipFileEntry,
+    ) -> Result<bool, DownloaderError> {
+        let marker = partial_marker_path(&self.id, entry.name()).await?;
+        Ok(tokio::fs::try_exists(marker).await?)
+    }
 
-                Bytes::from(decompressed_data)
-            }
-            any => {
-                return Err(DownloaderError::CompressionType(any.to_owned()));
-            }
+    /// Whether `entry`'s file was left partway through being written. Files that weren't
+    /// preallocated are told by their size, which is neither the entry's nor `previous`, the
+    /// size of the installed build's copy.
+    pub(crate) async fn is_partial(
+        &self,
+        entry: &ZipFileEntry,
+        previous: Option<i64>,
+    ) -> Result<bool, DownloaderError> {
+        if self.is_preallocated(entry).await? {
+            return Ok(true);
+        }
+
+        let size = match tokio::fs::metadata(self.path.join(entry.name())).await {
+            Ok(metadata) => metadata.len() as i64,
+            Err(_) => return Ok(false),
         };
+        Ok(size != *entry.uncompressed_size() && Some(size) != previous)
+    }
 
-        Ok(decompressed_data)
+    /// Whether `entry`'s file is its full size before it's done, and needs a partial marker
+    fn marks_partial(&self, state: &EntryDownloadState) -> bool {
+        // Borked files are cut to size before they're written again
+        self.preallocate || *state == EntryDownloadState::Borked
     }
 
-    pub async fn download_single_file(
+    fn context(&self) -> DownloadContext {
+        DownloadContext {
+            id: self.id.to_owned(),
+            path: self.path.clone(),
+        }
+    }
+
+    /// Gets `entry`'s file ready to be written. `None` when there's nothing to download,
+    /// for directories, empty files and files finished before a pause.
+    async fn prepare_entry(
         &self,
+        context: &DownloadContext,
         entry: &ZipFileEntry,
-        callback: Option<BytesDownloadedCallback>,
-    ) -> Result<usize, DownloaderError> {
+        callback: Option<&BytesDownloadedCallback>,
+    ) -> Result<Option<(File, EntryDownloadState)>, DownloaderError> {
         let file_path = self.path.join(entry.name());
 
         if !file_path.exists() {
@@ -513,13 +713,16 @@ impl ZipDownloader {
                 // This is a folder, create the dir
                 debug!("{} is a directory", entry.name());
                 create_dir(file_path).await?;
-                return Ok(0);
+                return Ok(None);
             }
         }
 
         if *entry.uncompressed_size() == 0 {
             debug!("{} is empty", entry.name());
-            return Ok(0);
+            if !entry.name().ends_with('/') {
+                File::create(&file_path).await?;
+            }
+            return Ok(None);
         }
 
         let offset = entry.data_offset();
@@ -533,17 +736,18 @@ impl ZipDownloader {
             .open(&file_path)
             .await?;
 
-        let context = DownloadContext {
-            id: self.id.to_owned(),
-            path: self.path.clone(),
-        };
-
-        let state = EntryDownloadRequest::state(&context, entry).await?;
+        let state = EntryDownloadRequest::state(context, entry).await?;
         if state == EntryDownloadState::Complete {
             if let Some(callback) = callback {
                 callback(*entry.compressed_size() as usize);
             }
-            return Ok(0);
+            return Ok(None);
+        }
+
+        // The file is about to be its full size without being done, mark it until it is
+        if self.marks_partial(&state) {
+            let marker = partial_marker_path(&self.id, entry.name()).await?;
+            tokio::fs::write(&marker, b"").await?;
         }
 
         if state == EntryDownloadState::Borked {
@@ -551,34 +755,276 @@ impl ZipDownloader {
             file.set_len(*entry.uncompressed_size() as u64).await?;
         }
 
-        let writer = tokio::io::BufWriter::new(file);
+        Ok(Some((file, state)))
+    }
 
-        let mut decoder: Box<dyn DownloadDecoder> = match entry.compression_type() {
-            CompressionType::None => Box::new(NoopDecoder::new(writer)),
-            CompressionType::Deflate => Box::new(ZLibDeflateDecoder::new(writer)),
+    pub async fn download_single_file(
+        &self,
+        entry: &ZipFileEntry,
+        callback: Option<BytesDownloadedCallback>,
+    ) -> Result<usize, DownloaderError> {
+        let context = self.context();
+        let (file, state) = match self
+            .prepare_entry(&context, entry, callback.as_ref())
+            .await?
+        {
+            Some(prepared) => prepared,
+            None => return Ok(0),
         };
 
-        if state == EntryDownloadState::Resumable {
-            let state_file = zstate_path(&self.id, &entry.name())?;
-            if state_file.exists() {
-                let mut buf = Bytes::from(tokio::fs::read(state_file).await?);
-                decoder.restore_state(&mut buf);
-            } else {
-                tokio::fs::create_dir_all(state_file.safe_parent()?).await?;
+        let file_path = self.path.join(entry.name());
+        let marked = self.marks_partial(&state);
+
+        let callback = callback.map(Arc::new);
+        let reported = Arc::new(AtomicUsize::new(0));
+        let mut file = Some(file);
+        let mut attempt = 1;
+        let mut refreshes = 0;
+        let mut checkpoint = match state {
+            EntryDownloadState::Resumable => load_checkpoint(&self.id, entry).await?,
+            _ => None,
+        };
+
+        loop {
+            let file = match file.take() {
+                Some(file) => file,
+                // Retries continue from the failed attempt's last checkpoint, if it got to one
+                None => {
+                    checkpoint = load_checkpoint(&self.id, entry).await?;
+                    OpenOptions::new()
+                        .write(true)
+                        .truncate(checkpoint.is_none())
+                        .open(&file_path)
+                        .await?
+                }
+            };
+
+            if self.preallocate {
+                preallocate(&file, *entry.uncompressed_size() as u64).await?;
             }
+
+            let (data, generation) = self.entry_data(entry);
+            let result = self
+                .download_attempt(
+                    entry,
+                    data,
+                    file,
+                    checkpoint.take(),
+                    callback.as_ref().map(|x| attempt_callback(x, &reported)),
+                )
+                .await;
+
+            match result {
+                Ok(()) => {
+                    remove_checkpoint(&self.id, entry).await?;
+                    if marked {
+                        let marker = partial_marker_path(&self.id, entry.name()).await?;
+                        tokio::fs::remove_file(marker).await?;
+                    }
+                    return Ok(0);
+                }
+                Err(err) if err.is_url_expired() && self.signed_url.is_some() => {
+                    if refreshes == MAX_URL_REFRESHES {
+                        error!("Download URL keeps getting rejected ({})", err);
+                        return Err(DownloaderError::UrlExpired);
+                    }
+
+                    // Not a failed attempt, the new URL gets the full set of retries
+                    warn!(
+                        "Downloading {} was rejected ({}), refreshing URL",
+                        entry.name(),
+                        err
+                    );
+                    if let Some(signed_url) = &self.signed_url {
+                        signed_url.refresh(generation).await?;
+                    }
+                    refreshes += 1;
+                }
+                Err(err) if err.is_transient() && self.retry.should_retry(attempt) => {
+                    self.failures.fetch_add(1, Ordering::SeqCst);
+
+                    let delay = self.retry.delay(attempt);
+                    warn!(
+                        "Downloading {} failed ({}), retrying in {:?} ({}/{})",
+                        entry.name(),
+                        err,
+                        delay,
+                        attempt,
+                        self.retry.max_attempts()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Splits `entries` into the requests they're downloaded with. Builds on disk have no
+    /// request overhead, so only remote ones get batched.
+    pub fn plan_downloads(&self, entries: Vec<ZipFileEntry>) -> Vec<DownloadUnit> {
+        if self.signed_url.is_some() {
+            batch::plan(entries)
+        } else {
+            entries.into_iter().map(DownloadUnit::Single).collect()
+        }
+    }
+
+    /// Downloads a batch from [`ZipDownloader::plan_downloads`] with a single range request,
+    /// reporting each entry's bytes once it's written. Nothing here is retried, this returns
+    /// the entries that didn't make it for [`ZipDownloader::download_single_file`] to finish.
+    pub async fn download_batch(
+        &self,
+        entries: &[ZipFileEntry],
+        callback: Option<BytesDownloadedCallback>,
+    ) -> Vec<ZipFileEntry> {
+        let mut done = vec![false; entries.len()];
+        if let Err(err) = self
+            .batch_attempt(entries, callback.as_ref(), &mut done)
+            .await
+        {
+            warn!(
+                "Batch of {} entries failed ({}), downloading the rest one by one",
+                entries.len(),
+                err
+            );
         }
 
+        entries
+            .iter()
+            .zip(done)
+            .filter(|(_, done)| !done)
+            .map(|(entry, _)| entry.clone())
+            .collect()
+    }
+
+    async fn batch_attempt(
+        &self,
+        entries: &[ZipFileEntry],
+        callback: Option<&BytesDownloadedCallback>,
+        done: &mut [bool],
+    ) -> Result<(), DownloaderError> {
+        let url = match &self.signed_url {
+            Some(signed_url) => signed_url.current().0,
+            // Nothing to save on disk, leave them all to download_single_file
+            None => return Ok(()),
+        };
+
+        let (start, end) = match (entries.first(), entries.last()) {
+            (Some(first), Some(last)) => (
+                *first.data_offset(),
+                last.data_offset() + last.compressed_size(),
+            ),
+            _ => return Ok(()),
+        };
+
+        debug!(
+            "Downloading {} entries from {} to {}",
+            entries.len(),
+            start,
+            end
+        );
+        let response = self
+            .client
+            .get(&url)
+            .header("range", format!("bytes={}-{}", start, end - 1))
+            .send()
+            .await?;
+
+        // Anything but the range we asked for can't be split up
+        if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+            return Err(DownloaderError::Http(response.status()));
+        }
+
+        let stream = Box::pin(response.bytes_stream().map_err(io::Error::other));
+        let stream = throttle(stream, self.limiter.clone());
+        let mut reader = BufReader::new(stream.into_async_read().compat());
+
+        let context = self.context();
+        let mut position = start;
+        for (index, entry) in entries.iter().enumerate() {
+            // Only once its bytes are next, a batch can have more entries than we can have
+            // open files. Entries with nothing to download are read past with the next gap.
+            let (file, state) = match self.prepare_entry(&context, entry, callback).await? {
+                Some(prepared) => prepared,
+                None => {
+                    done[index] = true;
+                    continue;
+                }
+            };
+
+            // Local headers, and entries that were already done, sit between the ones we want
+            let gap = (entry.data_offset() - position) as u64;
+            let skipped =
+                tokio::io::copy(&mut (&mut reader).take(gap), &mut tokio::io::sink()).await?;
+            if skipped != gap {
+                return Err(DownloaderError::Download(DownloadError::DownloadFailed(
+                    skipped as usize,
+                )));
+            }
+
+            let size = *entry.compressed_size() as u64;
+            let mut decoder = codec::decoder(entry, file)?;
+            let read = decode_entry(entry, &mut decoder, (&mut reader).take(size), None).await?;
+            if read != size {
+                return Err(DownloaderError::Download(DownloadError::DownloadFailed(
+                    read as usize,
+                )));
+            }
+
+            if self.marks_partial(&state) {
+                tokio::fs::remove_file(partial_marker_path(&self.id, entry.name()).await?).await?;
+            }
+            if let Some(callback) = callback {
+                callback(size as usize);
+            }
+
+            done[index] = true;
+            position = entry.data_offset() + entry.compressed_size();
+        }
+
+        Ok(())
+    }
+
+    async fn download_attempt(
+        &self,
+        entry: &ZipFileEntry,
+        data: EntryData,
+        file: File,
+        checkpoint: Option<Checkpoint>,
+        callback: Option<BytesDownloadedCallback>,
+    ) -> Result<(), DownloaderError> {
+        let codec = Codec::for_method(entry.compression_type())?;
+        let mut decoder = codec.decoder(entry, file);
+
+        let start = match checkpoint {
+            Some(mut checkpoint) if decoder.restore_state(&mut checkpoint.state) => {
+                debug!("Continuing {} from {}", entry.name(), checkpoint.read);
+                decoder.seek(SeekFrom::Start(checkpoint.written)).await?;
+                if let Some(callback) = &callback {
+                    callback(checkpoint.read as usize);
+                }
+                checkpoint.read
+            }
+            _ => {
+                // The entry starts over, an older checkpoint would skip what's written again
+                remove_checkpoint(&self.id, entry).await?;
+                0
+            }
+        };
+
+        let checkpoints = codec.resumable().then_some(self.id.as_str());
         let mut request = EntryDownloadRequest::new(
-            &context,
-            &self.url,
+            data,
             entry,
             self.client.clone(),
             decoder,
             callback,
+            self.limiter.clone(),
+            checkpoints,
         );
 
-        request.download().await?;
-        Ok(0)
+        request.download(start as i64).await
     }
 }
 
@@ -590,7 +1036,7 @@ struct ByteCountingStream<'a, S> {
 
 impl<'a, S> ByteCountingStream<'a, S>
 where
-    S: Stream<Item = Result<bytes::Bytes, reqwest::Error>>,
+    S: Stream<Item = Result<bytes::Bytes, io::Error>>,
 {
     fn new(inner: S, callback: Option<&'a BytesDownloadedCallback>) -> Self {
         ByteCountingStream {
@@ -607,7 +1053,7 @@ where
 
 impl<'a, S> Stream for ByteCountingStream<'a, S>
 where
-    S: Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Unpin,
+    S: Stream<Item = Result<bytes::Bytes, io::Error>> + Unpin,
 {
     type Item = Result<bytes::Bytes, tokio::io::Error>;
 
@@ -0,0 +1,441 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crc32fast::Hasher;
+use derive_getters::Getters;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    content::{
+        manager::DownloaderError,
+        zip::{self, ZipFileEntry},
+    },
+    util::hash::hash_file_crc32,
+};
+
+// This writes the subset of https://users.cs.jmu.edu/buchhofp/forensics/formats/pkzip.html
+// that `ZipFile` reads back
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const EOCD_SIGNATURE: u32 = 0x06054b50;
+const ZIP64_EOCD_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_EOCD_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+const ZIP64_EOCD_RECORD_SIZE: u64 = 44;
+const U32_MARKER: u32 = 0xFFFFFFFF;
+const U16_MARKER: u16 = 0xFFFF;
+
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+/// Names are UTF-8
+const FLAG_UTF8: u16 = 1 << 11;
+/// 1980-01-01 00:00, real timestamps would make two exports of the same build differ
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = (1 << 5) | 1;
+/// MS-DOS directory attribute
+const ATTR_DIRECTORY: u32 = 0x10;
+
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Stored as JSON in the archive comment, so the archive says which build it holds
+#[derive(Debug, Getters, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveMetadata {
+    offer_id: String,
+    build_id: String,
+    /// Game version from the build's manifest
+    version: Option<String>,
+}
+
+impl ArchiveMetadata {
+    pub fn new(offer_id: &str, build_id: &str, version: Option<String>) -> Self {
+        Self {
+            offer_id: offer_id.to_owned(),
+            build_id: build_id.to_owned(),
+            version,
+        }
+    }
+}
+
+struct CentralEntry {
+    name: String,
+    crc32: u32,
+    size: u64,
+    offset: u64,
+    is_dir: bool,
+}
+
+impl CentralEntry {
+    fn needs_zip64(&self) -> bool {
+        self.size >= U32_MARKER as u64 || self.offset >= U32_MARKER as u64
+    }
+}
+
+struct CountingWriter<W> {
+    inner: W,
+    position: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn write_u16(out: &mut impl Write, value: u16) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_u32(out: &mut impl Write, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_u64(out: &mut impl Write, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+/// A 32 bit size field, or the marker saying the real value is in the zip64 extra field
+fn size_field(value: u64) -> u32 {
+    if value >= U32_MARKER as u64 {
+        U32_MARKER
+    } else {
+        value as u32
+    }
+}
+
+fn write_local_header(out: &mut impl Write, entry: &CentralEntry) -> io::Result<()> {
+    // Local headers carry both sizes in the extra field, or neither
+    let zip64 = entry.size >= U32_MARKER as u64;
+    let (version, size, extra_len) = if zip64 {
+        (VERSION_ZIP64, U32_MARKER, 20)
+    } else {
+        (VERSION_DEFAULT, entry.size as u32, 0)
+    };
+
+    write_u32(out, LOCAL_HEADER_SIGNATURE)?;
+    write_u16(out, version)?;
+    write_u16(out, FLAG_UTF8)?;
+    write_u16(out, 0)?; // Stored
+    write_u16(out, DOS_TIME)?;
+    write_u16(out, DOS_DATE)?;
+    write_u32(out, entry.crc32)?;
+    write_u32(out, size)?; // Compressed
+    write_u32(out, size)?; // Uncompressed
+    write_u16(out, entry.name.len() as u16)?;
+    write_u16(out, extra_len)?;
+    out.write_all(entry.name.as_bytes())?;
+
+    if zip64 {
+        write_u16(out, ZIP64_EXTRA_FIELD)?;
+        write_u16(out, 16)?;
+        write_u64(out, entry.size)?;
+        write_u64(out, entry.size)?;
+    }
+
+    Ok(())
+}
+
+fn write_central_header(out: &mut impl Write, entry: &CentralEntry) -> io::Result<()> {
+    // Only the fields that overflowed go in the extra field, in this order
+    let mut extra = Vec::new();
+    if entry.size >= U32_MARKER as u64 {
+        write_u64(&mut extra, entry.size)?; // Uncompressed
+        write_u64(&mut extra, entry.size)?; // Compressed
+    }
+    if entry.offset >= U32_MARKER as u64 {
+        write_u64(&mut extra, entry.offset)?;
+    }
+
+    let version = if entry.needs_zip64() {
+        VERSION_ZIP64
+    } else {
+        VERSION_DEFAULT
+    };
+    // Includes the field's own header
+    let extra_len = if extra.is_empty() {
+        0
+    } else {
+        extra.len() as u16 + 4
+    };
+
+    write_u32(out, CENTRAL_HEADER_SIGNATURE)?;
+    write_u16(out, version)?; // Made by
+    write_u16(out, version)?; // Needed
+    write_u16(out, FLAG_UTF8)?;
+    write_u16(out, 0)?; // Stored
+    write_u16(out, DOS_TIME)?;
+    write_u16(out, DOS_DATE)?;
+    write_u32(out, entry.crc32)?;
+    write_u32(out, size_field(entry.size))?;
+    write_u32(out, size_field(entry.size))?;
+    write_u16(out, entry.name.len() as u16)?;
+    write_u16(out, extra_len)?;
+    write_u16(out, 0)?; // Comment
+    write_u16(out, 0)?; // Disk number start
+    write_u16(out, 0)?; // Internal attr.
+    write_u32(out, if entry.is_dir { ATTR_DIRECTORY } else { 0 })?;
+    write_u32(out, size_field(entry.offset))?;
+    out.write_all(entry.name.as_bytes())?;
+
+    if !extra.is_empty() {
+        write_u16(out, ZIP64_EXTRA_FIELD)?;
+        write_u16(out, extra.len() as u16)?;
+        out.write_all(&extra)?;
+    }
+
+    Ok(())
+}
+
+fn write_end_of_central_directory(
+    out: &mut CountingWriter<impl Write>,
+    entries: u64,
+    cd_size: u64,
+    cd_offset: u64,
+    comment: &[u8],
+) -> io::Result<()> {
+    // The reader only looks for the zip64 record when the offset is the marker
+    let zip64 = entries >= U16_MARKER as u64
+        || cd_size >= U32_MARKER as u64
+        || cd_offset >= U32_MARKER as u64;
+
+    if zip64 {
+        let record_offset = out.position;
+
+        write_u32(out, ZIP64_EOCD_SIGNATURE)?;
+        write_u64(out, ZIP64_EOCD_RECORD_SIZE)?;
+        write_u16(out, VERSION_ZIP64)?; // Made by
+        write_u16(out, VERSION_ZIP64)?; // Needed
+        write_u32(out, 0)?; // Disk number
+        write_u32(out, 0)?; // Disk with central directory
+        write_u64(out, entries)?;
+        write_u64(out, entries)?;
+        write_u64(out, cd_size)?;
+        write_u64(out, cd_offset)?;
+
+        write_u32(out, ZIP64_EOCD_LOCATOR_SIGNATURE)?;
+        write_u32(out, 0)?; // Disk with the zip64 record
+        write_u64(out, record_offset)?;
+        write_u32(out, 1)?; // Disk count
+    }
+
+    let entries = if zip64 { U16_MARKER } else { entries as u16 };
+    write_u32(out, EOCD_SIGNATURE)?;
+    write_u16(out, 0)?; // Disk number
+    write_u16(out, 0)?; // Disk with central directory
+    write_u16(out, entries)?;
+    write_u16(out, entries)?;
+    write_u32(out, if zip64 { U32_MARKER } else { cd_size as u32 })?;
+    write_u32(out, if zip64 { U32_MARKER } else { cd_offset as u32 })?;
+    write_u16(out, comment.len() as u16)?;
+    out.write_all(comment)
+}
+
+/// Copies `path` into the archive, returning the CRC32 of what was written
+fn copy_file(path: &Path, out: &mut impl Write, size: u64) -> io::Result<u32> {
+    let mut file = File::open(path)?.take(size);
+    let mut hasher = Hasher::new();
+    let mut buffer = vec![0; COPY_CHUNK_SIZE];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..read]);
+        out.write_all(&buffer[..read])?;
+    }
+
+    Ok(hasher.finalize())
+}
+
+/// Packs `entries` under `root` into a zip at `out`, with the same names and order as the build
+/// they came from. Files are stored uncompressed, and checked against their CRC32 as they're
+/// packed. Blocking, run it on a blocking thread.
+pub(crate) fn write_archive(
+    root: &Path,
+    entries: &[ZipFileEntry],
+    metadata: &ArchiveMetadata,
+    out: &Path,
+) -> Result<(), DownloaderError> {
+    let mut writer = CountingWriter {
+        inner: BufWriter::new(File::create(out)?),
+        position: 0,
+    };
+
+    let mut central = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let is_dir = entry.name().ends_with('/');
+        let size = if is_dir {
+            0
+        } else {
+            std::fs::metadata(root.join(entry.name()))?.len()
+        };
+
+        if !is_dir && size != *entry.uncompressed_size() as u64 {
+            return Err(DownloaderError::ChecksumMismatch(entry.name().to_owned()));
+        }
+
+        let written = CentralEntry {
+            name: entry.name().to_owned(),
+            crc32: if is_dir { 0 } else { *entry.crc32() },
+            size,
+            offset: writer.position,
+            is_dir,
+        };
+
+        debug!("Packing {}", written.name);
+        write_local_header(&mut writer, &written)?;
+        if !is_dir {
+            let crc32 = copy_file(&root.join(entry.name()), &mut writer, size)?;
+            if crc32 != written.crc32 {
+                return Err(DownloaderError::ChecksumMismatch(written.name));
+            }
+        }
+
+        central.push(written);
+    }
+
+    let cd_offset = writer.position;
+    for entry in &central {
+        write_central_header(&mut writer, entry)?;
+    }
+    let cd_size = writer.position - cd_offset;
+
+    let comment = serde_json::to_vec(metadata).map_err(io::Error::other)?;
+    write_end_of_central_directory(
+        &mut writer,
+        central.len() as u64,
+        cd_size,
+        cd_offset,
+        &comment,
+    )?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Packs everything under `root`, taking the files' CRC32s from the files themselves.
+/// Only meant for generating test builds, exports pack the build's own entries instead.
+#[doc(hidden)]
+pub fn archive_directory(
+    root: &Path,
+    metadata: &ArchiveMetadata,
+    out: &Path,
+) -> Result<(), DownloaderError> {
+    let entries = zip::directory_entries(root)?
+        .into_iter()
+        .map(|entry| {
+            if entry.name().ends_with('/') {
+                return Ok(entry);
+            }
+
+            let crc32 = hash_file_crc32(root.join(entry.name()))?;
+            Ok(ZipFileEntry::stored(
+                entry.name().to_owned(),
+                crc32,
+                *entry.uncompressed_size(),
+            ))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    write_archive(root, &entries, metadata, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{content::zip::ZipFile, util::testing::TempDir};
+
+    fn entries(root: &Path, files: &[(&str, Vec<u8>)]) -> Vec<ZipFileEntry> {
+        files
+            .iter()
+            .map(|(name, data)| {
+                if name.ends_with('/') {
+                    std::fs::create_dir_all(root.join(name)).unwrap();
+                    return ZipFileEntry::stored(name.to_string(), 0, 0);
+                }
+
+                std::fs::write(root.join(name), data).unwrap();
+                let crc32 = crc32fast::hash(data);
+                ZipFileEntry::stored(name.to_string(), crc32, data.len() as i64)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let dir = TempDir::new("export-round-trip");
+        let root = dir.path().join("game");
+        // `ZipFile` refuses archives smaller than its first read from the end
+        let files = [
+            ("Data/", Vec::new()),
+            (
+                "Data/levels.bin",
+                (0..40_000).map(|x| (x % 251) as u8).collect(),
+            ),
+            ("Data/empty.txt", Vec::new()),
+            ("game.exe", b"MZ not really an executable".to_vec()),
+        ];
+        std::fs::create_dir_all(&root).unwrap();
+        let entries = entries(&root, &files);
+
+        let out = dir.path().join("game.zip");
+        let metadata = ArchiveMetadata::new("Origin.OFR.50.0001", "1234", Some("1.0".into()));
+        write_archive(&root, &entries, &metadata, &out).unwrap();
+
+        let zip = ZipFile::open(&out).await.unwrap();
+        assert_eq!(zip.entries().len(), entries.len());
+        for (read, written) in zip.entries().iter().zip(&entries) {
+            assert_eq!(read.name(), written.name());
+            assert_eq!(read.crc32(), written.crc32());
+            assert_eq!(read.uncompressed_size(), written.uncompressed_size());
+            assert_eq!(read.compressed_size(), written.uncompressed_size());
+        }
+
+        let archive = std::fs::read(&out).unwrap();
+        let comment = serde_json::to_vec(&metadata).unwrap();
+        assert!(archive.ends_with(&comment));
+
+        // The data offsets point at each file's bytes
+        for ((name, data), read) in files.iter().zip(zip.entries()) {
+            let start = *read.data_offset() as usize;
+            assert_eq!(
+                &archive[start..start + data.len()],
+                data.as_slice(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn mismatched_file() {
+        let dir = TempDir::new("export-mismatch");
+        let entries = entries(dir.path(), &[("save.dat", b"original".to_vec())]);
+        std::fs::write(dir.path().join("save.dat"), b"modified").unwrap();
+
+        let metadata = ArchiveMetadata::new("Origin.OFR.50.0001", "1234", None);
+        let result = write_archive(
+            dir.path(),
+            &entries,
+            &metadata,
+            &dir.path().join("save.zip"),
+        );
+        assert!(
+            matches!(result, Err(DownloaderError::ChecksumMismatch(name)) if name == "save.dat")
+        );
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::BTreeSet;
+
+use derive_builder::Builder;
+use derive_getters::Getters;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    content::zip::ZipFileEntry,
+    core::manifest::dip::{DiPFileSet, DiPManifest},
+};
+
+lazy_static! {
+    // EA builds name locale specific content with the `xx_YY` form, e.g. `Loc/fr_FR/` or `Speech_de_DE.sb`
+    static ref LOCALE_PATTERN: Regex =
+        Regex::new(r"(?:^|[^A-Za-z])([a-z]{2})[_-]([A-Z]{2})(?:[^A-Za-z]|$)").unwrap();
+}
+
+/// Always installed, touchup and the manifest live here
+const INSTALLER_DIR: &str = "__Installer/";
+const DLC_DIR: &str = "dlc";
+
+/// What the user asked to install. Stored with the queued game and the install record
+/// so resumed downloads and updates pick the same content.
+#[derive(Default, Debug, Builder, Getters, Clone, Serialize, Deserialize, PartialEq)]
+#[builder(default)]
+pub struct InstallFilter {
+    /// Locales to install, e.g. `en_US`. `None` installs every language
+    #[builder(setter(strip_option))]
+    languages: Option<Vec<String>>,
+    /// Optional components to install. `None` installs every component
+    #[builder(setter(strip_option))]
+    components: Option<Vec<String>>,
+}
+
+impl InstallFilter {
+    pub fn is_empty(&self) -> bool {
+        self.languages.is_none() && self.components.is_none()
+    }
+
+    /// Whether `locale`'s files are installed
+    pub fn has_language(&self, locale: &str) -> bool {
+        match &self.languages {
+            Some(languages) => languages.iter().any(|x| x.eq_ignore_ascii_case(locale)),
+            None => true,
+        }
+    }
+
+    /// Installs `locale` too. Nothing changes when every language is installed anyway
+    pub fn add_language(&mut self, locale: &str) {
+        if self.has_language(locale) {
+            return;
+        }
+
+        if let Some(languages) = &mut self.languages {
+            languages.push(locale.to_owned());
+        }
+    }
+
+    pub fn includes(&self, rules: &InstallRules, entry: &ZipFileEntry) -> bool {
+        let name = entry.name();
+        if name.starts_with(INSTALLER_DIR) {
+            return true;
+        }
+
+        if let Some(languages) = &self.languages {
+            if !rules.wanted_by(name, languages) {
+                return false;
+            }
+        }
+
+        if let (Some(components), Some(component)) =
+            (&self.components, InstallRules::entry_component(name))
+        {
+            if !components.iter().any(|x| x.eq_ignore_ascii_case(component)) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Entries of `entries` that should end up on disk
+    pub fn apply(&self, rules: &InstallRules, entries: &[ZipFileEntry]) -> Vec<ZipFileEntry> {
+        entries
+            .iter()
+            .filter(|x| self.includes(rules, x))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Maps build entries to the language and optional component they belong to
+#[derive(Default, Debug, Getters, Clone)]
+pub struct InstallRules {
+    locales: BTreeSet<String>,
+    components: BTreeSet<String>,
+    /// Files each locale needs, from the manifest's `supportedLocales`. `None` goes by
+    /// [`LOCALE_PATTERN`] instead
+    #[getter(skip)]
+    locale_files: Option<Vec<(String, DiPFileSet)>>,
+}
+
+impl InstallRules {
+    pub fn from_entries(entries: &[ZipFileEntry]) -> Self {
+        let mut rules = Self::default();
+        for entry in entries {
+            if let Some(locale) = rules.entry_locale(entry.name()) {
+                rules.locales.insert(locale);
+            }
+
+            if let Some(component) = Self::entry_component(entry.name()) {
+                rules.components.insert(component.to_owned());
+            }
+        }
+
+        rules
+    }
+
+    /// Goes by the locales `manifest` lists and the files they need instead of file names,
+    /// unless it doesn't list any
+    pub fn with_manifest(mut self, manifest: &DiPManifest) -> Self {
+        let supported = &manifest.supportedLocales.locale;
+        if supported.is_empty() {
+            return self;
+        }
+
+        self.locales = supported.iter().map(|x| x.attr_name.clone()).collect();
+        self.locale_files = Some(
+            supported
+                .iter()
+                .filter(|x| !x.fileSet.is_empty())
+                .map(|x| (x.attr_name.clone(), x.fileSet.clone()))
+                .collect(),
+        );
+        self
+    }
+
+    /// Locale an entry is specific to, `None` for shared content. Where the manifest has
+    /// more than one locale needing the entry, the first of them.
+    pub fn entry_locale(&self, name: &str) -> Option<String> {
+        if name.starts_with(INSTALLER_DIR) {
+            return None;
+        }
+
+        match &self.locale_files {
+            Some(locale_files) => locale_files
+                .iter()
+                .find(|(_, files)| files.matches(name))
+                .map(|(locale, _)| locale.clone()),
+            None => Self::name_locale(name),
+        }
+    }
+
+    /// Locale in an entry's name, like `fr_FR` in `Loc/fr_FR/strings.bin`
+    pub fn name_locale(name: &str) -> Option<String> {
+        let captures = LOCALE_PATTERN.captures(name)?;
+        Some(format!("{}_{}", &captures[1], &captures[2]))
+    }
+
+    /// Whether an entry is shared content or needed by one of `languages`
+    pub fn wanted_by(&self, name: &str, languages: &[String]) -> bool {
+        let wanted = |locale: &str| languages.iter().any(|x| x.eq_ignore_ascii_case(locale));
+        match &self.locale_files {
+            Some(locale_files) => {
+                let mut needed_by = locale_files
+                    .iter()
+                    .filter(|(_, files)| files.matches(name))
+                    .peekable();
+                needed_by.peek().is_none() || needed_by.any(|(locale, _)| wanted(locale))
+            }
+            None => match self.entry_locale(name) {
+                Some(locale) => wanted(&locale),
+                None => true,
+            },
+        }
+    }
+
+    /// Optional component an entry belongs to. DLC ships in `DLC/<name>/` folders
+    pub fn entry_component(name: &str) -> Option<&str> {
+        let mut parts = name.split('/').peekable();
+        while let Some(part) = parts.next() {
+            if !part.eq_ignore_ascii_case(DLC_DIR) {
+                continue;
+            }
+
+            let component = parts.next()?;
+            // Files directly inside the DLC folder aren't part of a component
+            parts.peek()?;
+            if component.is_empty() {
+                return None;
+            }
+
+            return Some(component);
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(names: &[&str]) -> Vec<ZipFileEntry> {
+        names
+            .iter()
+            .map(|x| ZipFileEntry::stored(x.to_string(), 0, 0))
+            .collect()
+    }
+
+    fn filter(languages: &[&str], components: Option<&[&str]>) -> InstallFilter {
+        let mut filter = InstallFilterBuilder::default();
+        filter.languages(languages.iter().map(|x| x.to_string()).collect());
+        if let Some(components) = components {
+            filter.components(components.iter().map(|x| x.to_string()).collect());
+        }
+        filter.build().unwrap()
+    }
+
+    fn included(
+        filter: &InstallFilter,
+        rules: &InstallRules,
+        entries: &[ZipFileEntry],
+    ) -> Vec<String> {
+        filter
+            .apply(rules, entries)
+            .iter()
+            .map(|x| x.name().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn entry_locale() {
+        let rules = InstallRules::default();
+        assert_eq!(
+            rules.entry_locale("Loc/fr_FR/strings.bin").as_deref(),
+            Some("fr_FR")
+        );
+        assert_eq!(
+            rules.entry_locale("Sound/Speech_de-DE.sb").as_deref(),
+            Some("de_DE")
+        );
+        assert_eq!(rules.entry_locale("en_US.pak").as_deref(), Some("en_US"));
+
+        assert_eq!(
+            rules.entry_locale("__Installer/Loc/fr_FR/strings.bin"),
+            None
+        );
+        assert_eq!(rules.entry_locale("Data/Win32/Loc/strings.bin"), None);
+        assert_eq!(rules.entry_locale("Bin/x64_RETAIL/game.dll"), None);
+        assert_eq!(rules.entry_locale("Data/shader_FRX.bin"), None);
+        assert_eq!(rules.entry_locale("Data/ab_CDE.bin"), None);
+    }
+
+    #[test]
+    fn entry_component() {
+        assert_eq!(
+            InstallRules::entry_component("DLC/Xpack1/data.sb"),
+            Some("Xpack1")
+        );
+        assert_eq!(
+            InstallRules::entry_component("Game/dlc/Xpack2/a/b.sb"),
+            Some("Xpack2")
+        );
+
+        assert_eq!(InstallRules::entry_component("DLC/readme.txt"), None);
+        assert_eq!(InstallRules::entry_component("DLC//data.sb"), None);
+        assert_eq!(
+            InstallRules::entry_component("Data/DLCs/Xpack1/data.sb"),
+            None
+        );
+        assert_eq!(InstallRules::entry_component("Data/Win32/game.sb"), None);
+    }
+
+    #[test]
+    fn from_entries() {
+        let rules = InstallRules::from_entries(&entries(&[
+            "__Installer/installerdata.xml",
+            "Loc/de_DE/strings.bin",
+            "Loc/fr_FR/strings.bin",
+            "DLC/Xpack1/data.sb",
+            "game.exe",
+        ]));
+
+        assert!(rules.locales().iter().eq(["de_DE", "fr_FR"]));
+        assert!(rules.components().iter().eq(["Xpack1"]));
+    }
+
+    #[test]
+    fn includes() {
+        let entries = entries(&[
+            "__Installer/installerdata.xml",
+            "__Installer/Loc/fr_FR/touchup.dat",
+            "game.exe",
+            "Loc/de_DE/strings.bin",
+            "Loc/fr_FR/strings.bin",
+            "DLC/Xpack1/data.sb",
+            "DLC/Xpack2/data.sb",
+        ]);
+        let rules = InstallRules::from_entries(&entries);
+
+        assert_eq!(
+            InstallFilter::default().apply(&rules, &entries).len(),
+            entries.len()
+        );
+        assert_eq!(
+            included(&filter(&["DE_de"], Some(&["xpack2"])), &rules, &entries),
+            [
+                "__Installer/installerdata.xml",
+                "__Installer/Loc/fr_FR/touchup.dat",
+                "game.exe",
+                "Loc/de_DE/strings.bin",
+                "DLC/Xpack2/data.sb",
+            ]
+        );
+        assert_eq!(
+            included(&filter(&[], Some(&[])), &rules, &entries),
+            [
+                "__Installer/installerdata.xml",
+                "__Installer/Loc/fr_FR/touchup.dat",
+                "game.exe",
+            ]
+        );
+    }
+
+    #[test]
+    fn manifest_locales() {
+        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/manifests/full.xml");
+        let manifest = DiPManifest::from_bytes(std::fs::read(path).unwrap()).unwrap();
+
+        let entries = entries(&[
+            "game.exe",
+            "Data/Win32/Loc/en/strings.bin",
+            "Data/Win32/Loc/de/strings.bin",
+            "Data/Win32/Sound/VO/de/chunk0.sb",
+            "Data/Win32/Sound/VO/de/chunk0.bak",
+            "Data/Win32/Loc/ja/strings.bin",
+            "Data/Win32/Loc/fr_FR/strings.bin",
+        ]);
+        let rules = InstallRules::from_entries(&entries).with_manifest(&manifest);
+
+        assert!(rules.locales().iter().eq(["de_DE", "en_US", "ja_JP"]));
+        assert_eq!(
+            rules
+                .entry_locale("Data/Win32/Loc/de/strings.bin")
+                .as_deref(),
+            Some("de_DE")
+        );
+        assert_eq!(
+            rules
+                .entry_locale("data\\win32\\loc\\ja\\strings.bin")
+                .as_deref(),
+            Some("ja_JP")
+        );
+        // Only the manifest counts once it lists locales
+        assert_eq!(rules.entry_locale("Data/Win32/Loc/fr_FR/strings.bin"), None);
+        assert_eq!(
+            rules.entry_locale("Data/Win32/Sound/VO/de/chunk0.bak"),
+            None
+        );
+
+        assert_eq!(
+            included(&filter(&["de_DE"], None), &rules, &entries),
+            [
+                "game.exe",
+                "Data/Win32/Loc/de/strings.bin",
+                "Data/Win32/Sound/VO/de/chunk0.sb",
+                "Data/Win32/Sound/VO/de/chunk0.bak",
+                "Data/Win32/Loc/fr_FR/strings.bin",
+            ]
+        );
+    }
+
+    #[test]
+    fn manifest_without_locales() {
+        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/manifests/minimal.xml");
+        let manifest = DiPManifest::from_bytes(std::fs::read(path).unwrap()).unwrap();
+
+        let rules = InstallRules::default().with_manifest(&manifest);
+        assert_eq!(
+            rules.entry_locale("Loc/fr_FR/strings.bin").as_deref(),
+            Some("fr_FR")
+        );
+    }
+
+    #[test]
+    fn add_language() {
+        let mut filter = filter(&["en_US"], None);
+        assert!(!filter.has_language("fr_FR"));
+        filter.add_language("fr_FR");
+        filter.add_language("FR_fr");
+        assert!(filter.has_language("fr_fr"));
+        assert_eq!(filter.languages().as_ref().unwrap().len(), 2);
+
+        let mut filter = InstallFilter::default();
+        filter.add_language("fr_FR");
+        assert!(filter.is_empty());
+    }
+}
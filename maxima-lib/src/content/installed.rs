@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    content::{
+        downloader::BuildSource,
+        filter::InstallFilter,
+        manager::{ContentManagerError, QueuedGame},
+        zip::ZipFileEntry,
+    },
+    core::locale::Locale,
+    util::native::{maxima_dir, NativeError},
+};
+
+const INSTALLS_DIR: &str = "installs";
+
+/// What we know about a game after it finished installing. Updates and repairs
+/// start from here rather than from whatever the user picks next time.
+#[derive(Default, Debug, Getters, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InstalledGame {
+    offer_id: String,
+    build_id: String,
+    path: PathBuf,
+    #[serde(default)]
+    filter: InstallFilter,
+    /// Central directory of the installed build, after filtering
+    #[serde(default)]
+    entries: Vec<InstalledEntry>,
+    /// Where the build came from, if it wasn't the CDN. Verifying and repairing use it too
+    #[serde(default)]
+    source: Option<BuildSource>,
+    /// Language the game was touched up in, unknown for installs from before Maxima kept it
+    #[serde(default)]
+    locale: Option<String>,
+}
+
+#[derive(Default, Debug, Getters, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InstalledEntry {
+    name: String,
+    crc32: u32,
+    size: i64,
+}
+
+impl From<&ZipFileEntry> for InstalledEntry {
+    fn from(entry: &ZipFileEntry) -> Self {
+        Self {
+            name: entry.name().to_owned(),
+            crc32: *entry.crc32(),
+            size: *entry.uncompressed_size(),
+        }
+    }
+}
+
+fn record_path(offer_id: &str) -> Result<PathBuf, NativeError> {
+    Ok(maxima_dir()?
+        .join(INSTALLS_DIR)
+        .join(format!("{}.json", offer_id)))
+}
+
+impl InstalledGame {
+    /// A game we know nothing about yet, besides where it is
+    pub(crate) fn new(offer_id: &str, build_id: &str, path: PathBuf) -> Self {
+        Self {
+            offer_id: offer_id.to_owned(),
+            build_id: build_id.to_owned(),
+            path,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn from_queued(game: &QueuedGame, entries: &[ZipFileEntry]) -> Self {
+        Self {
+            offer_id: game.offer_id().to_owned(),
+            build_id: game.build_id().to_owned(),
+            path: game.path().to_owned(),
+            filter: game.filter().to_owned(),
+            entries: entries.iter().map(InstalledEntry::from).collect(),
+            source: game.source().to_owned(),
+            locale: Some(game.game_locale()),
+        }
+    }
+
+    pub(crate) fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+    }
+
+    pub(crate) fn set_locale(&mut self, locale: &str) {
+        self.locale = Some(locale.to_owned());
+    }
+
+    /// The language touchup and the game get, [`Locale::default`] if it's unknown
+    pub fn game_locale(&self) -> String {
+        self.locale
+            .clone()
+            .unwrap_or_else(|| Locale::default().full_str().to_owned())
+    }
+
+    pub async fn load(offer_id: &str) -> Result<Option<InstalledGame>, ContentManagerError> {
+        let file = record_path(offer_id)?;
+        if !file.exists() {
+            return Ok(None);
+        }
+
+        let data = fs::read_to_string(file).await?;
+        Ok(Some(serde_json::from_str(&data)?))
+    }
+
+    pub(crate) async fn save(&self) -> Result<(), ContentManagerError> {
+        let file = record_path(&self.offer_id)?;
+        fs::create_dir_all(maxima_dir()?.join(INSTALLS_DIR)).await?;
+        fs::write(file, serde_json::to_string(&self)?).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn delete(offer_id: &str) -> Result<(), ContentManagerError> {
+        match fs::remove_file(record_path(offer_id)?).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,15 +1,17 @@
 use std::{
-    path::PathBuf,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
 use derive_builder::Builder;
 use derive_getters::Getters;
 use futures::StreamExt;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -18,26 +20,116 @@ use tokio_util::sync::CancellationToken;
 
 use crate::{
     content::{
-        downloader::{DownloadError, ZipDownloader},
-        zip::{self, CompressionType, ZipError, ZipFileEntry},
+        batch::DownloadUnit,
+        builds::{side_by_side_path, InstalledBuilds},
+        cdn::ServiceUrlRefresher,
+        codec,
+        concurrency::{AdaptiveTuner, Concurrency, ConcurrencyGate},
+        dedup::{ContentStore, DedupMode},
+        diff,
+        downloader::{self, BuildSource, BytesDownloadedCallback, DownloadError, ZipDownloader},
+        export::{self, ArchiveMetadata},
+        filter::{InstallFilter, InstallRules},
+        installed::InstalledGame,
+        progress::{DownloadPhase, DownloadProgress, ProgressTracker},
+        progressive::{self, ProgressiveStatus},
+        relocate::InstallMove,
+        retry::RetryPolicy,
+        schedule::{DownloadSchedule, HoldReason},
+        settings::DownloadSettings,
+        space::{self, TOUCHUP_MARGIN},
+        throttle::RateLimiter,
+        verify::{self, VerifyReport},
+        zip::{self, CompressionType, ZipError, ZipFile, ZipFileEntry},
         ContentService,
     },
     core::{
         auth::storage::LockedAuthStorage,
-        manifest::{self, ManifestError, MANIFEST_RELATIVE_PATH},
+        locale::Locale,
+        manifest::{self, dip::DiPManifest, ManifestError, MANIFEST_RELATIVE_PATH},
         service_layer::ServiceLayerError,
         MaximaEvent,
     },
-    util::native::{maxima_dir, NativeError},
+    util::{
+        native::{available_space, maxima_dir, NativeError},
+        registry::remove_reg_keys,
+    },
 };
 
 const QUEUE_FILE: &str = "download_queue.json";
+/// How often a [`MaximaEvent::DownloadProgress`] is sent while the phase doesn't change
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+/// Window adaptive concurrency measures throughput over, see [`Concurrency::Adaptive`]
+const TUNE_INTERVAL: Duration = Duration::from_secs(3);
+/// How many entries are looked up in the content store at once, each hashes a file
+const LINK_CONCURRENCY: usize = 4;
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum InstallMode {
+    /// Download every entry of the build
+    #[default]
+    Full,
+    /// Only download entries that differ from the installed files, and delete
+    /// files the new build doesn't have anymore
+    Update,
+    /// Download [`QueuedGame::repair_entries`] again
+    Repair,
+}
 
 #[derive(Default, Builder, Getters, Clone, Serialize, Deserialize, PartialEq)]
 pub struct QueuedGame {
     offer_id: String,
     build_id: String,
     path: PathBuf,
+    #[serde(default)]
+    #[builder(default)]
+    filter: InstallFilter,
+    #[serde(default)]
+    #[builder(default)]
+    mode: InstallMode,
+    /// Entries found missing or corrupt by [`ContentManager::verify`]
+    #[serde(default)]
+    #[builder(default)]
+    repair_entries: Vec<String>,
+    /// Install from a local build instead of the CDN, see [`BuildSource::local`]
+    #[serde(default)]
+    #[builder(default, setter(strip_option))]
+    source: Option<BuildSource>,
+    /// Overrides [`DownloadSettings::concurrency`] for this install
+    #[serde(default)]
+    #[builder(default, setter(strip_option))]
+    concurrency: Option<Concurrency>,
+    /// Installed next to the active build instead of replacing it, see
+    /// [`ContentManager::add_build`]
+    #[serde(default)]
+    #[builder(default)]
+    side_by_side: bool,
+    /// Language the game is touched up and started in, e.g. `en_US`. Installed even if the
+    /// filter leaves it out. `None` picks the first filtered language, or [`Locale::default`]
+    #[serde(default)]
+    #[builder(default, setter(into, strip_option))]
+    locale: Option<String>,
+}
+
+impl QueuedGame {
+    /// The language touchup and the game get, see [`QueuedGame::locale`]
+    pub fn game_locale(&self) -> String {
+        self.locale
+            .clone()
+            .or_else(|| {
+                self.filter
+                    .languages()
+                    .as_ref()
+                    .and_then(|x| x.first().cloned())
+            })
+            .unwrap_or_else(|| Locale::default().full_str().to_owned())
+    }
+
+    fn include_locale(&mut self) {
+        if let Some(locale) = &self.locale {
+            self.filter.add_language(locale);
+        }
+    }
 }
 
 #[derive(Default, Getters, Serialize, Deserialize)]
@@ -47,6 +139,9 @@ pub struct DownloadQueue {
 
     queued: Vec<QueuedGame>,
     completed: Vec<QueuedGame>,
+    /// Installs that gave up, adding them again retries
+    #[serde(default)]
+    failed: Vec<QueuedGame>,
 }
 
 #[derive(Error, Debug)]
@@ -62,6 +157,38 @@ pub enum ContentManagerError {
 
     #[error("download in progress, you must cancel it before starting a new one")]
     DownloadInProgress,
+    #[error("`{0}` is not installed")]
+    NotInstalled(String),
+    #[error("not enough disk space, {required} bytes are needed but only {available} are free")]
+    InsufficientSpace { required: u64, available: u64 },
+    #[error("a game is already being moved")]
+    MoveInProgress,
+    #[error("`{0}` already exists")]
+    DestinationExists(PathBuf),
+    #[error("`{0}` was not copied completely")]
+    IncompleteCopy(String),
+    #[error("the build of `{0}` has no installer manifest")]
+    NoManifest(String),
+    #[error("build `{1}` of `{0}` is not installed")]
+    BuildNotInstalled(String, String),
+    #[error("build `{0}` is already installed")]
+    BuildInstalled(String),
+    #[error("build `{0}` is active, activate another one before removing it")]
+    BuildActive(String),
+    #[error("`{0}` has no previous build to roll back to")]
+    NoPreviousBuild(String),
+    #[error("`{0}` doesn't support changing its language")]
+    LanguageChangeUnsupported(String),
+    #[error("the build of `{0}` doesn't ship `{1}`")]
+    UnsupportedLocale(String, String),
+    #[error("`{0}` is running, close it first")]
+    GameRunning(String),
+    #[error("can't move `{0}` to `{1}`, one is inside the other")]
+    NestedMove(PathBuf, PathBuf),
+    #[error("nothing records which files of `{0}` are the game's")]
+    NoInstallRecord(String),
+    #[error("`{0}` was installed from a folder without the build's checksums to check it against")]
+    Unverifiable(String),
 }
 
 #[derive(Error, Debug)]
@@ -89,6 +216,80 @@ pub enum DownloaderError {
     EntrySize { requested: u64, entry: usize },
     #[error("unsupported compression type `{0:?}`")]
     CompressionType(CompressionType),
+    #[error("{0} files failed to download")]
+    FailedEntries(usize),
+    #[error("`{0}` doesn't match the build's checksum")]
+    ChecksumMismatch(String),
+    #[error("download URL expired and couldn't be refreshed")]
+    UrlExpired,
+}
+
+impl DownloaderError {
+    /// Whether trying again could help, like after a dropped connection or an overloaded CDN.
+    /// Anything else, a full disk or a 404, will fail the same way every time.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            DownloaderError::Request(err) => is_transient_request(err),
+            DownloaderError::Http(status) => is_transient_status(*status),
+            DownloaderError::Download(DownloadError::DownloadFailed(_)) => true,
+            DownloaderError::Download(DownloadError::ChunkDownload { error, .. }) => {
+                is_transient_request(error)
+            }
+            DownloaderError::Download(DownloadError::ChunkCopy { error, .. }) => {
+                is_transient_io(error)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the CDN rejected the signed URL, which happens once it expires.
+    /// Getting a new one signed fixes these, trying the same URL again doesn't.
+    pub fn is_url_expired(&self) -> bool {
+        let status = match self {
+            DownloaderError::Http(status) => *status,
+            DownloaderError::Request(err) => match err.status() {
+                Some(status) => status,
+                None => return false,
+            },
+            _ => return false,
+        };
+
+        matches!(
+            status,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::GONE
+        )
+    }
+}
+
+fn is_transient_request(err: &reqwest::Error) -> bool {
+    if let Some(status) = err.status() {
+        return is_transient_status(status);
+    }
+
+    // Anything else, like a malformed URL or a redirect loop, fails the same way again
+    err.is_timeout() || err.is_connect() || err.is_body() || err.is_decode()
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn is_transient_io(err: &std::io::Error) -> bool {
+    // Errors from the response stream get wrapped into io errors while copying
+    if err.get_ref().is_some_and(|x| x.is::<DownloadError>()) {
+        return true;
+    }
+
+    matches!(
+        err.kind(),
+        std::io::ErrorKind::TimedOut
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::UnexpectedEof
+            | std::io::ErrorKind::Interrupted
+    )
 }
 
 impl DownloadQueue {
@@ -124,14 +325,29 @@ impl DownloadQueue {
 
 pub struct GameDownloader {
     offer_id: String,
+    job: DownloadJob,
+}
 
+/// State shared between a [`GameDownloader`] and its download task
+#[derive(Clone)]
+struct DownloadJob {
     downloader: Arc<ZipDownloader>,
     entries: Vec<ZipFileEntry>,
+    mode: InstallMode,
+    previous: Option<InstalledGame>,
+    repair_entries: HashSet<String>,
+    concurrency: Concurrency,
+    side_by_side: bool,
+    /// Where files other installs already have are linked from, see [`DedupMode`]
+    store: Option<Arc<ContentStore>>,
+    dedup: DedupMode,
+    /// Set once the game can start while the rest downloads, see [`ProgressiveStatus`]
+    launchable: Arc<AtomicBool>,
+    /// What touchup gets as the game's language
+    locale: String,
 
     cancel_token: CancellationToken,
-    completed_bytes: Arc<AtomicUsize>,
-    total_count: usize,
-    total_bytes: usize,
+    progress: Arc<ProgressTracker>,
     notify: Arc<Notify>,
 }
 
@@ -139,163 +355,720 @@ impl GameDownloader {
     pub async fn new(
         content_service: &ContentService,
         game: &QueuedGame,
+        previous: Option<InstalledGame>,
+        limiter: Arc<RateLimiter>,
+        retry: RetryPolicy,
+        preallocate: bool,
+        concurrency: Concurrency,
     ) -> Result<Self, DownloaderError> {
-        let url = content_service
-            .download_url(&game.offer_id, Some(&game.build_id))
-            .await?;
+        let source = match &game.source {
+            Some(source) => source.clone(),
+            None => {
+                let url = content_service
+                    .download_url(&game.offer_id, Some(&game.build_id))
+                    .await?;
 
-        debug!("URL: {}", url.url());
+                debug!("URL: {}", url.url());
+                BuildSource::Remote(url.url().to_owned())
+            }
+        };
 
-        let downloader = ZipDownloader::new(&game.offer_id, &url.url(), &game.path).await?;
+        let mut downloader = open_source(
+            content_service,
+            &game.offer_id,
+            &game.build_id,
+            source,
+            &game.path,
+        )
+        .await?;
+        downloader.set_limiter(limiter);
+        downloader.set_retry_policy(retry);
+        downloader.set_preallocate(preallocate);
+        downloader.set_url_refresher(Arc::new(ServiceUrlRefresher::new(
+            content_service.clone(),
+            &game.offer_id,
+            &game.build_id,
+        )));
 
-        let mut entries = Vec::new();
-        for ele in downloader.manifest().entries() {
-            // TODO: Filtering
-            entries.push(ele.clone());
+        let all_entries = downloader.manifest().entries();
+        let rules = build_rules(&downloader).await;
+        let entries = game.filter.apply(&rules, all_entries);
+        if !game.filter.is_empty() {
+            info!(
+                "Filtered install of {}: {}/{} entries",
+                game.offer_id,
+                entries.len(),
+                all_entries.len()
+            );
         }
 
-        let total_count = entries.len();
-        let total_bytes = entries
-            .iter()
-            .map(|x| *x.compressed_size() as usize)
-            .sum::<usize>()
-            + 1; // Add 1 to account for running touchup at the end. Bad solution, but we're a bit rushed
+        codec::check_supported(&entries)?;
+
+        let progress = ProgressTracker::default();
+        progress.set_totals(entries.len(), compressed_size(&entries));
 
         Ok(GameDownloader {
             offer_id: game.offer_id.to_owned(),
-
-            downloader: Arc::new(downloader),
-            entries,
-            cancel_token: CancellationToken::new(),
-            completed_bytes: Arc::new(AtomicUsize::new(0)),
-            total_count,
-            total_bytes,
-            notify: Arc::new(Notify::new()),
+            job: DownloadJob {
+                downloader: Arc::new(downloader),
+                entries,
+                mode: game.mode,
+                previous,
+                repair_entries: game.repair_entries.iter().cloned().collect(),
+                concurrency: game.concurrency.unwrap_or(concurrency),
+                side_by_side: game.side_by_side,
+                store: None,
+                dedup: DedupMode::Off,
+                launchable: Arc::new(AtomicBool::new(false)),
+                locale: game.game_locale(),
+                cancel_token: CancellationToken::new(),
+                progress: Arc::new(progress),
+                notify: Arc::new(Notify::new()),
+            },
         })
     }
 
+    /// Links entries `store` has a copy of instead of downloading them, and adds the
+    /// installed files to it afterwards
+    pub fn set_content_store(&mut self, store: Arc<ContentStore>, mode: DedupMode) {
+        // Stored files are matched by CRC, which unverifiable builds don't have
+        if !self.job.downloader.manifest().verifiable() {
+            return;
+        }
+
+        self.job.store = Some(store);
+        self.job.dedup = mode;
+    }
+
     pub fn download(&self) {
-        let (downloader_arc, entries, cancel_token, completed_bytes, notify) =
-            self.prepare_download_vars();
-        let total_count = self.total_count;
+        let job = self.job.clone();
         tokio::spawn(async move {
-            let dl = GameDownloader::start_downloads(
-                total_count,
-                downloader_arc,
-                entries,
-                cancel_token,
-                completed_bytes,
-                notify,
-            )
-            .await;
+            let progress = job.progress.clone();
+            let dl = job.run().await;
             if let Err(err) = dl {
-                error!("Error when downloading!: `{:?}", err)
+                error!("Error when downloading!: `{:?}", err);
+                progress.fail(err.to_string());
             }
         });
     }
 
-    fn prepare_download_vars(
-        &self,
-    ) -> (
-        Arc<ZipDownloader>,
-        Vec<ZipFileEntry>,
-        CancellationToken,
-        Arc<AtomicUsize>,
-        Arc<Notify>,
-    ) {
-        (
-            self.downloader.clone(),
-            self.entries.clone(),
-            self.cancel_token.clone(),
-            self.completed_bytes.clone(),
-            self.notify.clone(),
-        )
+    pub fn cancel(&self) {
+        info!("Pausing installation of {}", self.offer_id);
+        self.job.cancel_token.cancel();
+    }
+
+    pub async fn wait(&self) {
+        self.job.notify.notified().await;
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.phase() == DownloadPhase::Finished
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.phase() == DownloadPhase::Failed
+    }
+
+    /// Why the download failed, if it did
+    pub fn error(&self) -> Option<String> {
+        self.job.progress.error()
+    }
+
+    pub fn phase(&self) -> DownloadPhase {
+        self.job.progress.phase()
     }
 
-    async fn start_downloads(
-        total_count: usize,
-        downloader_arc: Arc<ZipDownloader>,
+    pub fn progress(&self) -> DownloadProgress {
+        self.job.progress.snapshot(&self.offer_id)
+    }
+
+    pub fn percentage_done(&self) -> f64 {
+        self.progress().percentage_done()
+    }
+
+    /// Whether the game can be launched before the download finishes
+    pub fn launchable(&self) -> bool {
+        self.job.launchable.load(Ordering::SeqCst)
+    }
+
+    pub fn bytes_downloaded(&self) -> usize {
+        self.job.progress.bytes_downloaded()
+    }
+
+    pub fn bytes_total(&self) -> usize {
+        self.job.progress.bytes_total()
+    }
+
+    pub fn offer_id(&self) -> &String {
+        &self.offer_id
+    }
+
+    /// Disk space still needed to finish the download, not counting touchup
+    pub async fn required_space(&self) -> Result<u64, DownloaderError> {
+        let mut preallocated = HashSet::new();
+        for entry in &self.job.entries {
+            if self.job.downloader.is_preallocated(entry).await? {
+                preallocated.insert(entry.name().to_owned());
+            }
+        }
+
+        let root = self.job.downloader.path().to_owned();
+        let entries = self.job.entries.clone();
+        Ok(tokio::task::spawn_blocking(move || {
+            space::required_space(&root, &entries, &preallocated)
+        })
+        .await
+        .map_err(std::io::Error::other)?)
+    }
+
+    /// Every entry the finished install will have, not only the ones being downloaded
+    pub fn entries(&self) -> &[ZipFileEntry] {
+        &self.job.entries
+    }
+
+    /// Deletes what the download left on disk. Fresh and side by side installs go entirely,
+    /// otherwise only files left partway through go, the rest are the installed build's.
+    async fn discard(&self) {
+        let job = &self.job;
+        let fresh = job.mode == InstallMode::Full && (job.previous.is_none() || job.side_by_side);
+
+        let previous: HashMap<&str, i64> = job
+            .previous
+            .iter()
+            .flat_map(|x| x.entries())
+            .map(|x| (x.name().as_str(), *x.size()))
+            .collect();
+
+        let mut files = Vec::new();
+        for entry in job.entries.iter().filter(|x| !x.name().ends_with('/')) {
+            let previous = previous.get(entry.name().as_str()).copied();
+            if fresh
+                || job
+                    .downloader
+                    .is_partial(entry, previous)
+                    .await
+                    .unwrap_or(false)
+            {
+                files.push(entry.name().to_owned());
+            }
+        }
+
+        let root = job.downloader.path();
+        diff::remove_files(root, &files).await;
+        if fresh {
+            // Only goes if nothing else was in it
+            let _ = fs::remove_dir(root).await;
+        }
+    }
+}
+
+fn progress_due(last: &mut Option<(Instant, DownloadPhase)>, phase: DownloadPhase) -> bool {
+    let due = match *last {
+        Some((at, last_phase)) => last_phase != phase || at.elapsed() >= PROGRESS_INTERVAL,
+        None => true,
+    };
+
+    if due {
+        *last = Some((Instant::now(), phase));
+    }
+    due
+}
+
+/// Marks `ele` as started, and clears what an earlier build left of it.
+/// `false` when it can't be downloaded.
+async fn start_entry(
+    downloader: &ZipDownloader,
+    ele: &ZipFileEntry,
+    mode: InstallMode,
+    progress: &ProgressTracker,
+) -> bool {
+    progress.file_started(ele.name());
+
+    if mode != InstallMode::Full {
+        if let Err(err) = diff::reset_entry(downloader.path(), ele).await {
+            error!("Failed to reset {}: {}", ele.name(), err);
+            progress.file_finished(ele.name(), true);
+            return false;
+        }
+    }
+
+    true
+}
+
+async fn fetch_entry(
+    downloader: &ZipDownloader,
+    ele: &ZipFileEntry,
+    progress: &Arc<ProgressTracker>,
+    abort_token: &CancellationToken,
+) {
+    let callback_progress = progress.clone();
+    tokio::select! {
+        result = downloader.download_single_file(ele, Some(Box::new(move |bytes| {
+            callback_progress.add_bytes(bytes);
+        }))) => {
+            if let Err(err) = &result {
+                error!("File download failed: {}", err);
+                if !err.is_transient() {
+                    abort_token.cancel();
+                }
+            }
+            progress.file_finished(ele.name(), result.is_err());
+        },
+        _ = abort_token.cancelled() => {
+            info!("Download of {} cancelled", ele.name());
+        },
+    }
+}
+
+/// Keeps adjusting `gate` to what `tuner` makes of each window, until aborted
+async fn tune(
+    mut tuner: AdaptiveTuner,
+    gate: Arc<ConcurrencyGate>,
+    downloader: Arc<ZipDownloader>,
+    progress: Arc<ProgressTracker>,
+) {
+    let mut bytes = progress.bytes_downloaded();
+    let mut failures = downloader.failure_count();
+
+    loop {
+        tokio::time::sleep(TUNE_INTERVAL).await;
+
+        let (new_bytes, new_failures) = (progress.bytes_downloaded(), downloader.failure_count());
+        let rate = new_bytes.saturating_sub(bytes) as f64 / TUNE_INTERVAL.as_secs_f64();
+        bytes = new_bytes;
+
+        let current = gate.limit();
+        let next = tuner.next(current, rate, new_failures.saturating_sub(failures));
+        failures = new_failures;
+
+        if next != current {
+            debug!(
+                "Downloading {} files at once, was {} at {:.2} MiB/s",
+                next,
+                current,
+                rate / (1024.0 * 1024.0)
+            );
+            gate.set_limit(next);
+        }
+    }
+}
+
+/// Files of the build recorded for `installed`
+fn installed_files(installed: &InstalledGame) -> Vec<String> {
+    installed
+        .entries()
+        .iter()
+        .map(|x| x.name().to_owned())
+        .collect()
+}
+
+/// Deletes the install at `root`. With `keep_user_files` only the build's own `files` go.
+async fn remove_install(
+    root: &Path,
+    files: Vec<String>,
+    keep_user_files: bool,
+) -> Result<(), ContentManagerError> {
+    if keep_user_files {
+        let files: Vec<String> = files.into_iter().filter(|x| !x.ends_with('/')).collect();
+        diff::remove_files(root, &files).await;
+        // Only goes if nothing was left behind
+        let _ = fs::remove_dir(root).await;
+    } else if let Err(err) = fs::remove_dir_all(root).await {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            return Err(err.into());
+        }
+    }
+
+    ContentStore::forget(root).await
+}
+
+/// Rules for the build `downloader` installs, going by its manifest's locales when it has
+/// a DiP manifest listing them
+async fn build_rules(downloader: &ZipDownloader) -> InstallRules {
+    let entries = downloader.manifest().entries();
+    let rules = InstallRules::from_entries(entries);
+    let entry = match entries
+        .iter()
+        .find(|x| x.name().eq_ignore_ascii_case(MANIFEST_RELATIVE_PATH))
+    {
+        Some(entry) => entry,
+        None => return rules,
+    };
+
+    let manifest = match downloader
+        .read_zip_entry_bytes(entry, *entry.uncompressed_size() as u64)
+        .await
+    {
+        Ok(bytes) => DiPManifest::from_bytes(bytes.to_vec()),
+        Err(err) => {
+            warn!(
+                "Failed to read build manifest, going by file names: {}",
+                err
+            );
+            return rules;
+        }
+    };
+
+    match manifest {
+        Ok(manifest) => rules.with_manifest(&manifest),
+        Err(err) => {
+            debug!("No DiP manifest, going by file names: {}", err);
+            rules
+        }
+    }
+}
+
+/// The central directory of a build on the CDN
+async fn cdn_manifest(
+    service: &ContentService,
+    offer_id: &str,
+    build_id: &str,
+) -> Result<ZipFile, DownloaderError> {
+    let url = service.download_url(offer_id, Some(build_id)).await?;
+    Ok(ZipFile::fetch(url.url()).await?)
+}
+
+/// Reads the build at `source`. Extracted builds get their CRCs from the same build on the
+/// CDN, and can't be verified when it can't be reached.
+async fn open_source(
+    service: &ContentService,
+    offer_id: &str,
+    build_id: &str,
+    source: BuildSource,
+    path: &Path,
+) -> Result<ZipDownloader, DownloaderError> {
+    let build = match &source {
+        BuildSource::Directory(_) => match cdn_manifest(service, offer_id, build_id).await {
+            Ok(build) => Some(build),
+            Err(err) => {
+                warn!(
+                    "Failed to read build {} of {} from the CDN, its files can't be verified: {}",
+                    build_id, offer_id, err
+                );
+                None
+            }
+        },
+        _ => None,
+    };
+
+    ZipDownloader::from_source(offer_id, source, path, build.as_ref()).await
+}
+
+/// `path` with symlinks and `..` resolved, as given if it doesn't exist yet
+async fn canonical_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path)
+        .await
+        .unwrap_or_else(|_| path.to_owned())
+}
+
+fn compressed_size(entries: &[ZipFileEntry]) -> usize {
+    entries.iter().map(|x| *x.compressed_size() as usize).sum()
+}
+
+impl DownloadJob {
+    /// Entries that actually need downloading
+    async fn pending_entries(&self) -> Result<Vec<ZipFileEntry>, DownloaderError> {
+        let pending: Vec<ZipFileEntry> = match self.mode {
+            InstallMode::Full => return Ok(self.entries.clone()),
+            InstallMode::Update => {
+                self.progress.set_phase(DownloadPhase::Verifying);
+                self.changed_entries().await?
+            }
+            InstallMode::Repair => self
+                .entries
+                .iter()
+                .filter(|x| self.repair_entries.contains(x.name()))
+                .cloned()
+                .collect(),
+        };
+
+        self.progress
+            .set_totals(pending.len(), compressed_size(&pending));
+        Ok(pending)
+    }
+
+    /// Updates skip anything already on disk, unless the installed build opted out
+    /// of differential updates
+    async fn changed_entries(&self) -> Result<Vec<ZipFileEntry>, DownloaderError> {
+        let path = self.downloader.path();
+        let differential = match manifest::read_installed(path).await {
+            Ok(manifest) => manifest.differential_update(),
+            Err(err) => {
+                warn!("Failed to read installed manifest, comparing all files: {}", err);
+                true
+            }
+        };
+
+        if !differential {
+            info!("Installed build disables differential updates, downloading everything");
+            return Ok(self.entries.clone());
+        }
+
+        if !self.downloader.manifest().verifiable() {
+            info!("The build has no checksums to compare installed files to, copying everything");
+            return Ok(self.entries.clone());
+        }
+
+        let changed = diff::changed_entries(path, &self.entries, self.previous.as_ref()).await?;
+        info!(
+            "{}/{} entries changed since the installed build",
+            changed.len(),
+            self.entries.len()
+        );
+        Ok(changed)
+    }
+
+    /// Links the entries the content store has, returning the ones left to download
+    async fn link_stored(
+        &self,
+        store: &ContentStore,
         entries: Vec<ZipFileEntry>,
-        cancel_token: CancellationToken,
-        completed_bytes: Arc<AtomicUsize>,
-        notify: Arc<Notify>,
-    ) -> Result<(), DownloaderError> {
-        let mut handles = Vec::with_capacity(total_count);
+    ) -> Vec<ZipFileEntry> {
+        let root = self.downloader.path();
+        let mode = self.mode;
+        let linked = futures::stream::iter(entries)
+            .map(|entry| async move {
+                if mode != InstallMode::Full {
+                    if let Err(err) = diff::reset_entry(root, &entry).await {
+                        return (entry, Err(err.into()));
+                    }
+                }
 
-        for i in 0..total_count {
-            let downloader = downloader_arc.clone();
-            let ele = entries[i].clone();
+                let linked = store
+                    .link(&entry, &root.join(entry.name()), self.dedup)
+                    .await;
+                (entry, linked)
+            })
+            .buffer_unordered(LINK_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await;
 
-            let cancel_token = cancel_token.clone();
-            let completed_bytes = completed_bytes.clone();
+        let mut pending = Vec::new();
+        for (entry, linked) in linked {
+            match linked {
+                Ok(true) => {
+                    self.progress.file_started(entry.name());
+                    self.progress.add_bytes(*entry.compressed_size() as usize);
+                    self.progress.file_finished(entry.name(), false);
+                }
+                Ok(false) => pending.push(entry),
+                Err(err) => {
+                    warn!("Failed to link {}, downloading it: {}", entry.name(), err);
+                    pending.push(entry);
+                }
+            }
+        }
+
+        pending
+    }
+
+    /// Downloads `entries`. Returns false when the job was cancelled. Finished files are
+    /// kept for the next run. Stored and deflated ones left partway through continue from
+    /// their last checkpoint, other compression methods start them over.
+    async fn download_entries(&self, entries: Vec<ZipFileEntry>) -> Result<bool, DownloaderError> {
+        let mut handles = Vec::with_capacity(entries.len());
+
+        // Cancelled on errors retrying can't fix, without pausing the whole job
+        let abort_token = self.cancel_token.child_token();
+
+        let gate = Arc::new(ConcurrencyGate::new(self.concurrency.initial()));
+        let tuning = self.concurrency.tuner().map(|tuner| {
+            tokio::spawn(tune(
+                tuner,
+                gate.clone(),
+                self.downloader.clone(),
+                self.progress.clone(),
+            ))
+        });
+
+        for unit in self.downloader.plan_downloads(entries) {
+            let downloader = self.downloader.clone();
+            let mode = self.mode;
+
+            let abort_token = abort_token.clone();
+            let progress = self.progress.clone();
+            let gate = gate.clone();
 
             handles.push(async move {
-                if ele.name().contains("Cleanup") {
-                    info!("Ele: {:?}", ele);
+                let _permit = gate.acquire().await;
+
+                let mut started = Vec::with_capacity(unit.entries().len());
+                for ele in unit.entries() {
+                    if start_entry(&downloader, ele, mode, &progress).await {
+                        started.push(ele.clone());
+                    }
                 }
 
-                tokio::select! {
-                    result = downloader.download_single_file(&ele, Some(Box::new(move |bytes| {
-                        completed_bytes.fetch_add(bytes, Ordering::SeqCst);
-                    }))) => {
-                        if let Err(err) = result {
-                            error!("File download failed: {}", err);
+                let leftover = match unit {
+                    DownloadUnit::Single(_) => started,
+                    DownloadUnit::Batch(_) => {
+                        let callback_progress = progress.clone();
+                        let callback: BytesDownloadedCallback = Box::new(move |bytes| {
+                            callback_progress.add_bytes(bytes);
+                        });
+
+                        tokio::select! {
+                            leftover = downloader.download_batch(&started, Some(callback)) => {
+                                let names: HashSet<&String> =
+                                    leftover.iter().map(|x| x.name()).collect();
+                                for ele in started.iter().filter(|x| !names.contains(x.name())) {
+                                    progress.file_finished(ele.name(), false);
+                                }
+                                leftover
+                            },
+                            _ = abort_token.cancelled() => {
+                                info!("Download of {} batched entries cancelled", started.len());
+                                return;
+                            },
                         }
-                    },
-                    _ = cancel_token.cancelled() => {
-                        info!("Download of {} cancelled", ele.name());
-                    },
+                    }
+                };
+
+                for ele in leftover {
+                    fetch_entry(&downloader, &ele, &progress, &abort_token).await;
                 }
             });
         }
 
         let _results = futures::stream::iter(handles)
-            .buffer_unordered(16)
+            .buffer_unordered(self.concurrency.max())
             .collect::<Vec<_>>()
             .await;
 
-        let path = downloader_arc.path();
-
-        info!("Files downloaded, running touchup...");
-        let manifest = manifest::read(path.join(MANIFEST_RELATIVE_PATH)).await?;
+        if let Some(tuning) = tuning {
+            tuning.abort();
+        }
 
-        manifest.run_touchup(path).await?;
-        info!("Installation finished!");
+        if self.cancel_token.is_cancelled() {
+            return Ok(false);
+        }
 
-        completed_bytes.fetch_add(1, Ordering::SeqCst);
+        // Touchup on a half installed game won't do any good
+        let failed = self.progress.failed_count();
+        if failed > 0 {
+            return Err(DownloaderError::FailedEntries(failed));
+        }
 
-        notify.notify_one();
-        Ok(())
+        Ok(true)
     }
 
-    pub fn cancel(&self) {
-        info!("Pausing installation of {}", self.offer_id);
-        self.cancel_token.cancel();
+    /// Fresh installs of the active build can be played before they finish
+    fn progressive(&self) -> bool {
+        self.mode == InstallMode::Full && !self.side_by_side
     }
 
-    pub async fn wait(&self) {
-        self.notify.notified().await;
-    }
+    /// Downloads what the game needs to start and runs touchup on it, so it can be launched
+    /// while the rest downloads. Returns the entries left, `None` when cancelled.
+    async fn install_launch_set(
+        &self,
+        entries: Vec<ZipFileEntry>,
+    ) -> Result<Option<Vec<ZipFileEntry>>, DownloaderError> {
+        let path = self.downloader.path();
+        let (installer, entries): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .partition(progressive::is_installer_entry);
+        if !self.download_entries(installer).await? {
+            return Ok(None);
+        }
 
-    pub fn is_done(&self) -> bool {
-        self.completed_bytes.load(Ordering::SeqCst) == self.total_bytes
-    }
+        let manifest = match manifest::read_installed(path).await {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                warn!("Failed to read manifest, downloading in order: {}", err);
+                return Ok(Some(entries));
+            }
+        };
 
-    pub fn percentage_done(&self) -> f64 {
-        let completed = self.completed_bytes.load(Ordering::SeqCst);
-        (completed as f64 / self.total_bytes as f64) * 100.0
-    }
+        let launchers = progressive::launchers(manifest.as_ref());
+        if launchers.is_empty() {
+            info!("Manifest has no launcher, the game can be played once it's done");
+            return Ok(Some(entries));
+        }
 
-    pub fn bytes_downloaded(&self) -> usize {
-        self.completed_bytes.load(Ordering::SeqCst)
-    }
+        let (launch, rest) = progressive::split_launch_entries(&launchers, entries);
+        info!(
+            "Downloading {} entries needed to launch first",
+            launch.len()
+        );
+        if !self.download_entries(launch).await? {
+            return Ok(None);
+        }
 
-    pub fn bytes_total(&self) -> usize {
-        self.total_bytes
+        info!("Game can be launched, running touchup while the rest downloads...");
+        self.progress.set_phase(DownloadPhase::Touchup);
+        manifest.run_touchup(path, &self.locale).await?;
+        self.launchable.store(true, Ordering::SeqCst);
+
+        self.progress.set_phase(DownloadPhase::Downloading);
+        Ok(Some(rest))
     }
 
-    pub fn offer_id(&self) -> &String {
-        &self.offer_id
+    async fn run(self) -> Result<(), DownloaderError> {
+        let entries = self.pending_entries().await?;
+        let installing = entries.clone();
+        let entries = match &self.store {
+            Some(store) => {
+                let pending = self.link_stored(store, entries).await;
+                info!(
+                    "Linked {} files from other installs",
+                    installing.len() - pending.len()
+                );
+                pending
+            }
+            None => entries,
+        };
+
+        self.progress.set_phase(DownloadPhase::Downloading);
+        let rest = if self.progressive() {
+            match self.install_launch_set(entries.clone()).await? {
+                Some(rest) => rest,
+                None => return Ok(()),
+            }
+        } else {
+            entries.clone()
+        };
+
+        if !self.download_entries(rest).await? {
+            return Ok(());
+        }
+
+        let path = self.downloader.path();
+
+        if let Some(store) = &self.store {
+            // Hashes what was downloaded once more, linked files went in with the hash
+            // they were checked against
+            self.progress.set_phase(DownloadPhase::Verifying);
+            store.add(path, &entries).await;
+            if let Err(err) = store.save().await {
+                warn!("Failed to save the content store: {}", err);
+            }
+        }
+
+        if self.mode == InstallMode::Update {
+            if let Some(previous) = &self.previous {
+                let removed = diff::removed_files(previous, &self.entries);
+                info!("Removing {} files dropped by the new build", removed.len());
+                diff::remove_files(path, &removed).await;
+            }
+        }
+
+        if self.side_by_side {
+            // Touchup points the registry at the install, that waits until it's activated
+            info!("Build installed side by side, it's used once activated");
+        } else if self.launchable.load(Ordering::SeqCst) {
+            info!("Installation finished!");
+        } else {
+            info!("Files downloaded, running touchup...");
+            self.progress.set_phase(DownloadPhase::Touchup);
+            let manifest = manifest::read_installed(path).await?;
+
+            manifest.run_touchup(path, &self.locale).await?;
+            info!("Installation finished!");
+        }
+
+        self.progress.set_phase(DownloadPhase::Finished);
+        self.notify.notify_one();
+        Ok(())
     }
 }
 
@@ -304,6 +1077,26 @@ pub struct ContentManager {
     queue: DownloadQueue,
     service: ContentService,
     current: Option<GameDownloader>,
+    settings: DownloadSettings,
+    limiter: Arc<RateLimiter>,
+    /// Set while [`DownloadSettings::schedule`] keeps the queue from running
+    held: Option<HoldReason>,
+
+    /// Set when the queue was loaded with `resume`, picks the download back up on the first update
+    #[getter(skip)]
+    resume_pending: bool,
+    #[getter(skip)]
+    events: Vec<MaximaEvent>,
+    /// When progress was last reported, and in which phase
+    #[getter(skip)]
+    last_progress: Option<(Instant, DownloadPhase)>,
+    #[getter(skip)]
+    moving: Option<InstallMove>,
+    #[getter(skip)]
+    last_move_progress: Option<(Instant, DownloadPhase)>,
+    /// The game launched through Maxima, as of the last update
+    #[getter(skip)]
+    playing: Option<String>,
 }
 
 impl ContentManager {
@@ -313,74 +1106,1028 @@ impl ContentManager {
             queue.queued.clear();
             queue.save().await?;
         }
+
+        let settings = DownloadSettings::load().await?;
+        let limiter = Arc::new(RateLimiter::new(settings.max_bytes_per_second));
+
         Ok(Self {
             queue,
             service: ContentService::new(auth),
             current: None,
+            settings,
+            limiter,
+            held: None,
+            resume_pending: resume,
+            events: Vec::new(),
+            last_progress: None,
+            moving: None,
+            last_move_progress: None,
+            playing: None,
         })
     }
 
-    pub async fn add_install(&mut self, game: QueuedGame) -> Result<(), ContentManagerError> {
-        if self.queue.queued.is_empty() && self.queue.current == None && self.current.is_none() {
-            self.install_now(game).await?;
-        } else {
-            self.queue.queued.push(game);
-            self.queue.save().await?;
+    /// Caps the download speed of every game, in bytes per second. `None` removes the cap
+    pub async fn set_bandwidth_limit(
+        &mut self,
+        limit: Option<u64>,
+    ) -> Result<(), ContentManagerError> {
+        self.settings.max_bytes_per_second = limit;
+        self.settings.save().await?;
+        self.limiter.set_limit(limit);
+        Ok(())
+    }
+
+    /// How often and how patiently failed files are downloaded again
+    pub async fn set_retry_policy(&mut self, retry: RetryPolicy) -> Result<(), ContentManagerError> {
+        self.settings.retry = retry;
+        self.settings.save().await
+    }
+
+    /// Sizes files up front before writing them, which keeps them in one piece on HDDs
+    pub async fn set_preallocate(&mut self, preallocate: bool) -> Result<(), ContentManagerError> {
+        self.settings.preallocate = preallocate;
+        self.settings.save().await
+    }
+
+    /// Whether new installs link files other installs already have, see [`DedupMode`].
+    /// Takes effect from the next download on.
+    pub async fn set_dedup(&mut self, mode: DedupMode) -> Result<(), ContentManagerError> {
+        self.settings.dedup = mode;
+        self.settings.save().await
+    }
+
+    /// How many files are downloaded at once, for games queued without their own setting.
+    /// Takes effect from the next download on.
+    pub async fn set_concurrency(
+        &mut self,
+        concurrency: Concurrency,
+    ) -> Result<(), ContentManagerError> {
+        self.settings.concurrency = concurrency;
+        self.settings.save().await
+    }
+
+    /// When the queue may run by itself. Checked on every update, so a download that's
+    /// running outside of the new window stops right away.
+    pub async fn set_schedule(
+        &mut self,
+        schedule: DownloadSchedule,
+    ) -> Result<(), ContentManagerError> {
+        self.settings.schedule = schedule;
+        self.settings.save().await
+    }
+
+    pub async fn add_install(&mut self, mut game: QueuedGame) -> Result<(), ContentManagerError> {
+        game.include_locale();
+        if !self.queue.paused
+            && self.held.is_none()
+            && self.queue.queued.is_empty()
+            && self.queue.current == None
+            && self.current.is_none()
+        {
+            return self.install_now(game).await;
         }
 
-        Ok(())
+        self.queue.queued.push(game);
+        self.queue_changed().await
     }
 
-    pub async fn install_now(&mut self, game: QueuedGame) -> Result<(), ContentManagerError> {
+    pub async fn install_now(&mut self, mut game: QueuedGame) -> Result<(), ContentManagerError> {
+        game.include_locale();
         if let Some(current) = &self.current {
             current.cancel();
             self.current = None;
         }
 
-        if let Some(current) = &self.queue.current {
-            if current == &game {
-                self.install_direct(game).await?;
-                return Ok(());
+        self.queue.paused = false;
+        self.queue.queued.retain(|x| x.offer_id != game.offer_id);
+
+        if let Some(current) = self.queue.current.take() {
+            if current.offer_id != game.offer_id {
+                // Whatever got interrupted is next in line
+                self.queue.queued.insert(0, current);
             }
+        }
 
-            self.queue.queued.push(current.clone());
+        if self.held.is_some() {
+            // Goes first once the schedule allows it
+            self.queue.queued.insert(0, game);
+            return self.queue_changed().await;
         }
 
         self.install_direct(game).await?;
+        self.queue_changed().await
+    }
+
+    /// Stops the current download. It stays at the front of the queue and continues on
+    /// [`ContentManager::resume`], even after a restart. Files that were partway through
+    /// continue from their last checkpoint, up to a few MiB back, if their compression
+    /// allows it. The rest start over.
+    pub async fn pause(&mut self) -> Result<(), ContentManagerError> {
+        if let Some(current) = self.current.take() {
+            current.cancel();
+        }
+
+        self.queue.paused = true;
+        self.queue_changed().await
+    }
+
+    pub async fn resume(&mut self) -> Result<(), ContentManagerError> {
+        self.queue.paused = false;
+        self.start_next().await?;
+        self.queue_changed().await
+    }
+
+    /// Drops a game from the queue. If it's the current one its download is cancelled, and
+    /// what it left on disk is deleted.
+    pub async fn remove(&mut self, offer_id: &str) -> Result<(), ContentManagerError> {
+        let is_current = self
+            .queue
+            .current
+            .as_ref()
+            .is_some_and(|x| x.offer_id == offer_id);
+
+        if is_current {
+            let current = match (self.current.take(), &self.queue.current) {
+                (Some(current), _) => {
+                    current.cancel();
+                    Some(current)
+                }
+                // Paused, the build's file list has to be fetched again
+                (None, Some(game)) => match self.open_download(game).await {
+                    Ok(downloader) => Some(downloader),
+                    Err(err) => {
+                        warn!("Failed to clean up the download of {}: {}", offer_id, err);
+                        None
+                    }
+                },
+                (None, None) => None,
+            };
+
+            if let Some(current) = current {
+                current.discard().await;
+            }
+
+            self.queue.current = None;
+            if let Err(err) = downloader::clear_state(offer_id).await {
+                warn!("Failed to clear download state of {}: {}", offer_id, err);
+            }
+
+            self.start_next().await?;
+        } else {
+            self.queue.queued.retain(|x| x.offer_id != offer_id);
+        }
+
+        self.queue_changed().await
+    }
+
+    /// Makes a queued game the next one to download
+    pub async fn move_to_front(&mut self, offer_id: &str) -> Result<(), ContentManagerError> {
+        if let Some(idx) = self.queue.queued.iter().position(|x| x.offer_id == offer_id) {
+            let game = self.queue.queued.remove(idx);
+            self.queue.queued.insert(0, game);
+        }
+
+        self.queue_changed().await
+    }
+
+    /// Sorts the queued games by `order`. Games not in it keep their relative order at the end
+    pub async fn reorder(&mut self, order: &[String]) -> Result<(), ContentManagerError> {
+        self.queue.queued.sort_by_key(|x| {
+            order
+                .iter()
+                .position(|id| id == &x.offer_id)
+                .unwrap_or(usize::MAX)
+        });
+
+        self.queue_changed().await
+    }
+
+    /// Queues an update of an installed game to `build_id`, keeping its install path
+    /// and filter. Only changed entries get downloaded.
+    pub async fn add_update(
+        &mut self,
+        offer_id: &str,
+        build_id: &str,
+    ) -> Result<(), ContentManagerError> {
+        let installed = InstalledGame::load(offer_id)
+            .await?
+            .ok_or(ContentManagerError::NotInstalled(offer_id.to_owned()))?;
+
+        let game = QueuedGame {
+            offer_id: offer_id.to_owned(),
+            build_id: build_id.to_owned(),
+            path: installed.path().to_owned(),
+            filter: installed.filter().to_owned(),
+            mode: InstallMode::Update,
+            repair_entries: Vec::new(),
+            source: None,
+            concurrency: None,
+            side_by_side: false,
+            locale: installed.locale().clone(),
+        };
+
+        self.add_install(game).await
+    }
+
+    /// Switches an installed game to `locale`, e.g. `fr_FR`. If the install filter left the
+    /// language's files out they're queued for download, and touchup runs with the new
+    /// locale once they're in. Returns whether anything was queued.
+    pub async fn change_language(
+        &mut self,
+        offer_id: &str,
+        locale: &str,
+    ) -> Result<bool, ContentManagerError> {
+        if self
+            .moving
+            .as_ref()
+            .is_some_and(|x| x.offer_id() == offer_id)
+        {
+            return Err(ContentManagerError::MoveInProgress);
+        }
+
+        let queued = self
+            .queue
+            .current
+            .iter()
+            .chain(self.queue.queued.iter())
+            .any(|x| x.offer_id == offer_id);
+        if queued {
+            return Err(ContentManagerError::DownloadInProgress);
+        }
+
+        let mut installed = InstalledGame::load(offer_id)
+            .await?
+            .ok_or(ContentManagerError::NotInstalled(offer_id.to_owned()))?;
+
+        let manifest = manifest::read_installed(installed.path())
+            .await
+            .map_err(DownloaderError::from)?;
+        if !manifest.language_change_supported() {
+            return Err(ContentManagerError::LanguageChangeUnsupported(
+                offer_id.to_owned(),
+            ));
+        }
+
+        let locales = manifest.locales();
+        if !locales.is_empty() && !locales.iter().any(|x| x.eq_ignore_ascii_case(locale)) {
+            return Err(ContentManagerError::UnsupportedLocale(
+                offer_id.to_owned(),
+                locale.to_owned(),
+            ));
+        }
+
+        if !installed.filter().has_language(locale) {
+            info!("Downloading {} files of {}", locale, offer_id);
+            let game = QueuedGame {
+                offer_id: offer_id.to_owned(),
+                build_id: installed.build_id().to_owned(),
+                path: installed.path().to_owned(),
+                filter: installed.filter().to_owned(),
+                mode: InstallMode::Update,
+                repair_entries: Vec::new(),
+                source: installed.source().clone(),
+                concurrency: None,
+                side_by_side: false,
+                locale: Some(locale.to_owned()),
+            };
+
+            self.add_install(game).await?;
+            return Ok(true);
+        }
+
+        info!("Switching {} to {}", offer_id, locale);
+        manifest
+            .run_touchup(installed.path(), locale)
+            .await
+            .map_err(DownloaderError::from)?;
+
+        installed.set_locale(locale);
+        installed.save().await?;
+        Ok(false)
+    }
+
+    /// Checks an installed game against its build's central directory
+    pub async fn verify(&self, offer_id: &str) -> Result<VerifyReport, ContentManagerError> {
+        let installed = InstalledGame::load(offer_id)
+            .await?
+            .ok_or(ContentManagerError::NotInstalled(offer_id.to_owned()))?;
+
+        let entries = self.installed_entries(&installed).await?;
+        info!("Verifying {} entries of {}", entries.len(), offer_id);
+
+        Ok(verify::verify_install(offer_id, installed.path(), &entries).await?)
+    }
+
+    /// Verifies an installed game and queues anything missing or corrupt for download.
+    /// Touchup runs again once the download is done.
+    pub async fn repair(&mut self, offer_id: &str) -> Result<VerifyReport, ContentManagerError> {
+        let report = self.verify(offer_id).await?;
+        if report.is_intact() {
+            return Ok(report);
+        }
+
+        let installed = InstalledGame::load(offer_id)
+            .await?
+            .ok_or(ContentManagerError::NotInstalled(offer_id.to_owned()))?;
+
+        let game = QueuedGame {
+            offer_id: offer_id.to_owned(),
+            build_id: installed.build_id().to_owned(),
+            path: installed.path().to_owned(),
+            filter: installed.filter().to_owned(),
+            mode: InstallMode::Repair,
+            repair_entries: report.bad_entries(),
+            source: installed.source().clone(),
+            concurrency: None,
+            side_by_side: false,
+            locale: installed.locale().clone(),
+        };
+
+        self.add_install(game).await?;
+        Ok(report)
+    }
+
+    /// Deletes an installed game. With `keep_user_files` only the installed build's own files
+    /// go, leaving saves and anything else the user put there. `path` is used for games
+    /// installed before Maxima kept install records, those can only be removed entirely.
+    /// Registry keys the manifest lists go too.
+    pub async fn uninstall(
+        &mut self,
+        offer_id: &str,
+        path: Option<PathBuf>,
+        keep_user_files: bool,
+    ) -> Result<(), ContentManagerError> {
+        if self
+            .moving
+            .as_ref()
+            .is_some_and(|x| x.offer_id() == offer_id)
+        {
+            return Err(ContentManagerError::MoveInProgress);
+        }
+
+        let queued = self
+            .queue
+            .current
+            .iter()
+            .chain(self.queue.queued.iter())
+            .any(|x| x.offer_id == offer_id);
+        if queued {
+            self.remove(offer_id).await?;
+        }
+
+        let installed = InstalledGame::load(offer_id).await?;
+        let (root, files): (PathBuf, Vec<String>) = match installed {
+            Some(installed) if !installed.entries().is_empty() => {
+                (installed.path().to_owned(), installed_files(&installed))
+            }
+            installed => {
+                // Nothing recorded, so the build's files can't be told apart from the user's
+                if keep_user_files {
+                    return Err(ContentManagerError::NoInstallRecord(offer_id.to_owned()));
+                }
+
+                let root = installed
+                    .map(|x| x.path().to_owned())
+                    .or(path)
+                    .ok_or(ContentManagerError::NotInstalled(offer_id.to_owned()))?;
+                (root, Vec::new())
+            }
+        };
+
+        // Read before the manifest goes with the rest of the files
+        let uninstall_keys = match manifest::read_installed(&root).await {
+            Ok(manifest) => manifest.uninstall_keys(),
+            Err(err) => {
+                warn!("Failed to read the manifest of {}: {}", offer_id, err);
+                Vec::new()
+            }
+        };
+
+        info!("Uninstalling {} from {:?}", offer_id, root);
+        remove_install(&root, files, keep_user_files).await?;
+
+        if let Err(err) = remove_reg_keys(&uninstall_keys).await {
+            warn!("Failed to remove registry keys of {}: {}", offer_id, err);
+        }
+
+        let builds = InstalledBuilds::load(offer_id).await?;
+        for build in builds.inactive() {
+            info!(
+                "Uninstalling build {} of {} from {:?}",
+                build.build_id(),
+                offer_id,
+                build.path()
+            );
+            remove_install(build.path(), installed_files(build), keep_user_files).await?;
+        }
+
+        downloader::clear_state(offer_id).await?;
+        InstalledGame::delete(offer_id).await?;
+        InstalledBuilds::delete(offer_id).await?;
+
+        self.queue.completed.retain(|x| x.offer_id != offer_id);
+        self.queue.failed.retain(|x| x.offer_id != offer_id);
+        self.queue_changed().await
+    }
+
+    /// Queues `build_id` to be installed next to the active build instead of over it, at `path`
+    /// or [`side_by_side_path`]. Nothing changes for the game until the build is
+    /// activated with [`ContentManager::activate_build`].
+    pub async fn add_build(
+        &mut self,
+        offer_id: &str,
+        build_id: &str,
+        path: Option<PathBuf>,
+    ) -> Result<(), ContentManagerError> {
+        let installed = InstalledGame::load(offer_id)
+            .await?
+            .ok_or(ContentManagerError::NotInstalled(offer_id.to_owned()))?;
+
+        let builds = InstalledBuilds::load(offer_id).await?;
+        if installed.build_id() == build_id || builds.get(build_id).is_some() {
+            return Err(ContentManagerError::BuildInstalled(build_id.to_owned()));
+        }
+
+        let path = path.unwrap_or_else(|| side_by_side_path(installed.path(), build_id));
+        let game = QueuedGame {
+            offer_id: offer_id.to_owned(),
+            build_id: build_id.to_owned(),
+            path,
+            filter: installed.filter().to_owned(),
+            mode: InstallMode::Full,
+            repair_entries: Vec::new(),
+            source: None,
+            concurrency: None,
+            side_by_side: true,
+            locale: installed.locale().clone(),
+        };
+
+        self.add_install(game).await
+    }
+
+    /// Makes an installed build the one the game runs from. Touchup runs on it, which points
+    /// the registry's install path, and with it the execute path, at the build's folder.
+    /// The build that was active before stays installed for [`ContentManager::rollback`].
+    pub async fn activate_build(
+        &mut self,
+        offer_id: &str,
+        build_id: &str,
+    ) -> Result<(), ContentManagerError> {
+        self.check_not_busy(offer_id)?;
+
+        let active = InstalledGame::load(offer_id).await?;
+        if active.as_ref().is_some_and(|x| x.build_id() == build_id) {
+            return Ok(());
+        }
+
+        let mut builds = InstalledBuilds::load(offer_id).await?;
+        let build = builds.activate(build_id, active).ok_or_else(|| {
+            ContentManagerError::BuildNotInstalled(offer_id.to_owned(), build_id.to_owned())
+        })?;
+
+        info!(
+            "Activating build {} of {} at {:?}",
+            build_id,
+            offer_id,
+            build.path()
+        );
+        let manifest = manifest::read_installed(build.path())
+            .await
+            .map_err(DownloaderError::from)?;
+        manifest
+            .run_touchup(build.path(), &build.game_locale())
+            .await
+            .map_err(DownloaderError::from)?;
+
+        // Only recorded once touchup went through, the registry still points at the old build
+        // otherwise
+        build.save().await?;
+        builds.save().await?;
+        self.events
+            .push(MaximaEvent::BuildActivated(offer_id.to_owned()));
         Ok(())
     }
 
+    /// Activates the build that was active before the current one, returning its ID
+    pub async fn rollback(&mut self, offer_id: &str) -> Result<String, ContentManagerError> {
+        let previous = InstalledBuilds::load(offer_id)
+            .await?
+            .previous()
+            .clone()
+            .ok_or(ContentManagerError::NoPreviousBuild(offer_id.to_owned()))?;
+
+        self.activate_build(offer_id, &previous).await?;
+        Ok(previous)
+    }
+
+    /// Deletes a build installed next to the active one, along with its folder
+    pub async fn remove_build(
+        &mut self,
+        offer_id: &str,
+        build_id: &str,
+    ) -> Result<(), ContentManagerError> {
+        self.check_not_busy(offer_id)?;
+
+        let active = InstalledGame::load(offer_id).await?;
+        if active.is_some_and(|x| x.build_id() == build_id) {
+            return Err(ContentManagerError::BuildActive(build_id.to_owned()));
+        }
+
+        let mut builds = InstalledBuilds::load(offer_id).await?;
+        let build = builds.remove(build_id).ok_or_else(|| {
+            ContentManagerError::BuildNotInstalled(offer_id.to_owned(), build_id.to_owned())
+        })?;
+
+        info!(
+            "Removing build {} of {} from {:?}",
+            build_id,
+            offer_id,
+            build.path()
+        );
+        remove_install(build.path(), installed_files(&build), false).await?;
+        builds.save().await
+    }
+
+    /// Adds every installed build of a game to the content store, so new installs can link
+    /// its files. Installs made with deduplication on are added by themselves.
+    pub async fn index_installs(&self, offer_id: &str) -> Result<(), ContentManagerError> {
+        let mut installs: Vec<InstalledGame> =
+            InstalledGame::load(offer_id).await?.into_iter().collect();
+        installs.extend(
+            InstalledBuilds::load(offer_id)
+                .await?
+                .inactive()
+                .iter()
+                .cloned(),
+        );
+        if installs.is_empty() {
+            return Err(ContentManagerError::NotInstalled(offer_id.to_owned()));
+        }
+
+        let store = ContentStore::load().await?;
+        for installed in installs {
+            let entries = match self.installed_entries(&installed).await {
+                Err(ContentManagerError::Unverifiable(_)) => {
+                    warn!(
+                        "Build {} of {} has no checksums, leaving it out of the content store",
+                        installed.build_id(),
+                        offer_id
+                    );
+                    continue;
+                }
+                entries => entries?,
+            };
+            info!(
+                "Adding {} entries of build {} of {} to the content store",
+                entries.len(),
+                installed.build_id(),
+                offer_id
+            );
+            store.add(installed.path(), &entries).await;
+        }
+
+        store.save().await
+    }
+
+    /// Fails if `offer_id` is being downloaded or moved, its install records are in flux
+    fn check_not_busy(&self, offer_id: &str) -> Result<(), ContentManagerError> {
+        if self
+            .queue
+            .current
+            .as_ref()
+            .is_some_and(|x| x.offer_id == offer_id)
+        {
+            return Err(ContentManagerError::DownloadInProgress);
+        }
+
+        if self
+            .moving
+            .as_ref()
+            .is_some_and(|x| x.offer_id() == offer_id)
+        {
+            return Err(ContentManagerError::MoveInProgress);
+        }
+
+        Ok(())
+    }
+
+    /// Packs an installed game into a zip laid out like its build on the CDN, which can be
+    /// installed again with [`BuildSource::Archive`]. Files are checked against the build as
+    /// they're packed, so the archive holds exactly what was shipped.
+    pub async fn export(&self, offer_id: &str, out: &Path) -> Result<(), ContentManagerError> {
+        let installed = InstalledGame::load(offer_id)
+            .await?
+            .ok_or(ContentManagerError::NotInstalled(offer_id.to_owned()))?;
+
+        if !installed.filter().is_empty() {
+            warn!(
+                "{} was installed with a filter, only the installed languages and components are exported",
+                offer_id
+            );
+        }
+
+        let entries = self.installed_entries(&installed).await?;
+        if !entries.iter().any(|x| x.name() == MANIFEST_RELATIVE_PATH) {
+            return Err(ContentManagerError::NoManifest(offer_id.to_owned()));
+        }
+
+        let version = manifest::read(installed.path().join(MANIFEST_RELATIVE_PATH))
+            .await
+            .map_err(DownloaderError::from)?
+            .version();
+        let metadata = ArchiveMetadata::new(offer_id, installed.build_id(), version);
+
+        info!(
+            "Exporting {} entries of {} to {:?}",
+            entries.len(),
+            offer_id,
+            out
+        );
+
+        // Only a finished archive gets the real name
+        let mut partial = out.as_os_str().to_owned();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+
+        let root = installed.path().to_owned();
+        let target = partial.clone();
+        let written = tokio::task::spawn_blocking(move || {
+            export::write_archive(&root, &entries, &metadata, &target)
+        })
+        .await
+        .map_err(std::io::Error::other)?;
+
+        if let Err(err) = written {
+            // Half an archive is no use to anyone
+            let _ = fs::remove_file(&partial).await;
+            return Err(err.into());
+        }
+
+        fs::rename(&partial, out).await?;
+        Ok(())
+    }
+
+    /// Moves an installed game into the `library` folder, keeping the name of its own folder.
+    /// Runs in the background, reporting [`DownloadPhase::Moving`] progress, and runs touchup
+    /// again afterwards so the registry points at the new location. Refused while the game
+    /// is running through Maxima.
+    pub async fn move_install(
+        &mut self,
+        offer_id: &str,
+        library: &Path,
+    ) -> Result<(), ContentManagerError> {
+        if self.moving.is_some() {
+            return Err(ContentManagerError::MoveInProgress);
+        }
+
+        let queued = self
+            .queue
+            .current
+            .iter()
+            .chain(self.queue.queued.iter())
+            .any(|x| x.offer_id == offer_id);
+        if queued {
+            return Err(ContentManagerError::DownloadInProgress);
+        }
+
+        if self.playing.as_deref() == Some(offer_id) {
+            return Err(ContentManagerError::GameRunning(offer_id.to_owned()));
+        }
+
+        let installed = InstalledGame::load(offer_id)
+            .await?
+            .ok_or(ContentManagerError::NotInstalled(offer_id.to_owned()))?;
+
+        let name = installed
+            .path()
+            .file_name()
+            .map(|x| x.to_owned())
+            .unwrap_or_else(|| offer_id.into());
+        let to = library.join(name);
+        if to == *installed.path() {
+            return Ok(());
+        }
+
+        // A copy inside the original would go with it once the move is done, and the other
+        // way around the copy would overwrite what it copies from
+        let from = canonical_path(installed.path()).await;
+        let to_canonical = canonical_path(library)
+            .await
+            .join(to.file_name().unwrap_or_default());
+        if to_canonical.starts_with(&from) || from.starts_with(&to_canonical) {
+            return Err(ContentManagerError::NestedMove(
+                installed.path().to_owned(),
+                to,
+            ));
+        }
+
+        if to.exists() {
+            return Err(ContentManagerError::DestinationExists(to));
+        }
+
+        self.moving = Some(InstallMove::start(installed, to));
+        self.last_move_progress = None;
+        Ok(())
+    }
+
+    /// Entries of the installed build that the install filter kept
+    async fn installed_entries(
+        &self,
+        installed: &InstalledGame,
+    ) -> Result<Vec<ZipFileEntry>, ContentManagerError> {
+        let source = match installed.source() {
+            Some(source) => source.clone(),
+            None => {
+                let url = self
+                    .service
+                    .download_url(installed.offer_id(), Some(installed.build_id()))
+                    .await
+                    .map_err(DownloaderError::from)?;
+                BuildSource::Remote(url.url().to_owned())
+            }
+        };
+
+        let downloader = open_source(
+            &self.service,
+            installed.offer_id(),
+            installed.build_id(),
+            source,
+            installed.path(),
+        )
+        .await?;
+        if !downloader.manifest().verifiable() {
+            return Err(ContentManagerError::Unverifiable(
+                installed.offer_id().to_owned(),
+            ));
+        }
+
+        let entries = downloader.manifest().entries();
+        let rules = build_rules(&downloader).await;
+        Ok(installed.filter().apply(&rules, entries))
+    }
+
+    /// Languages and optional components a build can be filtered by
+    pub async fn install_rules(
+        &self,
+        offer_id: &str,
+        build_id: &str,
+    ) -> Result<InstallRules, ContentManagerError> {
+        let url = self
+            .service
+            .download_url(offer_id, Some(build_id))
+            .await
+            .map_err(DownloaderError::from)?;
+        // Only reads the build's manifest, nothing is written to the folder
+        let downloader = ZipDownloader::new(offer_id, url.url(), maxima_dir()?).await?;
+        Ok(build_rules(&downloader).await)
+    }
+
+    /// Progressive install state of a game, `None` unless it's being installed for the first time
+    pub fn progressive_status(&self, offer_id: &str) -> Option<ProgressiveStatus> {
+        let queued = self
+            .queue
+            .current
+            .iter()
+            .chain(self.queue.queued.iter())
+            .any(|x| x.offer_id == offer_id && x.mode == InstallMode::Full && !x.side_by_side);
+        if !queued {
+            return None;
+        }
+
+        match &self.current {
+            Some(current) if current.offer_id == offer_id && current.launchable() => {
+                Some(ProgressiveStatus::Launchable)
+            }
+            _ => Some(ProgressiveStatus::Pending),
+        }
+    }
+
+    /// Starts or resumes whatever is at the front of the queue, unless paused, held or busy
+    async fn start_next(&mut self) -> Result<(), ContentManagerError> {
+        if self.queue.paused || self.held.is_some() || self.current.is_some() {
+            return Ok(());
+        }
+
+        let game = match self.queue.current.clone() {
+            Some(game) => game,
+            None if !self.queue.queued.is_empty() => self.queue.queued.remove(0),
+            None => return Ok(()),
+        };
+
+        self.install_direct(game).await
+    }
+
+    /// Queues progress events for the current download and move, at most every
+    /// [`PROGRESS_INTERVAL`] unless the phase changed
+    fn report_progress(&mut self) {
+        if let Some(current) = &self.current {
+            if progress_due(&mut self.last_progress, current.phase()) {
+                self.events
+                    .push(MaximaEvent::DownloadProgress(current.progress()));
+            }
+        }
+
+        if let Some(moving) = &self.moving {
+            if progress_due(&mut self.last_move_progress, moving.phase()) {
+                self.events
+                    .push(MaximaEvent::DownloadProgress(moving.progress()));
+            }
+        }
+    }
+
+    /// Stops or continues the queue when the schedule's verdict changes. Held downloads stay
+    /// current and continue from their finished files and checkpoints, like after a pause.
+    async fn apply_schedule(&mut self, playing: Option<&str>) -> Result<(), ContentManagerError> {
+        // A game waiting on its own download shouldn't hold it up
+        let playing = playing.is_some_and(|offer_id| {
+            !self
+                .queue
+                .current
+                .as_ref()
+                .is_some_and(|x| x.offer_id == offer_id)
+        });
+        let held = self.settings.schedule.hold_reason(playing);
+        if held == self.held {
+            return Ok(());
+        }
+
+        self.held = held;
+        match held {
+            Some(reason) => {
+                info!("Holding download queue: {:?}", reason);
+                if let Some(current) = self.current.take() {
+                    current.cancel();
+                    self.last_progress = None;
+                }
+            }
+            None => {
+                info!("Download schedule allows the queue to run again");
+                self.start_next().await?;
+            }
+        }
+
+        self.queue_changed().await
+    }
+
+    async fn queue_changed(&mut self) -> Result<(), ContentManagerError> {
+        self.queue.save().await?;
+        self.events.push(MaximaEvent::DownloadQueueChanged);
+        Ok(())
+    }
+
+    /// Fetches the build `game` installs
+    async fn open_download(
+        &self,
+        game: &QueuedGame,
+    ) -> Result<GameDownloader, ContentManagerError> {
+        let previous = InstalledGame::load(&game.offer_id).await?;
+        if game.mode == InstallMode::Update && previous.is_none() {
+            // Files the new build dropped would stay behind
+            return Err(ContentManagerError::NoInstallRecord(
+                game.offer_id.to_owned(),
+            ));
+        }
+
+        Ok(GameDownloader::new(
+            &self.service,
+            game,
+            previous,
+            self.limiter.clone(),
+            self.settings.retry.clone(),
+            self.settings.preallocate,
+            self.settings.concurrency,
+        )
+        .await?)
+    }
+
+    /// Fetches the build and makes sure it fits on the target disk
+    async fn prepare_download(&self, game: &QueuedGame) -> Result<GameDownloader, ContentManagerError> {
+        let mut downloader = self.open_download(game).await?;
+        if self.settings.dedup != DedupMode::Off {
+            let store = ContentStore::load().await?;
+            downloader.set_content_store(Arc::new(store), self.settings.dedup);
+        }
+
+        let required = downloader.required_space().await? + TOUCHUP_MARGIN;
+        let available = available_space(&game.path)?;
+        if required > available {
+            return Err(ContentManagerError::InsufficientSpace {
+                required,
+                available,
+            });
+        }
+
+        Ok(downloader)
+    }
+
     async fn install_direct(&mut self, game: QueuedGame) -> Result<(), ContentManagerError> {
         if self.current.is_some() {
             return Err(ContentManagerError::DownloadInProgress);
         }
 
+        self.queue.failed.retain(|x| x.offer_id != game.offer_id);
         self.queue.current = Some(game.clone());
         self.queue.save().await?;
 
-        let downloader = GameDownloader::new(&self.service, &game).await?;
+        let downloader = match self.prepare_download(&game).await {
+            Ok(downloader) => downloader,
+            Err(err) => {
+                self.queue.current = None;
+                self.queue.failed.push(game.clone());
+                self.events.push(MaximaEvent::InstallFailed(
+                    game.offer_id.to_owned(),
+                    err.to_string(),
+                ));
+                self.queue_changed().await?;
+                return Err(err);
+            }
+        };
+
         downloader.download();
         self.current = Some(downloader);
+        self.last_progress = None;
         Ok(())
     }
 
-    pub(crate) async fn update(&mut self) -> Result<Option<MaximaEvent>, ContentManagerError> {
-        let mut event = None;
+    /// `playing` is the offer of the game launched through Maxima, if one is running
+    pub(crate) async fn update(
+        &mut self,
+        playing: Option<&str>,
+    ) -> Result<Vec<MaximaEvent>, ContentManagerError> {
+        self.playing = playing.map(str::to_owned);
+        self.apply_schedule(playing).await?;
 
-        if let Some(current) = &self.current {
-            if current.is_done() {
-                event = Some(MaximaEvent::InstallFinished(current.offer_id.to_owned()));
-                self.current = None;
-                self.queue.current = None;
+        if self.resume_pending {
+            self.resume_pending = false;
+            if self.queue.current.is_some() || !self.queue.queued.is_empty() {
+                info!("Resuming download queue");
+                self.start_next().await?;
+                self.queue_changed().await?;
+            }
+        }
+
+        self.report_progress();
+
+        let move_phase = self.moving.as_ref().map(|x| x.phase());
+        if matches!(
+            move_phase,
+            Some(DownloadPhase::Finished | DownloadPhase::Failed)
+        ) {
+            self.last_move_progress = None;
+            let moving = self.moving.take().unwrap();
+            let offer_id = moving.offer_id().to_owned();
+            match moving.error() {
+                Some(reason) => self
+                    .events
+                    .push(MaximaEvent::InstallMoveFailed(offer_id, reason)),
+                None => self.events.push(MaximaEvent::InstallMoved(offer_id)),
+            }
+        }
+
+        if self.current.as_ref().is_some_and(|x| x.is_done()) {
+            self.last_progress = None;
+            let current = self.current.take().unwrap();
+            self.events
+                .push(MaximaEvent::InstallFinished(current.offer_id.to_owned()));
 
-                if let Some(game) = self.queue.queued.pop() {
-                    self.install_now(game).await?;
+            if let Some(game) = self.queue.current.take() {
+                let installed = InstalledGame::from_queued(&game, current.entries());
+                if game.side_by_side {
+                    let mut builds = InstalledBuilds::load(&game.offer_id).await?;
+                    builds.add(installed);
+                    builds.save().await?;
+                } else {
+                    installed.save().await?;
                 }
+                self.queue.completed.push(game);
+            }
+
+            if let Err(err) = self.start_next().await {
+                error!("Failed to start next download: {}", err);
+            }
+
+            self.queue_changed().await?;
+        } else if self.current.as_ref().is_some_and(|x| x.is_failed()) {
+            self.last_progress = None;
+            let current = self.current.take().unwrap();
+            let reason = current.error().unwrap_or_default();
+            error!("Installation of {} failed: {}", current.offer_id, reason);
+            self.events.push(MaximaEvent::InstallFailed(
+                current.offer_id.to_owned(),
+                reason,
+            ));
 
-                self.queue.save().await?;
+            if let Some(game) = self.queue.current.take() {
+                self.queue.failed.push(game);
             }
+
+            if let Err(err) = self.start_next().await {
+                error!("Failed to start next download: {}", err);
+            }
+
+            self.queue_changed().await?;
         }
 
-        Ok(event)
+        Ok(std::mem::take(&mut self.events))
     }
 }
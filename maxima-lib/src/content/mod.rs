@@ -11,11 +11,31 @@ use crate::core::{
     },
 };
 
+pub mod batch;
+pub mod builds;
+pub mod cdn;
+pub mod codec;
+pub mod concurrency;
+pub mod dedup;
+pub mod diff;
 pub mod downloader;
+pub mod export;
+pub mod filter;
+pub mod installed;
 pub mod manager;
+pub mod progress;
+pub mod progressive;
+pub mod relocate;
+pub mod retry;
+pub mod schedule;
+pub mod settings;
+pub mod space;
+pub mod throttle;
+pub mod verify;
 pub mod zip;
 pub mod zlib;
 
+#[derive(Clone)]
 pub struct ContentService {
     service_layer: ServiceLayerClient,
     request_cache: DynamicCache<String>,
@@ -66,7 +86,7 @@ impl ContentService {
         offer_id: &str,
         build_id: Option<&str>,
     ) -> Result<ServiceDownloadUrlMetadata, ServiceLayerError> {
-        let cache_key = "download_url_".to_owned() + offer_id + "_" + build_id.unwrap_or("live");
+        let cache_key = download_url_cache_key(offer_id, build_id);
         if let Some(cached) = self.request_cache.get(&cache_key) {
             return Ok(cached);
         }
@@ -85,4 +105,19 @@ impl ContentService {
         self.request_cache.insert(cache_key, url.clone());
         Ok(url)
     }
+
+    /// Like [`ContentService::download_url`], but always asks for a newly signed URL
+    pub async fn refresh_download_url(
+        &self,
+        offer_id: &str,
+        build_id: Option<&str>,
+    ) -> Result<ServiceDownloadUrlMetadata, ServiceLayerError> {
+        self.request_cache
+            .remove(&download_url_cache_key(offer_id, build_id));
+        self.download_url(offer_id, build_id).await
+    }
+}
+
+fn download_url_cache_key(offer_id: &str, build_id: Option<&str>) -> String {
+    "download_url_".to_owned() + offer_id + "_" + build_id.unwrap_or("live")
 }
@@ -0,0 +1,189 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use derive_getters::Getters;
+use serde::Serialize;
+use strum_macros::Display;
+
+/// Smoothing factor for the throughput shown to the user
+const THROUGHPUT_SMOOTHING: f64 = 0.3;
+/// Shortest window a throughput sample is taken over, shorter ones are too noisy
+const MIN_SAMPLE_WINDOW: Duration = Duration::from_millis(500);
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Display)]
+pub enum DownloadPhase {
+    /// Fetching the build's central directory
+    #[default]
+    Preparing,
+    /// Comparing installed files against the build, for updates
+    Verifying,
+    Downloading,
+    /// Running the manifest's touchup, usually registry and redistributable setup
+    Touchup,
+    /// Copying an installed game to another folder
+    Moving,
+    Finished,
+    /// Gave up, see [`DownloadProgress::error`]
+    Failed,
+}
+
+/// A snapshot of a download, sent to frontends with [`crate::core::MaximaEvent::DownloadProgress`]
+#[derive(Default, Debug, Clone, Getters, Serialize)]
+pub struct DownloadProgress {
+    offer_id: String,
+    phase: DownloadPhase,
+    /// The most recently started file that's still downloading
+    current_file: Option<String>,
+    bytes_downloaded: usize,
+    bytes_total: usize,
+    /// Smoothed download speed
+    bytes_per_second: f64,
+    /// `None` until there's a throughput estimate
+    eta: Option<Duration>,
+    files_done: usize,
+    files_total: usize,
+    /// Files that failed to download, by name
+    failed_files: Vec<String>,
+    /// Why the download failed, if it did
+    error: Option<String>,
+}
+
+impl DownloadProgress {
+    pub fn percentage_done(&self) -> f64 {
+        if self.bytes_total == 0 {
+            return if self.phase == DownloadPhase::Finished { 100.0 } else { 0.0 };
+        }
+
+        (self.bytes_downloaded as f64 / self.bytes_total as f64) * 100.0
+    }
+}
+
+#[derive(Default)]
+struct TrackerState {
+    phase: DownloadPhase,
+    active_files: Vec<String>,
+    files_done: usize,
+    files_total: usize,
+    failed_files: Vec<String>,
+    error: Option<String>,
+
+    sample_start: Option<Instant>,
+    sample_bytes: usize,
+    bytes_per_second: f64,
+}
+
+/// Shared between a download task and whoever reports on it
+#[derive(Default)]
+pub(crate) struct ProgressTracker {
+    completed_bytes: AtomicUsize,
+    total_bytes: AtomicUsize,
+    state: Mutex<TrackerState>,
+}
+
+impl ProgressTracker {
+    pub(crate) fn set_phase(&self, phase: DownloadPhase) {
+        self.state.lock().unwrap().phase = phase;
+    }
+
+    pub(crate) fn fail(&self, error: String) {
+        let mut state = self.state.lock().unwrap();
+        state.phase = DownloadPhase::Failed;
+        state.error = Some(error);
+    }
+
+    pub(crate) fn error(&self) -> Option<String> {
+        self.state.lock().unwrap().error.clone()
+    }
+
+    pub(crate) fn failed_count(&self) -> usize {
+        self.state.lock().unwrap().failed_files.len()
+    }
+
+    pub(crate) fn phase(&self) -> DownloadPhase {
+        self.state.lock().unwrap().phase
+    }
+
+    pub(crate) fn set_totals(&self, files: usize, bytes: usize) {
+        self.total_bytes.store(bytes, Ordering::SeqCst);
+        self.state.lock().unwrap().files_total = files;
+    }
+
+    pub(crate) fn add_bytes(&self, bytes: usize) {
+        self.completed_bytes.fetch_add(bytes, Ordering::SeqCst);
+    }
+
+    pub(crate) fn bytes_downloaded(&self) -> usize {
+        self.completed_bytes.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn bytes_total(&self) -> usize {
+        self.total_bytes.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn file_started(&self, name: &str) {
+        self.state.lock().unwrap().active_files.push(name.to_owned());
+    }
+
+    pub(crate) fn file_finished(&self, name: &str, failed: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.active_files.retain(|x| x != name);
+        state.files_done += 1;
+        if failed {
+            state.failed_files.push(name.to_owned());
+        }
+    }
+
+    pub(crate) fn snapshot(&self, offer_id: &str) -> DownloadProgress {
+        let bytes_downloaded = self.bytes_downloaded();
+        let bytes_total = self.bytes_total();
+
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match state.sample_start {
+            None => {
+                state.sample_start = Some(now);
+                state.sample_bytes = bytes_downloaded;
+            }
+            Some(start) if now.duration_since(start) >= MIN_SAMPLE_WINDOW => {
+                let elapsed = now.duration_since(start).as_secs_f64();
+                let rate = bytes_downloaded.saturating_sub(state.sample_bytes) as f64 / elapsed;
+                state.bytes_per_second = if state.bytes_per_second == 0.0 {
+                    rate
+                } else {
+                    state.bytes_per_second * (1.0 - THROUGHPUT_SMOOTHING)
+                        + rate * THROUGHPUT_SMOOTHING
+                };
+
+                state.sample_start = Some(now);
+                state.sample_bytes = bytes_downloaded;
+            }
+            _ => {}
+        }
+
+        let eta = if state.phase == DownloadPhase::Downloading && state.bytes_per_second > 0.0 {
+            let remaining = bytes_total.saturating_sub(bytes_downloaded) as f64;
+            Some(Duration::from_secs_f64(remaining / state.bytes_per_second))
+        } else {
+            None
+        };
+
+        DownloadProgress {
+            offer_id: offer_id.to_owned(),
+            phase: state.phase,
+            current_file: state.active_files.last().cloned(),
+            bytes_downloaded,
+            bytes_total,
+            bytes_per_second: state.bytes_per_second,
+            eta,
+            files_done: state.files_done,
+            files_total: state.files_total,
+            failed_files: state.failed_files.clone(),
+            error: state.error.clone(),
+        }
+    }
+}
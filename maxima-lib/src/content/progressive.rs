@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use crate::{content::zip::ZipFileEntry, core::manifest::GameManifest};
+
+/// The chunk games ask about to know whether they can start. Maxima has no chunk data of
+/// its own, so this is the launch set and every other chunk is the rest of the game.
+pub const LAUNCH_CHUNK: u32 = 0;
+
+const INSTALLER_DIR: &str = "__installer/";
+
+/// Where a progressively installed game is at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressiveStatus {
+    /// Queued, or still downloading what it needs to start
+    Pending,
+    /// The launch set is downloaded and touched up, the rest of the game is still downloading
+    Launchable,
+}
+
+impl ProgressiveStatus {
+    /// The rest of the game only counts as installed once the download is done, and then
+    /// there's no status left to ask
+    pub fn chunk_installed(&self, chunk: u32) -> bool {
+        *self == ProgressiveStatus::Launchable && chunk == LAUNCH_CHUNK
+    }
+}
+
+/// Manifest and touchup files, they go before everything else
+pub(crate) fn is_installer_entry(entry: &ZipFileEntry) -> bool {
+    entry.name().to_lowercase().starts_with(INSTALLER_DIR)
+}
+
+/// The launchers in `manifest`, relative to the install folder
+pub(crate) fn launchers(manifest: &dyn GameManifest) -> Vec<String> {
+    [false, true]
+        .into_iter()
+        .filter_map(|trial| manifest.execute_path(trial))
+        .map(|x| launcher_path(&x))
+        .collect()
+}
+
+/// A launcher path from a manifest relative to the install folder, dropping the
+/// `[HKEY_...\Install Dir]` prefix
+fn launcher_path(path: &str) -> String {
+    let path = match path.rfind(']') {
+        Some(idx) => &path[idx + 1..],
+        None => path,
+    };
+
+    path.replace('\\', "/")
+        .trim_start_matches('/')
+        .to_lowercase()
+}
+
+fn parent(path: &str) -> &str {
+    Path::new(path)
+        .parent()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default()
+}
+
+/// Splits off the entries `launchers` need to start: the executables, and the DLLs next to
+/// them. Empty when there's no launcher.
+pub(crate) fn split_launch_entries(
+    launchers: &[String],
+    entries: Vec<ZipFileEntry>,
+) -> (Vec<ZipFileEntry>, Vec<ZipFileEntry>) {
+    entries.into_iter().partition(|entry| {
+        let name = entry.name().to_lowercase();
+        launchers.iter().any(|launcher| {
+            *launcher == name || (name.ends_with(".dll") && parent(launcher) == parent(&name))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(entries: &[ZipFileEntry]) -> Vec<&str> {
+        entries.iter().map(|x| x.name().as_str()).collect()
+    }
+
+    #[test]
+    fn launcher_paths() {
+        assert_eq!(
+            launcher_path(
+                "[HKEY_LOCAL_MACHINE\\SOFTWARE\\EA Games\\Game\\Install Dir]Bin\\Game.exe"
+            ),
+            "bin/game.exe"
+        );
+        assert_eq!(launcher_path("\\Game.exe"), "game.exe");
+    }
+
+    #[test]
+    fn launch_set() {
+        let entries = [
+            "__Installer/installerdata.xml",
+            "Bin/Game.exe",
+            "Bin/engine.DLL",
+            "Bin/config.ini",
+            "Bin/Plugins/plugin.dll",
+            "Data/level.big",
+            "root.dll",
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| ZipFileEntry::stored_at(name, i as i64, 1))
+        .collect();
+
+        let (launch, rest) = split_launch_entries(&["bin/game.exe".to_owned()], entries);
+        assert_eq!(names(&launch), ["Bin/Game.exe", "Bin/engine.DLL"]);
+        assert_eq!(
+            names(&rest),
+            [
+                "__Installer/installerdata.xml",
+                "Bin/config.ini",
+                "Bin/Plugins/plugin.dll",
+                "Data/level.big",
+                "root.dll"
+            ]
+        );
+    }
+
+    #[test]
+    fn no_launchers() {
+        let entries = vec![ZipFileEntry::stored_at("Game.exe", 0, 1)];
+        let (launch, rest) = split_launch_entries(&[], entries);
+        assert!(launch.is_empty());
+        assert_eq!(names(&rest), ["Game.exe"]);
+    }
+
+    #[test]
+    fn launch_chunk_only() {
+        assert!(ProgressiveStatus::Launchable.chunk_installed(LAUNCH_CHUNK));
+        assert!(!ProgressiveStatus::Launchable.chunk_installed(1));
+        assert!(!ProgressiveStatus::Pending.chunk_installed(LAUNCH_CHUNK));
+    }
+}
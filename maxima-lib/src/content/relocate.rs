@@ -0,0 +1,240 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::{error, info, warn};
+use tokio::fs;
+
+use crate::{
+    content::{
+        installed::{InstalledEntry, InstalledGame},
+        manager::{ContentManagerError, DownloaderError},
+        progress::{DownloadPhase, DownloadProgress, ProgressTracker},
+    },
+    core::manifest,
+    util::{hash::hash_file_crc32, native::available_space},
+};
+
+/// An installed game being moved to another folder, see
+/// [`crate::content::manager::ContentManager::move_install`]
+pub(crate) struct InstallMove {
+    offer_id: String,
+    progress: Arc<ProgressTracker>,
+}
+
+impl InstallMove {
+    /// Starts moving `installed` to `to` in the background
+    pub(crate) fn start(installed: InstalledGame, to: PathBuf) -> Self {
+        let offer_id = installed.offer_id().to_owned();
+        let progress = Arc::new(ProgressTracker::default());
+
+        let task_progress = progress.clone();
+        tokio::spawn(async move {
+            let offer_id = installed.offer_id().to_owned();
+            if let Err(err) = run(installed, to, &task_progress).await {
+                error!("Failed to move {}: {}", offer_id, err);
+                task_progress.fail(err.to_string());
+            }
+        });
+
+        Self { offer_id, progress }
+    }
+
+    pub(crate) fn offer_id(&self) -> &str {
+        &self.offer_id
+    }
+
+    pub(crate) fn phase(&self) -> DownloadPhase {
+        self.progress.phase()
+    }
+
+    pub(crate) fn error(&self) -> Option<String> {
+        self.progress.error()
+    }
+
+    pub(crate) fn progress(&self) -> DownloadProgress {
+        self.progress.snapshot(&self.offer_id)
+    }
+}
+
+async fn run(
+    mut installed: InstalledGame,
+    to: PathBuf,
+    progress: &ProgressTracker,
+) -> Result<(), ContentManagerError> {
+    progress.set_phase(DownloadPhase::Moving);
+    let from = installed.path().to_owned();
+    info!(
+        "Moving {} from {:?} to {:?}",
+        installed.offer_id(),
+        from,
+        to
+    );
+
+    move_tree(&from, &to, installed.entries(), progress).await?;
+
+    // Keep track of the game even if touchup fails below
+    installed.set_path(to.clone());
+    installed.save().await?;
+
+    // Touchup writes the install path to the registry, so this points it at the new folder
+    progress.set_phase(DownloadPhase::Touchup);
+    let manifest = manifest::read_installed(&to)
+        .await
+        .map_err(DownloaderError::from)?;
+    manifest
+        .run_touchup(&to, &installed.game_locale())
+        .await
+        .map_err(DownloaderError::from)?;
+
+    info!("Moved {} to {:?}", installed.offer_id(), to);
+    progress.set_phase(DownloadPhase::Finished);
+    Ok(())
+}
+
+/// Relative paths of every directory and file under `root`
+fn collect_tree(root: &Path) -> std::io::Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let relative = match path.strip_prefix(root) {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => continue,
+            };
+
+            if entry.file_type()?.is_dir() {
+                dirs.push(relative);
+                pending.push(path);
+            } else {
+                files.push(relative);
+            }
+        }
+    }
+
+    Ok((dirs, files))
+}
+
+/// Renames `from` to `to`, or copies it over when they're on different filesystems.
+/// The original is only deleted once every file arrived in full and the build's files
+/// match the CRCs of `entries`.
+async fn move_tree(
+    from: &Path,
+    to: &Path,
+    entries: &[InstalledEntry],
+    progress: &ProgressTracker,
+) -> Result<(), ContentManagerError> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    if fs::rename(from, to).await.is_ok() {
+        return Ok(());
+    }
+
+    let root = from.to_path_buf();
+    let (dirs, files) = tokio::task::spawn_blocking(move || collect_tree(&root))
+        .await
+        .map_err(std::io::Error::other)??;
+
+    let mut sizes = Vec::with_capacity(files.len());
+    for file in &files {
+        sizes.push(fs::metadata(from.join(file)).await?.len());
+    }
+
+    let required: u64 = sizes.iter().sum();
+    let available = available_space(to)?;
+    if required > available {
+        return Err(ContentManagerError::InsufficientSpace {
+            required,
+            available,
+        });
+    }
+
+    progress.set_totals(files.len(), required as usize);
+
+    let mut copied = copy_tree(from, to, &dirs, &files, &sizes, progress).await;
+    if copied.is_ok() {
+        progress.set_phase(DownloadPhase::Verifying);
+        copied = verify_copy(from, to, entries).await;
+    }
+
+    if let Err(err) = copied {
+        // The original is still complete, don't leave half a copy next to it
+        if let Err(err) = fs::remove_dir_all(to).await {
+            warn!("Failed to clean up partial copy at {:?}: {}", to, err);
+        }
+        return Err(err);
+    }
+
+    fs::remove_dir_all(from).await?;
+    Ok(())
+}
+
+async fn crc32(path: PathBuf) -> std::io::Result<u32> {
+    tokio::task::spawn_blocking(move || hash_file_crc32(path))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+/// Hashes the copied files of `entries` and compares them to what was recorded when the
+/// game was installed. Files the game changed itself since then are fine as long as the
+/// copy matches the original.
+async fn verify_copy(
+    from: &Path,
+    to: &Path,
+    entries: &[InstalledEntry],
+) -> Result<(), ContentManagerError> {
+    for entry in entries.iter().filter(|x| !x.name().ends_with('/')) {
+        let target = to.join(entry.name());
+        if !target.exists() {
+            // Not copied, so it wasn't there in the first place
+            continue;
+        }
+
+        let hash = crc32(target).await?;
+        if hash != *entry.crc32() && hash != crc32(from.join(entry.name())).await? {
+            return Err(ContentManagerError::IncompleteCopy(entry.name().to_owned()));
+        }
+    }
+
+    Ok(())
+}
+
+async fn copy_tree(
+    from: &Path,
+    to: &Path,
+    dirs: &[PathBuf],
+    files: &[PathBuf],
+    sizes: &[u64],
+    progress: &ProgressTracker,
+) -> Result<(), ContentManagerError> {
+    fs::create_dir_all(to).await?;
+    for dir in dirs {
+        fs::create_dir_all(to.join(dir)).await?;
+    }
+
+    for (file, size) in files.iter().zip(sizes) {
+        let name = file.to_string_lossy().replace('\\', "/");
+        progress.file_started(&name);
+
+        let target = to.join(file);
+        fs::copy(from.join(file), &target).await?;
+
+        let copied = fs::metadata(&target).await?.len();
+        if copied != *size {
+            progress.file_finished(&name, true);
+            return Err(ContentManagerError::IncompleteCopy(name));
+        }
+
+        progress.add_bytes(*size as usize);
+        progress.file_finished(&name, false);
+    }
+
+    Ok(())
+}
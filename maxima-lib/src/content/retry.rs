@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+
+/// How failed entry downloads are retried. Only transient failures are, see
+/// [`crate::content::manager::DownloaderError::is_transient`]
+#[derive(Debug, Getters, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RetryPolicy {
+    /// Including the first try
+    max_attempts: u32,
+    base_delay_ms: u64,
+    max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, base_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            base_delay_ms: base_delay.as_millis() as u64,
+            max_delay_ms: max_delay.as_millis() as u64,
+        }
+    }
+
+    /// Whether another try is allowed after `attempt` failed, counting from 1
+    pub fn should_retry(&self, attempt: u32) -> bool {
+        attempt < self.max_attempts
+    }
+
+    /// Exponential backoff with up to 50% jitter, so parallel entries don't all hit the
+    /// CDN again at the same moment
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay_ms
+            .saturating_mul(1u64 << attempt.saturating_sub(1).min(16))
+            .min(self.max_delay_ms);
+        let jitter = 0.5 + rand::random::<f64>() * 0.5;
+        Duration::from_millis((exponential as f64 * jitter) as u64)
+    }
+}
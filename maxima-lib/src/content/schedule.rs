@@ -0,0 +1,104 @@
+use chrono::{Local, Timelike};
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// Local time of day downloads may run in, as minutes after midnight. A window ending
+/// before it starts runs past midnight, `start == end` allows the whole day.
+#[derive(Debug, Getters, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DownloadWindow {
+    start: u16,
+    end: u16,
+}
+
+impl DownloadWindow {
+    pub fn new(start: u16, end: u16) -> Self {
+        Self {
+            start: start % MINUTES_PER_DAY,
+            end: end % MINUTES_PER_DAY,
+        }
+    }
+
+    /// Parses `HH:MM-HH:MM`, e.g. `23:00-07:00` for overnight only
+    pub fn parse(window: &str) -> Option<Self> {
+        let (start, end) = window.split_once('-')?;
+        Some(Self::new(parse_time(start)?, parse_time(end)?))
+    }
+
+    pub fn contains(&self, minute: u16) -> bool {
+        if self.start <= self.end {
+            self.start == self.end || (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+impl std::fmt::Display for DownloadWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+fn parse_time(time: &str) -> Option<u16> {
+    let (hour, minute) = time.trim().split_once(':')?;
+    let (hour, minute): (u16, u16) = (hour.parse().ok()?, minute.parse().ok()?);
+    if hour >= 24 || minute >= 60 {
+        return None;
+    }
+
+    Some(hour * 60 + minute)
+}
+
+/// Why the download queue is on hold even though it isn't paused
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HoldReason {
+    /// Outside of [`DownloadSchedule::window`]
+    OutsideWindow,
+    /// A game is running and [`DownloadSchedule::pause_while_playing`] is set
+    Playing,
+}
+
+/// When the download queue runs by itself. Downloads held by the schedule stop like a
+/// pause and continue from their finished files and entry checkpoints once it allows them
+/// again, see [`ContentManager::pause`](crate::content::manager::ContentManager::pause).
+#[derive(Default, Debug, Getters, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadSchedule {
+    /// Only download in this window, `None` for any time
+    #[serde(default)]
+    pub(crate) window: Option<DownloadWindow>,
+    /// Stay out of the way of a running game's bandwidth and disk
+    #[serde(default)]
+    pub(crate) pause_while_playing: bool,
+}
+
+impl DownloadSchedule {
+    pub fn new(window: Option<DownloadWindow>, pause_while_playing: bool) -> Self {
+        Self {
+            window,
+            pause_while_playing,
+        }
+    }
+
+    /// What keeps downloads from running right now, if anything
+    pub(crate) fn hold_reason(&self, playing: bool) -> Option<HoldReason> {
+        if self.pause_while_playing && playing {
+            return Some(HoldReason::Playing);
+        }
+
+        let now = Local::now();
+        let minute = (now.hour() * 60 + now.minute()) as u16;
+        match self.window {
+            Some(window) if !window.contains(minute) => Some(HoldReason::OutsideWindow),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    content::{
+        concurrency::Concurrency, dedup::DedupMode, manager::ContentManagerError,
+        retry::RetryPolicy, schedule::DownloadSchedule,
+    },
+    util::native::maxima_dir,
+};
+
+const SETTINGS_FILE: &str = "download_settings.json";
+
+/// Downloader settings shared by every frontend
+#[derive(Default, Debug, Getters, Clone, Serialize, Deserialize)]
+pub struct DownloadSettings {
+    /// Bytes per second, `None` for unlimited
+    #[serde(default)]
+    pub(crate) max_bytes_per_second: Option<u64>,
+    #[serde(default)]
+    pub(crate) retry: RetryPolicy,
+    /// Set output files to their final size before writing them
+    #[serde(default)]
+    pub(crate) preallocate: bool,
+    /// How many files are downloaded at once, unless a queued game says otherwise
+    #[serde(default)]
+    pub(crate) concurrency: Concurrency,
+    /// Whether installs link files other installs already have, see [`DedupMode`]
+    #[serde(default)]
+    pub(crate) dedup: DedupMode,
+    /// When the queue runs by itself, see [`DownloadSchedule`]
+    #[serde(default)]
+    pub(crate) schedule: DownloadSchedule,
+}
+
+impl DownloadSettings {
+    pub(crate) async fn load() -> Result<DownloadSettings, ContentManagerError> {
+        let file = maxima_dir()?.join(SETTINGS_FILE);
+        if !file.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = fs::read_to_string(file).await?;
+        Ok(serde_json::from_str(&data).unwrap_or_default())
+    }
+
+    pub(crate) async fn save(&self) -> Result<(), ContentManagerError> {
+        let file = maxima_dir()?.join(SETTINGS_FILE);
+        fs::write(file, serde_json::to_string(&self)?).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use std::{collections::HashSet, path::Path};
+
+use crate::content::zip::ZipFileEntry;
+
+/// Headroom for whatever touchup puts next to the game, mostly redistributables
+pub(crate) const TOUCHUP_MARGIN: u64 = 512 * 1024 * 1024;
+
+/// Bytes `entries` still need under `root`. Files already there, from an interrupted
+/// download or the build being updated, only count for the difference. `preallocated`
+/// files are their full size already, but may be sparse where the filesystem couldn't
+/// reserve their blocks, so they count as missing.
+pub(crate) fn required_space(
+    root: &Path,
+    entries: &[ZipFileEntry],
+    preallocated: &HashSet<String>,
+) -> u64 {
+    entries
+        .iter()
+        .filter(|x| !x.name().ends_with('/'))
+        .map(|entry| {
+            let size = (*entry.uncompressed_size()).max(0) as u64;
+            if preallocated.contains(entry.name()) {
+                return size;
+            }
+
+            let existing = std::fs::metadata(root.join(entry.name()))
+                .map(|x| x.len())
+                .unwrap_or(0);
+            size.saturating_sub(existing)
+        })
+        .sum()
+}
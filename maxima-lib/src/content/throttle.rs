@@ -0,0 +1,150 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+/// How often a paused limiter checks whether it may continue
+const PAUSED_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Smoothing factor for the measured throughput
+const THROUGHPUT_SMOOTHING: f64 = 0.2;
+
+struct Bucket {
+    /// Configured limit in bytes per second, `None` for unlimited
+    limit: Option<u64>,
+    /// Set at runtime by games, scales the limit down. 1.0 means full speed
+    utilization: f32,
+
+    tokens: f64,
+    last_refill: Instant,
+
+    window_start: Instant,
+    window_bytes: u64,
+    /// Rolling estimate of unthrottled throughput, used when a game asks to slow down
+    /// and there's no configured limit to scale
+    measured_rate: f64,
+}
+
+impl Bucket {
+    fn rate(&self) -> Option<f64> {
+        if self.utilization >= 1.0 {
+            return self.limit.map(|x| x as f64);
+        }
+
+        let base = match self.limit {
+            Some(limit) => limit as f64,
+            None if self.measured_rate > 0.0 => self.measured_rate,
+            None => return None,
+        };
+
+        Some(base * self.utilization.max(0.0) as f64)
+    }
+
+    fn refill(&mut self, rate: f64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+
+        // Allow at most a second worth of burst
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+    }
+
+    fn record(&mut self, bytes: usize) {
+        self.window_bytes += bytes as u64;
+
+        let elapsed = self.window_start.elapsed().as_secs_f64();
+        if elapsed < 1.0 {
+            return;
+        }
+
+        // Only learn from unthrottled windows, otherwise the estimate shrinks forever
+        if self.utilization >= 1.0 && self.limit.is_none() {
+            let rate = self.window_bytes as f64 / elapsed;
+            self.measured_rate = if self.measured_rate == 0.0 {
+                rate
+            } else {
+                self.measured_rate * (1.0 - THROUGHPUT_SMOOTHING) + rate * THROUGHPUT_SMOOTHING
+            };
+        }
+
+        self.window_start = Instant::now();
+        self.window_bytes = 0;
+    }
+}
+
+/// Token bucket shared by every entry download
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: Option<u64>) -> Self {
+        let now = Instant::now();
+        Self {
+            bucket: Mutex::new(Bucket {
+                limit,
+                utilization: 1.0,
+                tokens: 0.0,
+                last_refill: now,
+                window_start: now,
+                window_bytes: 0,
+                measured_rate: 0.0,
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> Option<u64> {
+        self.bucket.lock().unwrap().limit
+    }
+
+    /// Sets the limit in bytes per second, `None` removes it
+    pub fn set_limit(&self, limit: Option<u64>) {
+        info!("Download limit set to {:?} B/s", limit);
+        self.bucket.lock().unwrap().limit = limit;
+    }
+
+    pub fn utilization(&self) -> f32 {
+        self.bucket.lock().unwrap().utilization
+    }
+
+    /// Scales the limit between 0.0 (paused) and 1.0 (full speed)
+    pub fn set_utilization(&self, utilization: f32) {
+        info!("Download utilization set to {}", utilization);
+        self.bucket.lock().unwrap().utilization = utilization.clamp(0.0, 1.0);
+    }
+
+    /// Waits until `bytes` may be passed on
+    pub async fn acquire(&self, bytes: usize) {
+        loop {
+            // Keep the guard out of the awaits below
+            let debt = {
+                let mut bucket = self.bucket.lock().unwrap();
+                match bucket.rate() {
+                    None => {
+                        bucket.record(bytes);
+                        Some(0.0)
+                    }
+                    Some(rate) if rate < 1.0 => None,
+                    Some(rate) => {
+                        bucket.refill(rate);
+                        bucket.tokens -= bytes as f64;
+                        bucket.record(bytes);
+                        Some((-bucket.tokens / rate).max(0.0))
+                    }
+                }
+            };
+
+            match debt {
+                // The bytes are already taken out, sleep off the debt and carry on
+                Some(seconds) => {
+                    if seconds > 0.0 {
+                        tokio::time::sleep(Duration::from_secs_f64(seconds)).await;
+                    }
+                    return;
+                }
+                None => tokio::time::sleep(PAUSED_POLL_INTERVAL).await,
+            }
+        }
+    }
+}
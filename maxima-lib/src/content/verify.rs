@@ -0,0 +1,125 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use derive_getters::Getters;
+use futures::StreamExt;
+use serde::Serialize;
+
+use crate::content::{diff, manager::DownloaderError, zip::ZipFileEntry};
+
+/// How many files get hashed at once
+const VERIFY_CONCURRENCY: usize = 4;
+
+#[derive(Default, Debug, Getters, Clone, Serialize)]
+pub struct VerifyReport {
+    offer_id: String,
+    checked: usize,
+    missing: Vec<String>,
+    corrupt: Vec<String>,
+    /// Files on disk the build doesn't know about. Could be saves or configs, so they're
+    /// only ever reported
+    extra: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_intact(&self) -> bool {
+        self.missing.is_empty() && self.corrupt.is_empty()
+    }
+
+    /// Entries that have to be downloaded again
+    pub fn bad_entries(&self) -> Vec<String> {
+        self.missing
+            .iter()
+            .chain(self.corrupt.iter())
+            .cloned()
+            .collect()
+    }
+}
+
+enum EntryStatus {
+    Intact,
+    Missing,
+    Corrupt,
+}
+
+async fn entry_status(root: &Path, entry: &ZipFileEntry) -> Result<EntryStatus, DownloaderError> {
+    if !root.join(entry.name()).exists() {
+        return Ok(EntryStatus::Missing);
+    }
+
+    if diff::entry_matches(root, entry, None).await? {
+        Ok(EntryStatus::Intact)
+    } else {
+        Ok(EntryStatus::Corrupt)
+    }
+}
+
+/// Relative paths of every file under `root`, using zip style separators
+fn installed_files(root: &Path) -> std::io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+
+            if let Ok(relative) = path.strip_prefix(root) {
+                files.push(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Hashes every installed file of `entries` under `root` and compares it to the central directory
+pub(crate) async fn verify_install(
+    offer_id: &str,
+    root: &Path,
+    entries: &[ZipFileEntry],
+) -> Result<VerifyReport, DownloaderError> {
+    let mut report = VerifyReport {
+        offer_id: offer_id.to_owned(),
+        checked: entries.len(),
+        ..Default::default()
+    };
+
+    let results = futures::stream::iter(
+        entries
+            .iter()
+            .map(|entry| async move { (entry, entry_status(root, entry).await) }),
+    )
+    .buffer_unordered(VERIFY_CONCURRENCY)
+    .collect::<Vec<_>>()
+    .await;
+
+    for (entry, status) in results {
+        match status? {
+            EntryStatus::Intact => {}
+            EntryStatus::Missing => report.missing.push(entry.name().to_owned()),
+            EntryStatus::Corrupt => report.corrupt.push(entry.name().to_owned()),
+        }
+    }
+
+    let known: HashSet<&str> = entries.iter().map(|x| x.name().as_str()).collect();
+    let root_buf = PathBuf::from(root);
+    let files = tokio::task::spawn_blocking(move || installed_files(&root_buf))
+        .await
+        .map_err(std::io::Error::other)??;
+    report.extra = files
+        .into_iter()
+        .filter(|x| !known.contains(x.as_str()))
+        .collect();
+
+    report.missing.sort();
+    report.corrupt.sort();
+    report.extra.sort();
+    Ok(report)
+}
@@ -1,12 +1,16 @@
 use bytebuffer::{ByteBuffer, Endian};
 use derive_getters::Getters;
 use encoding::{all::WINDOWS_1252, DecoderTrap, Encoding};
-use log::{debug, warn};
+use log::{debug, info, warn};
 use reqwest::header::ToStrError;
 use reqwest::Client;
 use std::cmp;
+use std::future::Future;
+use std::io::SeekFrom;
+use std::path::Path;
 use std::string::FromUtf8Error;
 use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 
 /// This module is based on https://users.cs.jmu.edu/buchhofp/forensics/formats/pkzip.html
 
@@ -98,15 +102,27 @@ fn signature_scan_rev(data: &[u8], signature: u32) -> Option<usize> {
 #[derive(Default, Clone, Debug, PartialEq)]
 pub enum CompressionType {
     #[default]
-    None = 0,
-    Deflate = 8,
+    None,
+    Deflate,
+    Deflate64,
+    Lzma,
+    Zstd,
+    Xz,
+    /// Any other method, kept so it can be reported instead of writing out compressed data
+    Unknown(u16),
 }
 
 impl CompressionType {
     pub fn from_num(num: u16) -> CompressionType {
         match num {
+            0 => CompressionType::None,
             8 => CompressionType::Deflate,
-            0 | _ => CompressionType::None,
+            9 => CompressionType::Deflate64,
+            14 => CompressionType::Lzma,
+            // 20 is what Zstandard used before it got its own ID
+            20 | 93 => CompressionType::Zstd,
+            95 => CompressionType::Xz,
+            other => CompressionType::Unknown(other),
         }
     }
 }
@@ -127,6 +143,38 @@ pub struct ZipFileEntry {
 }
 
 impl ZipFileEntry {
+    /// An uncompressed entry for a file of an extracted build. Its data is the whole file.
+    pub(crate) fn stored(name: String, crc32: u32, size: i64) -> Self {
+        Self {
+            name,
+            crc32,
+            compressed_size: size,
+            uncompressed_size: size,
+            ..Default::default()
+        }
+    }
+
+    /// A stored entry of `size` bytes whose data starts `data_offset` bytes into the zip
+    #[cfg(test)]
+    pub(crate) fn stored_at(name: &str, data_offset: i64, size: i64) -> Self {
+        Self {
+            data_offset,
+            ..Self::stored(name.to_owned(), 0, size)
+        }
+    }
+
+    /// An entry of `size` bytes packed into `data` with `compression_type`
+    #[cfg(test)]
+    pub(crate) fn compressed(compression_type: CompressionType, data: &[u8], size: i64) -> Self {
+        Self {
+            name: "entry".to_owned(),
+            compression_type,
+            compressed_size: data.len() as i64,
+            uncompressed_size: size,
+            ..Default::default()
+        }
+    }
+
     pub fn parse(data: &mut ByteBuffer) -> Result<ZipFileEntry, EntryError> {
         let mut entry = Self::default();
 
@@ -227,6 +275,9 @@ impl ZipFileEntry {
 #[derive(Default, Getters)]
 pub struct ZipFile {
     entries: Vec<ZipFileEntry>,
+    /// Whether the entries carry the build's own CRC32s. Extracted builds listed without
+    /// the build's central directory have nothing to check their files against.
+    verifiable: bool,
 }
 
 #[derive(Default)]
@@ -308,26 +359,83 @@ impl ZipFile {
             .parse::<i64>()
             .unwrap_or(0);
 
+        Self::load_tail(content_length, |start, end| {
+            let request = client
+                .get(url)
+                .header("range", format!("bytes={}-{}", start, end - 1));
+            async move { Ok(request.send().await?.bytes().await?.to_vec()) }
+        })
+        .await
+    }
+
+    /// Reads the central directory of a build zip on disk
+    pub async fn open(path: &Path) -> Result<Self, ZipError> {
+        let content_length = tokio::fs::metadata(path).await?.len() as i64;
+
+        Self::load_tail(content_length, |start, end| async move {
+            let mut file = tokio::fs::File::open(path).await?;
+            file.seek(SeekFrom::Start(start as u64)).await?;
+
+            let mut data = vec![0; (end - start) as usize];
+            file.read_exact(&mut data).await?;
+            Ok(data)
+        })
+        .await
+    }
+
+    /// Lists an extracted build as if it were a zip of uncompressed entries. With the central
+    /// directory of the `build` it was extracted from, the entries are that build's, CRCs
+    /// and all. Without one, the files are listed as they are and can't be verified.
+    pub async fn from_directory(root: &Path, build: Option<&ZipFile>) -> Result<Self, ZipError> {
+        if let Some(build) = build {
+            let entries = build
+                .entries
+                .iter()
+                .map(|x| ZipFileEntry::stored(x.name.clone(), x.crc32, x.uncompressed_size))
+                .collect();
+
+            return Ok(Self {
+                entries,
+                verifiable: build.verifiable,
+            });
+        }
+
+        let root = root.to_path_buf();
+        let entries = tokio::task::spawn_blocking(move || directory_entries(&root))
+            .await
+            .map_err(std::io::Error::other)??;
+
+        info!("Read {} entries from extracted build", entries.len());
+        Ok(Self {
+            entries,
+            verifiable: false,
+        })
+    }
+
+    /// Reads backwards from the end of the zip until the whole central directory is loaded.
+    /// `read` fetches the half-open byte range it's given.
+    async fn load_tail<F, Fut>(content_length: i64, mut read: F) -> Result<Self, ZipError>
+    where
+        F: FnMut(i64, i64) -> Fut,
+        Fut: Future<Output = Result<Vec<u8>, ZipError>>,
+    {
         let mut data: Vec<u8> = Vec::with_capacity(MAX_BACKSCAN_OFFSET);
         let mut offset = content_length - 8 * 1024;
         if offset < 0 {
             return Err(ZipError::ContentTooLong);
         }
 
-        let mut zip = Self::default();
+        let mut zip = Self {
+            verifiable: true,
+            ..Default::default()
+        };
 
         while offset > 0 && data.len() < MAX_BACKSCAN_OFFSET {
-            let read = content_length - offset - data.len() as i64;
-            let start_offset = content_length - data.len() as i64 - read;
-            let end_offset = start_offset + read;
+            let read_len = content_length - offset - data.len() as i64;
+            let start_offset = content_length - data.len() as i64 - read_len;
+            let end_offset = start_offset + read_len;
 
-            let range_header = format!("bytes={}-{}", start_offset, end_offset - 1);
-            let response = client
-                .get(url)
-                .header("range", &range_header)
-                .send()
-                .await?;
-            let this_data = response.bytes().await?.to_vec();
+            let this_data = read(start_offset, end_offset).await?;
             data = [this_data, data].concat();
 
             offset = zip.load(&mut ByteBuffer::from_vec(data.clone()), content_length)?;
@@ -450,3 +558,32 @@ impl ZipFile {
         Ok(())
     }
 }
+
+/// Entries for every directory and file under `root`, with zip style names. Their CRCs
+/// are left at 0, hashing a file against itself wouldn't prove anything.
+pub(crate) fn directory_entries(root: &Path) -> std::io::Result<Vec<ZipFileEntry>> {
+    let mut entries = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        for item in std::fs::read_dir(&dir)? {
+            let item = item?;
+            let path = item.path();
+            let name = match path.strip_prefix(root) {
+                Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+                Err(_) => continue,
+            };
+
+            if item.file_type()?.is_dir() {
+                entries.push(ZipFileEntry::stored(format!("{}/", name), 0, 0));
+                pending.push(path);
+                continue;
+            }
+
+            let size = item.metadata()?.len() as i64;
+            entries.push(ZipFileEntry::stored(name, 0, size));
+        }
+    }
+
+    Ok(entries)
+}
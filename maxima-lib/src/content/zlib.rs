@@ -90,7 +90,30 @@ macro_rules! if_win {
 type ZSize = if_win!(u32, c_ulong);
 type ZChecksum = if_win!(u32, c_ulong);
 
-pub(crate) fn write_zlib_state(buf: &mut BytesMut, stream: &mut mz_stream) {
+/// Where `code` is in `state.codes`. `None` for zlib's own tables of the fixed codes, their
+/// address means nothing to another process.
+fn code_index(state: &ZInflateState, code: *const ZCode) -> Option<u32> {
+    let size = std::mem::size_of::<ZCode>();
+    let offset = (code as usize).wrapping_sub(state.codes.as_ptr() as usize);
+    (offset % size == 0 && offset / size <= Z_ENOUGH).then_some((offset / size) as u32)
+}
+
+/// Saves the state of `stream`. Returns false without writing anything while it can't be
+/// restored later, in a block using the fixed codes.
+pub(crate) fn write_zlib_state(buf: &mut BytesMut, stream: &mut mz_stream) -> bool {
+    let state = stream.state as *mut ZInflateState;
+    let state_ref = unsafe { &mut *state };
+
+    let indices = (
+        code_index(state_ref, state_ref.lencode),
+        code_index(state_ref, state_ref.distcode),
+        code_index(state_ref, state_ref.next),
+    );
+    let (lencode_index, distcode_index, next_index) = match indices {
+        (Some(lencode), Some(distcode), Some(next)) => (lencode, distcode, next),
+        _ => return false,
+    };
+
     buf.put_u32(Z_MAGIC);
 
     buf.put_u64(stream.total_in as u64);
@@ -98,9 +121,6 @@ pub(crate) fn write_zlib_state(buf: &mut BytesMut, stream: &mut mz_stream) {
     buf.put_i32(stream.data_type);
     buf.put_u64(stream.adler as u64);
 
-    let state = stream.state as *mut ZInflateState;
-    let state_ref = unsafe { &mut *state };
-
     let size = std::mem::size_of::<ZInflateState>();
     let mut buffer = vec![0; size];
     unsafe {
@@ -123,35 +143,52 @@ pub(crate) fn write_zlib_state(buf: &mut BytesMut, stream: &mut mz_stream) {
         }
     }
 
-    let mut lencode_index = unsafe { state_ref.lencode.offset_from(state_ref.codes.as_ptr()) };
-    let mut distcode_index = unsafe { state_ref.distcode.offset_from(state_ref.codes.as_ptr()) };
-    let mut next_index = unsafe { state_ref.next.offset_from(state_ref.codes.as_ptr()) };
+    buf.put_u32(lencode_index);
+    buf.put_u32(distcode_index);
+    buf.put_u32(next_index);
 
-    if lencode_index > Z_ENOUGH.try_into().unwrap() {
-        lencode_index = 0;
-    }
+    buf.put_u32(state_ref.lenbits);
+    buf.put_u32(state_ref.distbits);
+    true
+}
 
-    if distcode_index > Z_ENOUGH.try_into().unwrap() {
-        distcode_index = 0;
-    }
+/// Bytes [`write_zlib_state`] puts ahead of the inflate state, and after it and the window
+const Z_STREAM_LEN: usize = 32;
+const Z_CODES_LEN: usize = 20;
 
-    if next_index > Z_ENOUGH.try_into().unwrap() {
-        next_index = 0;
+/// Checks that `buf` holds a whole state from [`write_zlib_state`], without consuming it
+fn check_zlib_state(buf: &Bytes) -> bool {
+    let size = std::mem::size_of::<ZInflateState>();
+    if buf.len() < Z_STREAM_LEN + size || buf[..4] != Z_MAGIC.to_be_bytes() {
+        return false;
     }
 
-    buf.put_u32(lencode_index as u32);
-    buf.put_u32(distcode_index as u32);
-    buf.put_u32(next_index as u32);
+    let state =
+        unsafe { ptr::read_unaligned(buf[Z_STREAM_LEN..].as_ptr() as *const ZInflateState) };
+    if state.wbits > 15 {
+        return false;
+    }
 
-    buf.put_u32(state_ref.lenbits);
-    buf.put_u32(state_ref.distbits);
+    let window_size = if state.window.is_null() {
+        0
+    } else {
+        1 << state.wbits
+    };
+    let mut codes = match buf.get(Z_STREAM_LEN + size + window_size..) {
+        Some(codes) if codes.len() >= Z_CODES_LEN => codes,
+        _ => return false,
+    };
+    (0..3).all(|_| codes.get_u32() as usize <= Z_ENOUGH)
 }
 
-pub(crate) fn restore_zlib_state(buf: &mut Bytes, stream: &mut mz_stream) {
-    if buf.get_u32() != Z_MAGIC {
-        error!("Invalid magic number while reading zlib state");
-        return;
+/// Puts a state saved by [`write_zlib_state`] into `stream`, which has to be freshly reset.
+/// Returns false and leaves `stream` alone if `buf` doesn't hold a whole one.
+pub(crate) fn restore_zlib_state(buf: &mut Bytes, stream: &mut mz_stream) -> bool {
+    if !check_zlib_state(buf) {
+        error!("Invalid zlib state");
+        return false;
     }
+    buf.get_u32();
 
     stream.total_in = buf.get_u64() as ZSize;
     stream.total_out = buf.get_u64() as ZSize;
@@ -199,14 +236,11 @@ pub(crate) fn restore_zlib_state(buf: &mut Bytes, stream: &mut mz_stream) {
     let distcode = buf.get_u32() as isize;
     let nextcode = buf.get_u32() as isize;
 
-    if lencode > Z_ENOUGH.try_into().unwrap() {
-        panic!("Can't deserialize this zlib state, lencode too high!");
-    }
-
     state_ref.lencode = unsafe { state_ref.codes.as_ptr().offset(lencode) as *mut ZCode };
     state_ref.distcode = unsafe { state_ref.codes.as_ptr().offset(distcode) as *mut ZCode };
     state_ref.next = unsafe { state_ref.codes.as_ptr().offset(nextcode) as *mut ZCode };
 
     state_ref.lenbits = buf.get_u32();
     state_ref.distbits = buf.get_u32();
+    true
 }
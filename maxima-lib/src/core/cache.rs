@@ -3,6 +3,7 @@ use std::{any::Any, borrow::Borrow, hash::Hash, sync::Arc, time::Duration};
 use moka::sync::Cache;
 
 /// Note that values are cloned when retrieved
+#[derive(Clone)]
 pub struct DynamicCache<K> {
     cache: Cache<K, Arc<dyn Any + Sync + Send>>,
 }
@@ -25,6 +26,14 @@ impl<K: Eq + Hash + Sync + Send + 'static> DynamicCache<K> {
         self.cache.insert(key, Arc::new(request));
     }
 
+    pub fn remove<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.invalidate(key);
+    }
+
     pub fn get<Q, T>(&self, key: &Q) -> Option<T>
     where
         K: Borrow<Q>,
@@ -620,7 +620,9 @@ impl CloudSyncClient {
 
 #[cfg(test)]
 mod tests {
-    use crate::core::{auth::storage::AuthStorage, library::GameLibrary};
+    use crate::core::{
+        auth::storage::AuthStorage, library::GameLibrary, service_layer::ServiceLayerClient,
+    };
 
     use super::*;
 
@@ -631,7 +633,7 @@ mod tests {
             return Err(CloudSyncError::NotSignedIn);
         }
 
-        let mut library = GameLibrary::new(auth.clone()).await;
+        let mut library = GameLibrary::new(ServiceLayerClient::new(auth.clone())).await;
         let offer = library
             .game_by_base_slug("star-wars-battlefront-2")
             .await?
@@ -654,7 +656,7 @@ mod tests {
             return Err(CloudSyncError::NotSignedIn);
         }
 
-        let mut library = GameLibrary::new(auth.clone()).await;
+        let mut library = GameLibrary::new(ServiceLayerClient::new(auth.clone())).await;
         let offer = library
             .game_by_base_slug("star-wars-battlefront-2")
             .await?
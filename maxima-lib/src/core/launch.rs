@@ -1,6 +1,6 @@
 use base64::{engine::general_purpose, Engine};
 use derive_getters::Getters;
-use log::{error, info};
+use log::{error, info, warn};
 use std::{env, fmt::Display, path::PathBuf, sync::Arc};
 use tokio::{
     process::{Child, Command},
@@ -120,9 +120,12 @@ pub struct ActiveGameContext {
     cloud_saves: bool,
     process: Child,
     started: bool,
+    /// What the game was started in, e.g. `en_US`
+    locale: String,
 }
 
 impl ActiveGameContext {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         launch_id: &str,
         game_path: &str,
@@ -131,6 +134,7 @@ impl ActiveGameContext {
         offer: Option<OwnedOffer>,
         mode: LaunchMode,
         process: Child,
+        locale: &str,
     ) -> Self {
         Self {
             launch_id: launch_id.to_owned(),
@@ -142,6 +146,7 @@ impl ActiveGameContext {
             cloud_saves,
             process,
             started: false,
+            locale: locale.to_owned(),
         }
     }
 
@@ -197,7 +202,18 @@ pub async fn start_game(
             };
 
             if !offer.is_installed().await {
-                return Err(LaunchError::NotInstalled(offer.offer_id().clone()));
+                // The registry may have lost track of a legacy install, Maxima hasn't
+                let registered = match offer.register_legacy_install().await {
+                    Ok(registered) => registered,
+                    Err(err) => {
+                        warn!("Failed to register {}: {}", offer.offer_id(), err);
+                        false
+                    }
+                };
+
+                if !registered || !offer.is_installed().await {
+                    return Err(LaunchError::NotInstalled(offer.offer_id().clone()));
+                }
             }
 
             let content_id = offer.offer().content_id().to_owned();
@@ -330,6 +346,13 @@ pub async fn start_game(
     let user = maxima.local_user().await?;
     let launch_id = Uuid::new_v4().to_string();
 
+    // The language the game was installed in, the user's otherwise
+    let locale = match &offer {
+        Some(offer) => offer.game_locale().await,
+        None => None,
+    }
+    .unwrap_or_else(|| maxima.locale.full_str().to_owned());
+
     child
         .current_dir(PathBuf::from(path).safe_parent()?)
         .env("MXLaunchId", launch_id.to_owned())
@@ -338,7 +361,7 @@ pub async fn start_game(
         .env("EAEntitlementSource", "EA")
         .env("EAExternalSource", "EA")
         .env("EAFreeTrialGame", "false")
-        .env("EAGameLocale", maxima.locale.full_str())
+        .env("EAGameLocale", &locale)
         .env("EAGenericAuthToken", access_token.to_owned())
         .env("EALaunchCode", "unavailable")
         .env("EALaunchOwner", "EA")
@@ -392,6 +415,7 @@ pub async fn start_game(
         offer,
         mode,
         child,
+        &locale,
     ));
 
     Ok(())
@@ -1,7 +1,6 @@
 use super::{
-    auth::storage::LockedAuthStorage,
     locale::Locale,
-    manifest::{self, GameManifest, ManifestError, MANIFEST_RELATIVE_PATH},
+    manifest::{self, pre_dip, GameManifest, ManifestError},
     service_layer::{
         ServiceGameProductType, ServiceGetLegacyCatalogDefsRequestBuilder,
         ServiceGetPreloadedOwnedGamesRequest, ServiceGetPreloadedOwnedGamesRequestBuilder,
@@ -11,16 +10,26 @@ use super::{
         SERVICE_REQUEST_GETPRELOADEDOWNEDGAMES,
     },
 };
+use crate::content::{
+    installed::InstalledGame,
+    manager::{ContentManager, ContentManagerError},
+};
 #[cfg(unix)]
 use crate::unix::fs::case_insensitive_path;
-use crate::util::native::{NativeError, SafeStr};
-use crate::util::registry::{parse_partial_registry_path, parse_registry_path, RegistryError};
+use crate::util::native::{NativeError, SafeParent, SafeStr};
+use crate::util::registry::{
+    parse_partial_registry_path, parse_registry_path, remove_install_key, write_install_paths,
+    RegistryError,
+};
 use derive_getters::Getters;
+use log::{info, warn};
 use std::{collections::HashMap, path::PathBuf, time::SystemTimeError};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum LibraryError {
+    #[error(transparent)]
+    ContentManager(#[from] ContentManagerError),
     #[error(transparent)]
     Manifest(#[from] ManifestError),
     #[error(transparent)]
@@ -87,22 +96,31 @@ impl OwnedOffer {
     }
 
     pub async fn execute_path(&self, trial: bool) -> Result<PathBuf, LibraryError> {
-        let manifest = match self.local_manifest().await? {
-            Some(manifest) => manifest,
-            None => return Err(LibraryError::NoManifest(self.slug.clone())),
-        };
+        let manifest = self.local_manifest().await?;
 
-        let path = if let Some(path) = manifest.execute_path(trial) {
+        let path = if let Some(path) = manifest.and_then(|x| x.execute_path(trial)) {
             &Some(path)
         } else {
             self.offer.execute_path_override()
         };
 
         if let Some(path) = path {
-            Ok(parse_registry_path(path).await?)
-        } else {
-            Err(LibraryError::NoPath(self.slug.clone()))
+            return Ok(parse_registry_path(path).await?);
         }
+
+        // Some legacy offers don't say either, the executable has to be found
+        let install_path = self.install_path().await?;
+        let names = [self.offer.display_name().to_owned(), self.slug.clone()];
+        let discovered = tokio::task::spawn_blocking(move || {
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            pre_dip::discover_executable(&install_path, &names)
+        })
+        .await;
+
+        discovered
+            .ok()
+            .flatten()
+            .ok_or(LibraryError::NoPath(self.slug.clone()))
     }
 
     pub async fn installed_version(&self) -> Result<String, LibraryError> {
@@ -123,35 +141,112 @@ impl OwnedOffer {
     }
 
     pub async fn local_manifest(&self) -> Result<Option<Box<dyn GameManifest>>, ManifestError> {
-        let path = if self
+        let install_check = self
             .offer
             .install_check_override()
             .as_ref()
-            .ok_or(ManifestError::NoInstallPath(self.slug.clone()))?
-            .contains("installerdata.xml")
-        {
+            .ok_or(ManifestError::NoInstallPath(self.slug.clone()))?;
+
+        if install_check.contains("installerdata.xml") {
             let ic_path = PathBuf::from(self.install_check_path().await?);
             #[cfg(unix)]
             let ic_path = case_insensitive_path(ic_path);
-            ic_path
-        } else {
-            let path = PathBuf::from(
-                parse_partial_registry_path(
-                    &self
-                        .offer
-                        .install_check_override()
-                        .as_ref()
-                        .ok_or(ManifestError::NoInstallPath(self.slug.clone()))?,
-                )
+            return Ok(Some(manifest::read(ic_path).await?));
+        }
+
+        let path = PathBuf::from(
+            parse_partial_registry_path(install_check)
                 .await?
                 .safe_str()?
                 .to_owned(),
-            );
+        );
+
+        // Legacy packages may come without one
+        Ok(Some(manifest::read_installed(&path).await?))
+    }
 
-            path.join(MANIFEST_RELATIVE_PATH)
+    /// The folder the game is installed to, going by its install check
+    pub async fn install_path(&self) -> Result<PathBuf, LibraryError> {
+        let install_check = self
+            .offer
+            .install_check_override()
+            .as_ref()
+            .ok_or(ManifestError::NoInstallPath(self.slug.clone()))?;
+
+        if install_check.contains("installerdata.xml") {
+            // <install path>/__Installer/installerdata.xml
+            let manifest = PathBuf::from(self.install_check_path().await?);
+            return Ok(manifest.safe_parent()?.safe_parent()?.to_path_buf());
+        }
+
+        Ok(parse_partial_registry_path(install_check).await?)
+    }
+
+    /// Deletes the game's files and the registry key touchup created for it. With
+    /// `keep_user_files`, saves and anything else that isn't part of the build stays.
+    pub async fn uninstall(
+        &self,
+        content_manager: &mut ContentManager,
+        keep_user_files: bool,
+    ) -> Result<(), LibraryError> {
+        let path = self.install_path().await.ok();
+        content_manager
+            .uninstall(self.offer_id(), path, keep_user_files)
+            .await?;
+
+        if let Some(install_check) = self.offer.install_check_override() {
+            if let Err(err) = remove_install_key(install_check).await {
+                warn!("Failed to remove registry key of {}: {}", self.slug, err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The language Maxima installed the game in, `None` if it didn't install it
+    pub async fn game_locale(&self) -> Option<String> {
+        match InstalledGame::load(self.offer_id()).await {
+            Ok(installed) => installed.map(|x| x.game_locale()),
+            Err(err) => {
+                warn!("Failed to load install record of {}: {}", self.slug, err);
+                None
+            }
+        }
+    }
+
+    /// Registry paths the offer finds the game by, the ones touchup sets up for DiP games
+    fn registry_paths(&self) -> Vec<String> {
+        [
+            self.offer.install_check_override(),
+            self.offer.execute_path_override(),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|x| x.starts_with('['))
+        .cloned()
+        .collect()
+    }
+
+    /// Legacy builds have no touchup telling the registry where they are, this points the
+    /// keys the offer reads at the installed build instead. Returns whether it had to.
+    pub async fn register_legacy_install(&self) -> Result<bool, LibraryError> {
+        let installed = match InstalledGame::load(self.offer_id()).await? {
+            Some(installed) => installed,
+            None => return Ok(false),
         };
 
-        Ok(Some(manifest::read(path).await?))
+        let manifest = manifest::read_installed(installed.path()).await?;
+        if manifest.registers_install() {
+            return Ok(false);
+        }
+
+        info!(
+            "Registering legacy install of {} at {:?}",
+            self.slug,
+            installed.path()
+        );
+        write_install_paths(&self.registry_paths(), installed.path()).await?;
+        Ok(true)
     }
 
     pub fn offer_id(&self) -> &String {
@@ -294,14 +389,17 @@ pub struct GameLibrary {
     service_layer: ServiceLayerClient,
     library: Vec<OwnedTitle>,
     last_request: u64,
+    /// Language `library` was requested in, a different one on the service layer requests it again
+    locale: Locale,
 }
 
 impl GameLibrary {
-    pub async fn new(auth: LockedAuthStorage) -> Self {
+    pub async fn new(service_layer: ServiceLayerClient) -> Self {
         Self {
-            service_layer: ServiceLayerClient::new(auth),
+            service_layer,
             library: Vec::new(),
             last_request: 0,
+            locale: Locale::default(),
         }
     }
 
@@ -350,7 +448,7 @@ impl GameLibrary {
             .duration_since(std::time::UNIX_EPOCH)?
             .as_secs();
 
-        if now - self.last_request > 1200 {
+        if now - self.last_request > 1200 || self.locale != self.service_layer.locale() {
             self.request_owned_games().await?;
         }
 
@@ -358,7 +456,8 @@ impl GameLibrary {
     }
 
     async fn request_owned_games(&mut self) -> Result<(), LibraryError> {
-        self.request_page_concurrent(Locale::EnUs, 1).await?;
+        let locale = self.service_layer.locale();
+        self.request_page_concurrent(locale, 1).await?;
 
         Ok(())
     }
@@ -398,7 +497,7 @@ impl GameLibrary {
                 SERVICE_REQUEST_GETLEGACYCATALOGDEFS,
                 ServiceGetLegacyCatalogDefsRequestBuilder::default()
                     .offer_ids(offer_ids)
-                    .locale(locale)
+                    .locale(locale.clone())
                     .build()
                     .unwrap(),
             )
@@ -431,6 +530,7 @@ impl GameLibrary {
 
         self.library = titles;
         self.last_request = now;
+        self.locale = locale;
         Ok(())
     }
 
@@ -1,20 +1,228 @@
+#[cfg(unix)]
+use log::warn;
 use serde::{Deserialize, Serialize};
+use tokio::fs;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::util::native::{maxima_dir, NativeError};
+
+const OVERRIDE_FILE: &str = "locale.json";
+
+/// Languages EA ships games and store text in
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Locale {
+    ArSa,
+    CsCz,
+    DaDk,
+    DeDe,
+    EnGb,
+    #[default]
     EnUs,
+    EsEs,
+    EsMx,
+    FiFi,
+    FrFr,
+    HuHu,
+    ItIt,
+    JaJp,
+    KoKr,
+    NlNl,
+    NoNo,
+    PlPl,
+    PtBr,
+    PtPt,
+    RuRu,
+    SvSe,
+    ThTh,
+    TrTr,
+    ZhCn,
+    ZhTw,
 }
 
 impl Locale {
+    /// Every locale. Where a language has more than one, the one a bare language code
+    /// should pick comes first.
+    pub const ALL: &'static [Locale] = &[
+        Locale::ArSa,
+        Locale::CsCz,
+        Locale::DaDk,
+        Locale::DeDe,
+        Locale::EnUs,
+        Locale::EnGb,
+        Locale::EsEs,
+        Locale::EsMx,
+        Locale::FiFi,
+        Locale::FrFr,
+        Locale::HuHu,
+        Locale::ItIt,
+        Locale::JaJp,
+        Locale::KoKr,
+        Locale::NlNl,
+        Locale::NoNo,
+        Locale::PlPl,
+        Locale::PtBr,
+        Locale::PtPt,
+        Locale::RuRu,
+        Locale::SvSe,
+        Locale::ThTh,
+        Locale::TrTr,
+        Locale::ZhCn,
+        Locale::ZhTw,
+    ];
+
     pub fn short_str(&self) -> &'static str {
         match self {
-            Locale::EnUs => "en",
+            Locale::ArSa => "ar",
+            Locale::CsCz => "cs",
+            Locale::DaDk => "da",
+            Locale::DeDe => "de",
+            Locale::EnGb | Locale::EnUs => "en",
+            Locale::EsEs | Locale::EsMx => "es",
+            Locale::FiFi => "fi",
+            Locale::FrFr => "fr",
+            Locale::HuHu => "hu",
+            Locale::ItIt => "it",
+            Locale::JaJp => "ja",
+            Locale::KoKr => "ko",
+            Locale::NlNl => "nl",
+            Locale::NoNo => "no",
+            Locale::PlPl => "pl",
+            Locale::PtBr | Locale::PtPt => "pt",
+            Locale::RuRu => "ru",
+            Locale::SvSe => "sv",
+            Locale::ThTh => "th",
+            Locale::TrTr => "tr",
+            Locale::ZhCn | Locale::ZhTw => "zh",
         }
     }
 
     pub fn full_str(&self) -> &'static str {
         match self {
+            Locale::ArSa => "ar_SA",
+            Locale::CsCz => "cs_CZ",
+            Locale::DaDk => "da_DK",
+            Locale::DeDe => "de_DE",
+            Locale::EnGb => "en_GB",
             Locale::EnUs => "en_US",
+            Locale::EsEs => "es_ES",
+            Locale::EsMx => "es_MX",
+            Locale::FiFi => "fi_FI",
+            Locale::FrFr => "fr_FR",
+            Locale::HuHu => "hu_HU",
+            Locale::ItIt => "it_IT",
+            Locale::JaJp => "ja_JP",
+            Locale::KoKr => "ko_KR",
+            Locale::NlNl => "nl_NL",
+            Locale::NoNo => "no_NO",
+            Locale::PlPl => "pl_PL",
+            Locale::PtBr => "pt_BR",
+            Locale::PtPt => "pt_PT",
+            Locale::RuRu => "ru_RU",
+            Locale::SvSe => "sv_SE",
+            Locale::ThTh => "th_TH",
+            Locale::TrTr => "tr_TR",
+            Locale::ZhCn => "zh_CN",
+            Locale::ZhTw => "zh_TW",
+        }
+    }
+
+    /// Parses `de_DE`, `de-DE`, `de_DE.UTF-8` and the like. A region EA doesn't have, or
+    /// none at all, falls back to the language's main one, e.g. `de_AT` is [`Locale::DeDe`].
+    pub fn from_code(code: &str) -> Option<Self> {
+        let code = code.split(['.', '@']).next()?.trim().replace('-', "_");
+        if let Some(locale) = Self::ALL
+            .iter()
+            .find(|x| x.full_str().eq_ignore_ascii_case(&code))
+        {
+            return Some(locale.clone());
+        }
+
+        let language = code.split('_').next()?.to_lowercase();
+        let language = match language.as_str() {
+            "nb" | "nn" => "no",
+            language => language,
+        };
+
+        Self::ALL
+            .iter()
+            .find(|x| x.short_str() == language)
+            .cloned()
+    }
+
+    /// The system's language, if EA has it
+    pub fn system() -> Option<Self> {
+        sys_locale::get_locale().and_then(|x| Self::from_code(&x))
+    }
+
+    /// The language of the wine prefix games run in, if EA has it
+    #[cfg(unix)]
+    pub async fn wine_prefix() -> Option<Self> {
+        match crate::unix::wine::wine_prefix_locale().await {
+            Ok(code) => code.and_then(|x| Self::from_code(&x)),
+            Err(err) => {
+                warn!("Failed to read the wine prefix locale: {}", err);
+                None
+            }
+        }
+    }
+
+    /// What to use when the user hasn't picked anything. Games see the prefix's language
+    /// under wine, so that goes before the system's.
+    pub async fn detect() -> Self {
+        #[cfg(unix)]
+        if let Some(locale) = Self::wine_prefix().await {
+            return locale;
+        }
+
+        Self::system().unwrap_or_default()
+    }
+}
+
+impl std::fmt::Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.full_str())
+    }
+}
+
+/// The language the user picked, `None` to go with [`Locale::detect`]
+pub(crate) async fn load_override() -> Option<Locale> {
+    let file = maxima_dir().ok()?.join(OVERRIDE_FILE);
+    let data = fs::read_to_string(file).await.ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+pub(crate) async fn save_override(locale: Option<&Locale>) -> Result<(), NativeError> {
+    let file = maxima_dir()?.join(OVERRIDE_FILE);
+    match locale {
+        Some(locale) => fs::write(file, serde_json::to_string(locale)?).await?,
+        None if file.exists() => fs::remove_file(file).await?,
+        None => {}
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_code() {
+        assert_eq!(Locale::from_code("de_DE"), Some(Locale::DeDe));
+        assert_eq!(Locale::from_code("pt-PT"), Some(Locale::PtPt));
+        assert_eq!(Locale::from_code("en_GB.UTF-8"), Some(Locale::EnGb));
+        assert_eq!(Locale::from_code("zh_tw"), Some(Locale::ZhTw));
+        assert_eq!(Locale::from_code("de_AT"), Some(Locale::DeDe));
+        assert_eq!(Locale::from_code("en_AU"), Some(Locale::EnUs));
+        assert_eq!(Locale::from_code("es"), Some(Locale::EsEs));
+        assert_eq!(Locale::from_code("nb_NO"), Some(Locale::NoNo));
+        assert_eq!(Locale::from_code("C"), None);
+        assert_eq!(Locale::from_code(""), None);
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        for locale in Locale::ALL {
+            assert_eq!(Locale::from_code(locale.full_str()).as_ref(), Some(locale));
         }
     }
 }
@@ -2,9 +2,13 @@
 
 use std::path::PathBuf;
 
-use crate::{core::manifest::ManifestError, util::native::platform_path};
+use crate::{
+    core::manifest::{bytes_to_string, ManifestError},
+    util::native::platform_path,
+};
 use derive_getters::Getters;
-use serde::Deserialize;
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
 
 macro_rules! dip_type {
     (
@@ -26,7 +30,7 @@ macro_rules! dip_type {
         paste::paste! {
             // Main struct definition
             $(#[$message_attr])*
-            #[derive(Default, Debug, Clone, Deserialize, PartialEq, Getters)]
+            #[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Getters)]
             #[serde(rename_all = "camelCase")]
             pub struct [<DiP $message_name>] {
                 $(
@@ -43,6 +47,30 @@ macro_rules! dip_type {
     }
 }
 
+dip_type!(
+    /// Text in one language
+    LocalizedString;
+    attr {
+        locale: String,
+    },
+    data {
+        #[serde(rename = "$text", default)]
+        value: String,
+    }
+);
+
+dip_type!(
+    /// Paths relative to the install folder, `*` and `?` match like in globs
+    FileSet;
+    attr {},
+    data {
+        #[serde(default)]
+        include: Vec<String>,
+        #[serde(default)]
+        exclude: Vec<String>,
+    }
+);
+
 dip_type!(
     Launcher;
     attr {
@@ -50,9 +78,16 @@ dip_type!(
     },
     data {
         file_path: String,
+        #[serde(default)]
+        parameters: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
         execute_elevated: Option<bool>,
         #[serde(default)]
+        requires64BitOS: bool,
+        #[serde(default)]
         trial: bool,
+        #[serde(default)]
+        name: Vec<DiPLocalizedString>,
     }
 );
 
@@ -107,6 +142,133 @@ dip_type!(
     }
 );
 
+dip_type!(
+    ContentIDs;
+    attr {},
+    data {
+        #[serde(default)]
+        contentID: Vec<String>,
+    }
+);
+
+dip_type!(
+    GameTitles;
+    attr {},
+    data {
+        #[serde(default)]
+        gameTitle: Vec<DiPLocalizedString>,
+    }
+);
+
+dip_type!(
+    /// A language the build ships, and the files only it needs
+    Locale;
+    attr {
+        name: String,
+    },
+    data {
+        #[serde(default)]
+        fileSet: DiPFileSet,
+    }
+);
+
+dip_type!(
+    SupportedLocales;
+    attr {},
+    data {
+        #[serde(default)]
+        locale: Vec<DiPLocale>,
+    }
+);
+
+dip_type!(
+    /// Content that can be left out, e.g. high resolution textures
+    Component;
+    attr {
+        id: String,
+        #[serde(default)]
+        installByDefault: bool,
+    },
+    data {
+        #[serde(default)]
+        name: Vec<DiPLocalizedString>,
+        #[serde(default)]
+        fileSet: DiPFileSet,
+    }
+);
+
+dip_type!(
+    OptionalComponents;
+    attr {},
+    data {
+        #[serde(default)]
+        component: Vec<DiPComponent>,
+    }
+);
+
+dip_type!(
+    /// DLC shipped with the base build, only installed for those who own `contentID`
+    Dlc;
+    attr {
+        contentID: String,
+    },
+    data {
+        #[serde(default)]
+        name: Vec<DiPLocalizedString>,
+        #[serde(default)]
+        fileSet: DiPFileSet,
+    }
+);
+
+dip_type!(
+    Dlcs;
+    attr {},
+    data {
+        #[serde(default)]
+        dlc: Vec<DiPDlc>,
+    }
+);
+
+dip_type!(
+    /// Something touchup installs before the game can run, e.g. a Visual C++ runtime
+    Redistributable;
+    attr {},
+    data {
+        file_path: String,
+        #[serde(default)]
+        parameters: String,
+        /// Registry value or file that exists once it's installed
+        #[serde(skip_serializing_if = "Option::is_none")]
+        install_check: Option<String>,
+        #[serde(default)]
+        requires64BitOS: bool,
+    }
+);
+
+dip_type!(
+    Redistributables;
+    attr {},
+    data {
+        #[serde(default)]
+        redistributable: Vec<DiPRedistributable>,
+    }
+);
+
+dip_type!(
+    /// What EA's launchers run to uninstall the game, and what touchup left behind
+    Uninstall;
+    attr {},
+    data {
+        #[serde(default)]
+        file_path: String,
+        #[serde(default)]
+        parameters: String,
+        /// Registry keys touchup created
+        #[serde(default)]
+        registryKey: Vec<String>,
+    }
+);
+
 dip_type!(
     Touchup;
     attr {},
@@ -134,6 +296,48 @@ impl DiPTouchup {
     }
 }
 
+/// What games without the user's language get touched up in
+const FALLBACK_LOCALE: &str = "en_US";
+
+/// Manifests come from Windows, their paths don't care about case
+const FILE_SET_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+fn normalize_path(path: &str) -> String {
+    remove_leading_slash(&path.replace('\\', "/")).to_owned()
+}
+
+impl DiPFileSet {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty()
+    }
+
+    /// Whether `path`, relative to the install folder, belongs to the set
+    pub fn matches(&self, path: &str) -> bool {
+        let path = normalize_path(path);
+        let matches = |patterns: &[String]| {
+            patterns.iter().any(|x| {
+                Pattern::new(&normalize_path(x))
+                    .is_ok_and(|pattern| pattern.matches_with(&path, FILE_SET_OPTIONS))
+            })
+        };
+
+        matches(&self.include) && !matches(&self.exclude)
+    }
+}
+
+/// The string in `locale`, falling back to the first one listed
+pub(crate) fn localized<'a>(strings: &'a [DiPLocalizedString], locale: &str) -> Option<&'a str> {
+    strings
+        .iter()
+        .find(|x| x.attr_locale.eq_ignore_ascii_case(locale))
+        .or(strings.first())
+        .map(|x| x.value.as_str())
+}
+
 dip_type!(
     Manifest;
     attr {
@@ -141,8 +345,22 @@ dip_type!(
     },
     data {
         buildMetaData: DiPBuildMetaData,
+        #[serde(default)]
+        contentIDs: DiPContentIDs,
+        #[serde(default)]
+        gameTitles: DiPGameTitles,
+        #[serde(default)]
+        supportedLocales: DiPSupportedLocales,
+        #[serde(default)]
+        optionalComponents: DiPOptionalComponents,
+        #[serde(default)]
+        dlcs: DiPDlcs,
         runtime: DiPRuntime,
+        #[serde(default)]
+        redistributables: DiPRedistributables,
         touchup: DiPTouchup,
+        #[serde(default)]
+        uninstall: DiPUninstall,
     }
 );
 
@@ -154,31 +372,65 @@ dip_type!(
     }
 );
 
-/// https://www.reddit.com/r/rust/comments/11co87m/comment/ja4sy88
-fn bytes_to_string(bytes: Vec<u8>) -> Option<String> {
-    if let Ok(v) = String::from_utf8(bytes.clone()) {
-        return Some(v);
+impl DiPManifest {
+    pub async fn read(path: &PathBuf) -> Result<Self, ManifestError> {
+        Self::from_bytes(tokio::fs::read(path).await?)
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, ManifestError> {
+        let string = bytes_to_string(bytes).ok_or(ManifestError::Decode)?;
+        Ok(quick_xml::de::from_str(
+            string.trim_start_matches('\u{feff}'),
+        )?)
     }
 
-    let u16_bytes: Vec<u16> = bytes
-        .chunks_exact(2)
-        .into_iter()
-        .map(|a| u16::from_ne_bytes([a[0], a[1]]))
-        .collect();
+    pub fn to_xml(&self) -> Result<String, ManifestError> {
+        Ok(quick_xml::se::to_string(self)?)
+    }
 
-    if let Ok(v) = String::from_utf16(&u16_bytes) {
-        return Some(v);
+    /// Locales the build ships, e.g. `en_US`
+    pub fn locales(&self) -> Vec<&str> {
+        self.supportedLocales
+            .locale
+            .iter()
+            .map(|x| x.attr_name.as_str())
+            .collect()
     }
 
-    None
-}
+    /// Files only `locale` needs, `None` if the build doesn't ship it
+    pub fn locale_files(&self, locale: &str) -> Option<&DiPFileSet> {
+        self.supportedLocales
+            .locale
+            .iter()
+            .find(|x| x.attr_name.eq_ignore_ascii_case(locale))
+            .map(|x| &x.fileSet)
+    }
 
-impl DiPManifest {
-    pub async fn read(path: &PathBuf) -> Result<Self, ManifestError> {
-        let bytes = tokio::fs::read(path).await?;
-        let string = bytes_to_string(bytes).ok_or(ManifestError::Decode)?;
+    /// `locale` if the build ships it, otherwise en_US or whatever it lists first
+    pub fn supported_locale<'a>(&'a self, locale: &'a str) -> &'a str {
+        let locales = self.locales();
+        if locales.is_empty() {
+            return locale;
+        }
+
+        let find = |wanted: &str| {
+            locales
+                .iter()
+                .find(|x| x.eq_ignore_ascii_case(wanted))
+                .copied()
+        };
+        find(locale)
+            .or_else(|| find(FALLBACK_LOCALE))
+            .unwrap_or(locales[0])
+    }
 
-        Ok(quick_xml::de::from_str(&string)?)
+    pub fn content_ids(&self) -> &[String] {
+        &self.contentIDs.contentID
+    }
+
+    /// The game's name in `locale`, or whichever language the manifest lists first
+    pub fn title(&self, locale: &str) -> Option<&str> {
+        localized(&self.gameTitles.gameTitle, locale)
     }
 
     pub fn execute_path(&self, trial: bool) -> Option<String> {
@@ -191,7 +443,11 @@ impl DiPManifest {
     }
 
     #[cfg(unix)]
-    pub async fn run_touchup(&self, install_path: &PathBuf) -> Result<(), ManifestError> {
+    pub async fn run_touchup(
+        &self,
+        install_path: &PathBuf,
+        locale: &str,
+    ) -> Result<(), ManifestError> {
         use crate::{
             core::launch::mx_linux_setup,
             unix::{
@@ -205,7 +461,7 @@ impl DiPManifest {
         let install_path = PathBuf::from(remove_trailing_slash(
             install_path.to_str().ok_or(ManifestError::Decode)?,
         ));
-        let args = self.collect_touchup_args(&install_path)?;
+        let args = self.collect_touchup_args(&install_path, locale)?;
         let path = install_path.join(&self.touchup.path());
         let path = case_insensitive_path(path);
         run_wine_command(path, Some(args), None, true, CommandType::Run).await?;
@@ -215,11 +471,15 @@ impl DiPManifest {
     }
 
     #[cfg(windows)]
-    pub async fn run_touchup(&self, install_path: &PathBuf) -> Result<(), ManifestError> {
+    pub async fn run_touchup(
+        &self,
+        install_path: &PathBuf,
+        locale: &str,
+    ) -> Result<(), ManifestError> {
         use crate::util::native::NativeError;
         use tokio::process::Command;
 
-        let args = self.collect_touchup_args(install_path)?;
+        let args = self.collect_touchup_args(install_path, locale)?;
         let path = install_path.join(&self.touchup.path());
 
         let mut binding = Command::new(path);
@@ -235,10 +495,16 @@ impl DiPManifest {
         Ok(())
     }
 
-    fn collect_touchup_args(&self, install_path: &PathBuf) -> Result<Vec<PathBuf>, ManifestError> {
+    fn collect_touchup_args(
+        &self,
+        install_path: &PathBuf,
+        locale: &str,
+    ) -> Result<Vec<PathBuf>, ManifestError> {
+        let locale = self.supported_locale(locale);
+
         let mut args = Vec::new();
         for arg in self.touchup.parameters.split(" ") {
-            let arg = arg.replace("{locale}", "en_US").replace(
+            let arg = arg.replace("{locale}", locale).replace(
                 "\"{installLocation}\"",
                 platform_path(
                     remove_trailing_backslash(install_path.to_str().ok_or(ManifestError::Decode)?)
@@ -253,3 +519,162 @@ impl DiPManifest {
         Ok(args)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use quick_xml::events::Event;
+
+    use super::*;
+    use crate::util::testing::fixtures;
+
+    /// Hand-written manifests in the layouts of DiP 2.2, 3.0 and 4.0. They only catch what the
+    /// model drops from XML we already know about, real `installerdata.xml` files still need
+    /// adding, see `testdata/README.md`.
+    fn corpus() -> Vec<(PathBuf, Vec<u8>)> {
+        fixtures("manifests")
+    }
+
+    fn parse(name: &str) -> DiPManifest {
+        let (_, bytes) = corpus()
+            .into_iter()
+            .find(|(path, _)| path.ends_with(name))
+            .unwrap();
+        DiPManifest::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let corpus = corpus();
+        assert!(!corpus.is_empty());
+
+        for (path, bytes) in corpus {
+            let manifest = DiPManifest::from_bytes(bytes)
+                .unwrap_or_else(|err| panic!("Failed to parse {:?}: {}", path, err));
+            let xml = manifest.to_xml().unwrap();
+            let reparsed = DiPManifest::from_bytes(xml.into_bytes())
+                .unwrap_or_else(|err| panic!("Failed to parse {:?} again: {}", path, err));
+
+            assert_eq!(manifest, reparsed, "{:?} changed in a round trip", path);
+        }
+    }
+
+    /// Every element and attribute in `xml` by path, e.g. `DiPManifest/runtime/launcher/@uid`
+    fn paths(xml: &str) -> BTreeSet<String> {
+        let mut reader = quick_xml::Reader::from_str(xml);
+        let mut stack = Vec::new();
+        let mut paths = BTreeSet::new();
+
+        loop {
+            let (element, empty) = match reader.read_event().unwrap() {
+                Event::Start(element) => (element, false),
+                Event::Empty(element) => (element, true),
+                Event::End(_) => {
+                    stack.pop();
+                    continue;
+                }
+                Event::Eof => break,
+                _ => continue,
+            };
+
+            stack.push(String::from_utf8_lossy(element.name().as_ref()).into_owned());
+            let path = stack.join("/");
+            for attr in element.attributes() {
+                let key = String::from_utf8_lossy(attr.unwrap().key.as_ref()).into_owned();
+                paths.insert(format!("{}/@{}", path, key));
+            }
+            paths.insert(path);
+
+            if empty {
+                stack.pop();
+            }
+        }
+
+        paths
+    }
+
+    #[test]
+    fn nothing_dropped() {
+        for (path, bytes) in corpus() {
+            let source = bytes_to_string(bytes.clone()).unwrap();
+            let xml = DiPManifest::from_bytes(bytes).unwrap().to_xml().unwrap();
+
+            let kept = paths(&xml);
+            let dropped: Vec<String> = paths(source.trim_start_matches('\u{feff}'))
+                .into_iter()
+                .filter(|x| !kept.contains(x))
+                .collect();
+            assert!(dropped.is_empty(), "{:?} drops {:?}", path, dropped);
+        }
+    }
+
+    #[test]
+    fn full_manifest() {
+        let manifest = parse("full.xml");
+
+        assert_eq!(manifest.content_ids(), ["1026023", "1026480"]);
+        assert_eq!(manifest.locales(), ["en_US", "de_DE", "ja_JP"]);
+        assert_eq!(manifest.title("ja_JP"), Some("バトルフィールド 1"));
+        assert_eq!(manifest.title("pl_PL"), Some("Battlefield™ 1"));
+
+        let german = manifest.locale_files("de_de").unwrap();
+        assert!(german.matches("Data/Win32/Loc/de/strings.bin"));
+        assert!(german.matches("data\\win32\\sound\\vo\\de\\chunk0.sb"));
+        assert!(!german.matches("Data/Win32/Sound/VO/de/chunk0.bak"));
+        assert!(!german.matches("Data/Win32/Loc/en/strings.bin"));
+        assert!(manifest
+            .locale_files("ja_JP")
+            .unwrap()
+            .matches("Data/Win32/Loc/ja/strings.bin"));
+        assert!(manifest.locale_files("fr_FR").is_none());
+        assert_eq!(manifest.supported_locale("de_de"), "de_DE");
+        assert_eq!(manifest.supported_locale("pl_PL"), "en_US");
+
+        let component = &manifest.optionalComponents.component[0];
+        assert_eq!(component.attr_id, "HDTextures");
+        assert!(!component.attr_installByDefault);
+        assert_eq!(manifest.dlcs.dlc.len(), 2);
+        assert_eq!(manifest.dlcs.dlc[1].attr_contentID, "1026483");
+
+        let redistributables = &manifest.redistributables.redistributable;
+        assert_eq!(redistributables.len(), 2);
+        assert!(redistributables[0].install_check.is_some());
+        assert!(redistributables[1].install_check.is_none());
+
+        assert_eq!(manifest.uninstall.registryKey.len(), 2);
+        assert_eq!(
+            manifest.execute_path(true).as_deref(),
+            Some(
+                "[HKEY_LOCAL_MACHINE\\SOFTWARE\\EA Games\\Battlefield 1\\Install Dir]bf1Trial.exe"
+            )
+        );
+    }
+
+    #[test]
+    fn missing_sections_default() {
+        let manifest = parse("minimal.xml");
+
+        assert!(manifest.content_ids().is_empty());
+        assert!(manifest.locales().is_empty());
+        assert_eq!(manifest.supported_locale("pl_PL"), "pl_PL");
+        assert!(manifest.title("en_US").is_none());
+        assert!(manifest.uninstall.file_path.is_empty());
+        assert_eq!(manifest.runtime.launcher[0].execute_elevated, None);
+        assert!(
+            !manifest
+                .buildMetaData
+                .featureFlags
+                .attr_enableDifferentialUpdate
+        );
+    }
+
+    #[test]
+    fn utf16_manifest() {
+        let manifest = parse("utf16.xml");
+
+        assert_eq!(manifest.content_ids(), ["1011583"]);
+        assert_eq!(manifest.locales(), ["en_US", "fr_FR"]);
+        assert_eq!(manifest.runtime.launcher[0].execute_elevated, Some(true));
+    }
+}
@@ -4,7 +4,7 @@ pub mod pre_dip;
 use dip::DiPManifest;
 use pre_dip::PreDiPManifest;
 use quick_xml::DeError;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -31,16 +31,47 @@ pub enum ManifestError {
 
 pub const MANIFEST_RELATIVE_PATH: &str = "__Installer/installerdata.xml";
 
+/// Manifests are UTF-8 or UTF-16, whichever the tool that wrote them liked.
+/// https://www.reddit.com/r/rust/comments/11co87m/comment/ja4sy88
+pub(crate) fn bytes_to_string(bytes: Vec<u8>) -> Option<String> {
+    if let Ok(v) = String::from_utf8(bytes.clone()) {
+        return Some(v);
+    }
+
+    let u16_bytes: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|a| u16::from_ne_bytes([a[0], a[1]]))
+        .collect();
+
+    if let Ok(v) = String::from_utf16(&u16_bytes) {
+        return Some(v);
+    }
+
+    None
+}
+
 #[async_trait::async_trait]
 pub trait GameManifest: Send + std::fmt::Debug {
-    async fn run_touchup(&self, install_path: &PathBuf) -> Result<(), ManifestError>;
+    /// Runs touchup for the game to start in `locale`, e.g. `en_US`
+    async fn run_touchup(&self, install_path: &PathBuf, locale: &str) -> Result<(), ManifestError>;
     fn execute_path(&self, trial: bool) -> Option<String>;
     fn version(&self) -> Option<String>;
+    /// Whether updates may only replace the files that changed
+    fn differential_update(&self) -> bool;
+    /// Whether touchup writes the registry keys the offer's install check reads. Maxima
+    /// writes them itself for games where it doesn't.
+    fn registers_install(&self) -> bool;
+    /// Locales the build ships, empty if it doesn't say
+    fn locales(&self) -> Vec<String>;
+    /// Whether the game can switch languages after installing, by running touchup again
+    fn language_change_supported(&self) -> bool;
+    /// Registry keys the game leaves behind, to delete when uninstalling
+    fn uninstall_keys(&self) -> Vec<String>;
 }
 #[async_trait::async_trait]
 impl GameManifest for DiPManifest {
-    async fn run_touchup(&self, install_path: &PathBuf) -> Result<(), ManifestError> {
-        self.run_touchup(install_path).await
+    async fn run_touchup(&self, install_path: &PathBuf, locale: &str) -> Result<(), ManifestError> {
+        self.run_touchup(install_path, locale).await
     }
 
     fn execute_path(&self, trial: bool) -> Option<String> {
@@ -50,21 +81,66 @@ impl GameManifest for DiPManifest {
     fn version(&self) -> Option<String> {
         self.version()
     }
+
+    fn differential_update(&self) -> bool {
+        self.buildMetaData
+            .featureFlags
+            .attr_enableDifferentialUpdate
+    }
+
+    fn registers_install(&self) -> bool {
+        true
+    }
+
+    fn locales(&self) -> Vec<String> {
+        self.locales().into_iter().map(str::to_owned).collect()
+    }
+
+    fn language_change_supported(&self) -> bool {
+        self.buildMetaData
+            .featureFlags
+            .attr_languageChangeSupportEnabled
+    }
+
+    fn uninstall_keys(&self) -> Vec<String> {
+        self.uninstall.registryKey.clone()
+    }
 }
 
 #[async_trait::async_trait]
 impl GameManifest for PreDiPManifest {
-    async fn run_touchup(&self, install_path: &PathBuf) -> Result<(), ManifestError> {
-        self.run_touchup(install_path).await
+    async fn run_touchup(&self, install_path: &PathBuf, locale: &str) -> Result<(), ManifestError> {
+        self.run_touchup(install_path, locale).await
     }
 
     fn execute_path(&self, _: bool) -> Option<String> {
-        None // pre-dip games don't have an exe field, most if not all just use info in the offer
+        // Most pre-dip games don't have one, and just use the info in the offer
+        self.execute_path()
     }
 
     fn version(&self) -> Option<String> {
         self.version()
     }
+
+    fn differential_update(&self) -> bool {
+        true
+    }
+
+    fn registers_install(&self) -> bool {
+        false
+    }
+
+    fn locales(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn language_change_supported(&self) -> bool {
+        false
+    }
+
+    fn uninstall_keys(&self) -> Vec<String> {
+        self.registry_keys()
+    }
 }
 
 pub async fn read(path: PathBuf) -> Result<Box<dyn GameManifest>, ManifestError> {
@@ -82,3 +158,18 @@ pub async fn read(path: PathBuf) -> Result<Box<dyn GameManifest>, ManifestError>
         pre_dip_attempt: pre_dip_attempt.unwrap_err().into(),
     })
 }
+
+/// Reads the manifest of the build at `install_path`. Legacy packages may come without
+/// one, they get an empty pre-DiP manifest with nothing to touch up. A missing
+/// `install_path` is still an error.
+pub async fn read_installed(install_path: &Path) -> Result<Box<dyn GameManifest>, ManifestError> {
+    let path = install_path.join(MANIFEST_RELATIVE_PATH);
+    #[cfg(unix)]
+    let path = crate::unix::fs::case_insensitive_path(path);
+
+    if install_path.is_dir() && !path.exists() {
+        return Ok(Box::new(PreDiPManifest::default()));
+    }
+
+    read(path).await
+}
@@ -1,9 +1,40 @@
 #![allow(non_snake_case)]
 
-use crate::{core::manifest::ManifestError, util::native::platform_path};
+use crate::{
+    core::manifest::{
+        bytes_to_string,
+        dip::{localized, DiPLocalizedString},
+        ManifestError,
+    },
+    util::{
+        native::platform_path,
+        registry::{write_reg_values, RegistryValue},
+    },
+};
 use derive_getters::Getters;
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// How deep [`discover_executable`] looks below the install folder
+const MAX_DISCOVERY_DEPTH: usize = 2;
+
+/// Parts of names of executables legacy packages ship next to the game that never start it
+const NON_GAME_EXECUTABLES: &[&str] = &[
+    "setup",
+    "install",
+    "uninst",
+    "redist",
+    "dxsetup",
+    "directx",
+    "crash",
+    "report",
+    "punkbuster",
+    "pbsvc",
+    "easyanticheat",
+    "touchup",
+    "activation",
+    "update",
+];
 
 macro_rules! predip_type {
     (
@@ -47,10 +78,62 @@ predip_type!(
     attr {},
     data {
         file_path: String,
+        #[serde(default)]
+        parameters: String,
+    }
+);
+
+predip_type!(
+    /// The executable that starts the game. Most legacy packages leave this to the offer.
+    Launcher;
+    attr {},
+    data {
+        file_path: String,
+        #[serde(default)]
         parameters: String,
     }
 );
 
+predip_type!(
+    ContentIDs;
+    attr {},
+    data {
+        #[serde(default)]
+        contentID: Vec<String>,
+    }
+);
+
+predip_type!(
+    GameTitles;
+    attr {},
+    data {
+        #[serde(default)]
+        gameTitle: Vec<DiPLocalizedString>,
+    }
+);
+
+predip_type!(
+    /// A string value the installer writes, `{installLocation}` and `{locale}` get replaced
+    RegistryValue;
+    attr {
+        key: String,
+        name: String,
+    },
+    data {
+        #[serde(rename = "$text", default)]
+        data: String,
+    }
+);
+
+predip_type!(
+    Registry;
+    attr {},
+    data {
+        #[serde(default)]
+        value: Vec<PreDiPRegistryValue>,
+    }
+);
+
 fn remove_leading_slash(path: &str) -> &str {
     path.strip_prefix('/').unwrap_or(path)
 }
@@ -70,43 +153,97 @@ predip_type!(
         manifestVersion: String,
     },
     data {
-        executable: PreDiPExecutable,
+        /// Run after installing like DiP's touchup, if there is one
+        #[serde(default)]
+        executable: Option<PreDiPExecutable>,
+        #[serde(default)]
+        launcher: Option<PreDiPLauncher>,
+        #[serde(default)]
+        contentIDs: PreDiPContentIDs,
+        #[serde(default)]
+        gameTitles: PreDiPGameTitles,
+        #[serde(default)]
+        registry: PreDiPRegistry,
     }
 );
 
-/// https://www.reddit.com/r/rust/comments/11co87m/comment/ja4sy88
-fn bytes_to_string(bytes: Vec<u8>) -> Option<String> {
-    if let Ok(v) = String::from_utf8(bytes.clone()) {
-        return Some(v);
+impl PreDiPManifest {
+    pub async fn read(path: &PathBuf) -> Result<Self, ManifestError> {
+        Self::from_bytes(tokio::fs::read(path).await?)
     }
 
-    let u16_bytes: Vec<u16> = bytes
-        .chunks_exact(2)
-        .into_iter()
-        .map(|a| u16::from_ne_bytes([a[0], a[1]]))
-        .collect();
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, ManifestError> {
+        let string = bytes_to_string(bytes).ok_or(ManifestError::Decode)?;
+        Ok(quick_xml::de::from_str(
+            string.trim_start_matches('\u{feff}'),
+        )?)
+    }
 
-    if let Ok(v) = String::from_utf16(&u16_bytes) {
-        return Some(v);
+    pub fn version(&self) -> Option<String> {
+        Some(self.attr_gameVersion.clone()).filter(|x| !x.is_empty())
     }
 
-    None
-}
+    pub fn execute_path(&self) -> Option<String> {
+        self.launcher
+            .as_ref()
+            .map(|x| x.file_path.clone())
+            .filter(|x| !x.is_empty())
+    }
 
-impl PreDiPManifest {
-    pub async fn read(path: &PathBuf) -> Result<Self, ManifestError> {
-        let bytes = tokio::fs::read(path).await?;
-        let string = bytes_to_string(bytes).ok_or(ManifestError::Decode)?;
+    pub fn content_ids(&self) -> &[String] {
+        &self.contentIDs.contentID
+    }
 
-        Ok(quick_xml::de::from_str(&string)?)
+    /// The game's name in `locale`, falling back to the first one listed
+    pub fn title(&self, locale: &str) -> Option<&str> {
+        localized(&self.gameTitles.gameTitle, locale)
     }
 
-    pub fn version(&self) -> Option<String> {
-        Some(self.attr_gameVersion.clone())
+    /// The registry values the manifest asks for, pointed at `install_path` and `locale`
+    pub fn registry_values(
+        &self,
+        install_path: &Path,
+        locale: &str,
+    ) -> Result<Vec<RegistryValue>, ManifestError> {
+        let install_location = windows_install_path(install_path)?;
+
+        Ok(self
+            .registry
+            .value
+            .iter()
+            .map(|x| RegistryValue {
+                key: x.attr_key.clone(),
+                name: x.attr_name.clone(),
+                data: x
+                    .data
+                    .replace("{locale}", locale)
+                    .replace("{installLocation}", &install_location),
+            })
+            .collect())
+    }
+
+    /// The keys [`PreDiPManifest::registry_values`] go to
+    pub fn registry_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = Vec::new();
+        for value in &self.registry.value {
+            if !keys.contains(&value.attr_key) {
+                keys.push(value.attr_key.clone());
+            }
+        }
+        keys
+    }
+
+    /// The touchup executable, if the package has one
+    fn touchup(&self) -> Option<&PreDiPExecutable> {
+        self.executable.as_ref().filter(|x| !x.file_path.is_empty())
     }
 
     #[cfg(unix)]
-    pub async fn run_touchup(&self, install_path: &PathBuf) -> Result<(), ManifestError> {
+    pub async fn run_touchup(
+        &self,
+        install_path: &PathBuf,
+        locale: &str,
+    ) -> Result<(), ManifestError> {
         use crate::{
             core::launch::mx_linux_setup,
             unix::{
@@ -120,9 +257,16 @@ impl PreDiPManifest {
         let install_path = PathBuf::from(remove_trailing_slash(
             install_path.to_str().ok_or(ManifestError::Decode)?,
         ));
-        let args = self.collect_touchup_args(&install_path)?;
+        write_reg_values(&self.registry_values(&install_path, locale)?).await?;
 
-        let path = install_path.join(remove_leading_slash(&self.executable.file_path));
+        let executable = match self.touchup() {
+            Some(executable) => executable,
+            None => return Ok(()),
+        };
+
+        let args = self.collect_touchup_args(executable, &install_path, locale)?;
+
+        let path = install_path.join(remove_leading_slash(&executable.file_path));
         let path = case_insensitive_path(path);
         run_wine_command(path, Some(args), None, true, CommandType::Run).await?;
 
@@ -131,12 +275,23 @@ impl PreDiPManifest {
     }
 
     #[cfg(windows)]
-    pub async fn run_touchup(&self, install_path: &PathBuf) -> Result<(), ManifestError> {
+    pub async fn run_touchup(
+        &self,
+        install_path: &PathBuf,
+        locale: &str,
+    ) -> Result<(), ManifestError> {
         use crate::util::native::NativeError;
         use tokio::process::Command;
 
-        let args = self.collect_touchup_args(install_path)?;
-        let path = install_path.join(&self.executable.file_path);
+        write_reg_values(&self.registry_values(install_path, locale)?).await?;
+
+        let executable = match self.touchup() {
+            Some(executable) => executable,
+            None => return Ok(()),
+        };
+
+        let args = self.collect_touchup_args(executable, install_path, locale)?;
+        let path = install_path.join(remove_leading_slash(&executable.file_path));
 
         let mut binding = Command::new(path);
         let child = binding.args(args);
@@ -151,21 +306,214 @@ impl PreDiPManifest {
         Ok(())
     }
 
-    fn collect_touchup_args(&self, install_path: &PathBuf) -> Result<Vec<PathBuf>, ManifestError> {
+    fn collect_touchup_args(
+        &self,
+        executable: &PreDiPExecutable,
+        install_path: &Path,
+        locale: &str,
+    ) -> Result<Vec<PathBuf>, ManifestError> {
+        let install_location = windows_install_path(install_path)?;
+
         let mut args = Vec::new();
-        for arg in self.executable.parameters.split(" ") {
-            let arg = arg.replace("{locale}", "en_US").replace(
-                "\"{installLocation}\"",
-                platform_path(
-                    remove_trailing_backslash(install_path.to_str().ok_or(ManifestError::Decode)?)
-                        .replace("/", "\\"),
-                )
-                .to_str()
-                .ok_or(ManifestError::Decode)?,
-            );
+        for arg in executable.parameters.split(" ") {
+            let arg = arg
+                .replace("{locale}", locale)
+                .replace("\"{installLocation}\"", &install_location);
 
             args.push(PathBuf::from(arg));
         }
         Ok(args)
     }
 }
+
+/// `install_path` the way Windows programs see it, without a trailing backslash
+fn windows_install_path(install_path: &Path) -> Result<String, ManifestError> {
+    Ok(platform_path(
+        remove_trailing_backslash(install_path.to_str().ok_or(ManifestError::Decode)?)
+            .replace("/", "\\"),
+    )
+    .to_str()
+    .ok_or(ManifestError::Decode)?
+    .to_owned())
+}
+
+fn simple_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Guesses the executable that starts a legacy game nothing names one for: the largest
+/// `.exe` near the top of `install_path`, preferring ones named like one of `names`
+pub fn discover_executable(install_path: &Path, names: &[&str]) -> Option<PathBuf> {
+    let names: Vec<String> = names
+        .iter()
+        .map(|x| simple_name(x))
+        .filter(|x| !x.is_empty())
+        .collect();
+
+    let mut best: Option<(bool, u64, PathBuf)> = None;
+    let mut pending = vec![(install_path.to_path_buf(), 0)];
+    while let Some((dir, depth)) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_lowercase();
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+
+            if metadata.is_dir() {
+                if depth < MAX_DISCOVERY_DEPTH && !file_name.starts_with("__installer") {
+                    pending.push((entry.path(), depth + 1));
+                }
+                continue;
+            }
+
+            let stem = match file_name.strip_suffix(".exe") {
+                Some(stem) => stem,
+                None => continue,
+            };
+            if NON_GAME_EXECUTABLES.iter().any(|x| stem.contains(x)) {
+                continue;
+            }
+
+            let stem = simple_name(stem);
+            let named = !stem.is_empty()
+                && names
+                    .iter()
+                    .any(|name| name.contains(&stem) || stem.contains(name.as_str()));
+
+            let better = match &best {
+                Some((best_named, best_size, _)) => {
+                    (named, metadata.len()) > (*best_named, *best_size)
+                }
+                None => true,
+            };
+            if better {
+                best = Some((named, metadata.len(), entry.path()));
+            }
+        }
+    }
+
+    best.map(|x| x.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::{fixtures, TempDir};
+
+    fn parse(name: &str) -> PreDiPManifest {
+        let (_, bytes) = fixtures("pre_dip")
+            .into_iter()
+            .find(|(path, _)| path.ends_with(name))
+            .unwrap();
+        PreDiPManifest::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn full_manifest() {
+        let manifest = parse("legacy.xml");
+
+        assert_eq!(manifest.version().as_deref(), Some("1.0.0.2"));
+        assert_eq!(manifest.content_ids(), ["70621", "70622"]);
+        assert_eq!(manifest.title("de_DE"), Some("Beispiel Altspiel"));
+        assert_eq!(manifest.title("fr_FR"), Some("Sample Legacy Game"));
+        assert!(manifest
+            .execute_path()
+            .is_some_and(|x| x.ends_with("]sample.exe")));
+        assert_eq!(
+            manifest.touchup().map(|x| x.file_path.as_str()),
+            Some("/__Installer/Touchup.exe")
+        );
+
+        let install_path = Path::new("/games/sample");
+        let install_location = windows_install_path(install_path).unwrap();
+        let values = manifest.registry_values(install_path, "de_DE").unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0].name, "Install Dir");
+        assert_eq!(values[0].data, format!("{}\\", install_location));
+        assert_eq!(values[1].data, "de_DE");
+        assert_eq!(
+            manifest.registry_keys(),
+            ["HKEY_LOCAL_MACHINE\\SOFTWARE\\EA Games\\Sample Legacy Game"]
+        );
+    }
+
+    #[test]
+    fn missing_sections_default() {
+        let manifest = parse("minimal.xml");
+
+        assert!(manifest.touchup().is_none());
+        assert!(manifest.execute_path().is_none());
+        assert!(manifest.content_ids().is_empty());
+        assert!(manifest.title("en_US").is_none());
+        assert!(manifest.registry_keys().is_empty());
+        assert!(manifest
+            .registry_values(Path::new("/games/sample"), "en_US")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn utf16_manifest() {
+        let manifest = parse("utf16.xml");
+
+        assert_eq!(manifest.version().as_deref(), Some("1.1.0.0"));
+        assert_eq!(manifest.content_ids(), ["71001"]);
+        assert_eq!(manifest.title("en_US"), Some("Sample Legacy Game™"));
+        assert_eq!(manifest.registry_keys().len(), 1);
+    }
+
+    #[test]
+    fn touchup_args() {
+        let manifest = parse("legacy.xml");
+        let install_path = Path::new("/games/sample");
+        let install_location = windows_install_path(install_path).unwrap();
+
+        let args = manifest
+            .collect_touchup_args(manifest.touchup().unwrap(), install_path, "de_DE")
+            .unwrap();
+        assert_eq!(
+            args,
+            [
+                PathBuf::from("-locale"),
+                PathBuf::from("de_DE"),
+                PathBuf::from("-installPath"),
+                PathBuf::from(install_location),
+            ]
+        );
+    }
+
+    #[test]
+    fn discovers_executable() {
+        let dir = TempDir::new("discover");
+        dir.file("SampleGame.exe", 10);
+        dir.file("Launcher.exe", 100);
+        dir.file("Setup.exe", 1000);
+        dir.file("Support/DXSETUP.exe", 1000);
+        dir.file("__Installer/Touchup.exe", 1000);
+        dir.file("Bin/Win32/Deep/Huge.exe", 1000);
+
+        let found = |names: &[&str]| {
+            discover_executable(dir.path(), names)
+                .map(|x| x.strip_prefix(dir.path()).unwrap().to_path_buf())
+        };
+
+        // Named like the game beats bigger, installers and touchup never count
+        assert_eq!(
+            found(&["Sample Game", "sample-game"]),
+            Some(PathBuf::from("SampleGame.exe"))
+        );
+        assert_eq!(found(&[]), Some(PathBuf::from("Launcher.exe")));
+
+        let empty = TempDir::new("discover-empty");
+        assert!(discover_executable(empty.path(), &["Sample Game"]).is_none());
+    }
+}
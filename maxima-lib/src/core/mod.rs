@@ -51,7 +51,7 @@ use self::{
     cache::DynamicCache,
     launch::ActiveGameContext,
     library::GameLibrary,
-    locale::Locale,
+    locale::{self, Locale},
     service_layer::{
         ServiceAvatarListBuilder, ServiceAvatarListBuilderError, ServiceFriends,
         ServiceGetBasicPlayerRequestBuilder, ServiceGetMyFriendsRequestBuilder,
@@ -63,7 +63,10 @@ use self::{
     },
 };
 use crate::{
-    content::manager::{ContentManager, ContentManagerError},
+    content::{
+        manager::{ContentManager, ContentManagerError},
+        progress::DownloadProgress,
+    },
     lsx::{self, service::LSXServerError, types::LSXRequestType},
     rtm::client::{BasicPresence, RtmClient},
     util::native::{maxima_dir, NativeError},
@@ -75,13 +78,28 @@ pub enum MaximaEvent {
     ReceivedLSXRequest(u32, LSXRequestType),
     /// Offer ID. Use `maxima.mut_library().title_by_base_offer(id)` for details
     InstallFinished(String),
+    /// Offer ID, reason. Files that did download are kept, adding the game again continues
+    InstallFailed(String, String),
+    /// The download queue was added to, reordered, paused or resumed. Read it again from
+    /// `maxima.content_manager().queue()`
+    DownloadQueueChanged,
+    /// Sent periodically while a game downloads or moves, and whenever its phase changes
+    DownloadProgress(DownloadProgress),
+    /// Offer ID. The game's install record and registry keys point at its new folder
+    InstallMoved(String),
+    /// Offer ID, reason
+    InstallMoveFailed(String, String),
+    /// Offer ID. A build installed side by side is the active one now, see
+    /// [`crate::content::manager::ContentManager::activate_build`]
+    BuildActivated(String),
 }
 
 pub type MaximaLSXEventCallback = extern "C" fn(*const c_char);
 
 #[derive(Getters)]
 pub struct Maxima {
-    locale: Locale,
+    /// The language the user picked, see [`Maxima::locale`] for the one in use
+    locale_override: Option<Locale>,
 
     auth_storage: LockedAuthStorage,
     service_layer: ServiceLayerClient,
@@ -117,6 +135,9 @@ pub struct Maxima {
 pub struct MaximaOptions {
     load_auth_storage: bool,
     dummy_local_user: bool,
+    /// Pick up the download queue from the last session
+    #[builder(default)]
+    resume_downloads: bool,
 }
 
 #[derive(Error, Debug)]
@@ -201,17 +222,26 @@ impl Maxima {
             None
         };
 
+        let locale_override = locale::load_override().await;
+        let service_layer = ServiceLayerClient::new(auth_storage.clone());
+        service_layer.set_locale(match &locale_override {
+            Some(locale) => locale.clone(),
+            None => Locale::detect().await,
+        });
+        info!("Using locale {}", service_layer.locale());
+
         Ok(Arc::new(Mutex::new(Self {
-            locale: Locale::EnUs,
+            locale_override,
             auth_storage: auth_storage.clone(),
-            service_layer: ServiceLayerClient::new(auth_storage.clone()),
-            library: GameLibrary::new(auth_storage.clone()).await,
+            service_layer: service_layer.clone(),
+            library: GameLibrary::new(service_layer).await,
             playing: None,
             lsx_port,
             lsx_event_callback: None,
             lsx_connections: 0,
             cloud_sync: CloudSyncClient::new(auth_storage.clone()),
-            content_manager: ContentManager::new(auth_storage.clone(), false).await?,
+            content_manager: ContentManager::new(auth_storage.clone(), options.resume_downloads)
+                .await?,
             rtm: RtmClient::new(auth_storage),
             request_cache,
             dummy_local_user,
@@ -242,6 +272,29 @@ impl Maxima {
         Ok(())
     }
 
+    /// The language games, store and library text are in
+    pub fn locale(&self) -> Locale {
+        self.service_layer.locale()
+    }
+
+    /// The user's pick from [`Maxima::set_locale`], `None` when it's detected
+    pub fn locale_override(&self) -> Option<&Locale> {
+        self.locale_override.as_ref()
+    }
+
+    /// Saves `locale` as the user's pick, `None` goes back to detecting it. The library is
+    /// requested again in the new language the next time it's read.
+    pub async fn set_locale(&mut self, locale: Option<Locale>) -> Result<(), NativeError> {
+        locale::save_override(locale.as_ref()).await?;
+        self.service_layer.set_locale(match &locale {
+            Some(locale) => locale.clone(),
+            None => Locale::detect().await,
+        });
+
+        self.locale_override = locale;
+        Ok(())
+    }
+
     pub async fn access_token(&mut self) -> Result<String, TokenError> {
         let mut auth_storage = self.auth_storage.lock().await;
         match auth_storage.access_token().await? {
@@ -428,9 +481,20 @@ impl Maxima {
     }
 
     pub(super) fn set_lsx_connections(&mut self, connections: u16) {
+        if connections == 0 {
+            self.reset_download_utilization();
+        }
         self.lsx_connections = connections;
     }
 
+    /// Games slow downloads down while they run through LSX, that ends with them
+    fn reset_download_utilization(&self) {
+        let limiter = self.content_manager.limiter();
+        if limiter.utilization() < 1.0 {
+            limiter.set_utilization(1.0);
+        }
+    }
+
     pub fn set_player_started(&mut self) {
         match &mut self.playing {
             Some(ref mut playing) => playing.set_started(),
@@ -442,17 +506,46 @@ impl Maxima {
     pub async fn update(&mut self) {
         self.update_playing_status().await;
 
-        let result = self.content_manager.update().await;
+        let playing = self.playing.as_ref().map(|x| {
+            x.offer()
+                .as_ref()
+                .map_or("", |offer| offer.offer_id().as_str())
+        });
+        let result = self.content_manager.update(playing).await;
         match result {
             Err(err) => warn!("Failed to update content manager: {}", err),
-            Ok(result) => {
-                if let Some(event) = result {
+            Ok(events) => {
+                for event in events {
+                    if let MaximaEvent::InstallFinished(offer_id)
+                    | MaximaEvent::InstallMoved(offer_id)
+                    | MaximaEvent::BuildActivated(offer_id) = &event
+                    {
+                        self.register_legacy_install(offer_id).await;
+                    }
+
                     self.call_event(event);
                 }
             }
         }
     }
 
+    /// Legacy builds have no touchup pointing the registry at them, so Maxima does once
+    /// they're installed, moved or activated
+    async fn register_legacy_install(&mut self, offer_id: &str) {
+        let offer = match self.library.game_by_base_offer(offer_id).await {
+            Ok(Some(offer)) => offer,
+            Ok(None) => return,
+            Err(err) => {
+                warn!("Failed to look up {}: {}", offer_id, err);
+                return;
+            }
+        };
+
+        if let Err(err) = offer.register_legacy_install().await {
+            warn!("Failed to register install of {}: {}", offer_id, err);
+        }
+    }
+
     async fn update_playing_status(&mut self) {
         if self.lsx_connections > 0 || self.playing.is_none() {
             return;
@@ -492,6 +585,7 @@ impl Maxima {
             .await
             .ok();
         self.playing = None;
+        self.reset_download_utilization();
     }
 
     /// Returns whether this Maxima instance was constructed with a dummy
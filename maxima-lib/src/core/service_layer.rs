@@ -1,5 +1,7 @@
 #![allow(non_snake_case)]
 
+use std::sync::{Arc, RwLock};
+
 use log::debug;
 use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
@@ -136,6 +138,8 @@ define_graphql_request!(ContentfulProxy, GetHeroBackgroundImage, gameHubCollecti
 pub struct ServiceLayerClient {
     auth: LockedAuthStorage,
     client: Client,
+    /// Shared between clones, so everything holding one follows [`Self::set_locale`]
+    locale: Arc<RwLock<Locale>>,
 }
 
 impl ServiceLayerClient {
@@ -143,9 +147,19 @@ impl ServiceLayerClient {
         Self {
             auth,
             client: Client::new(),
+            locale: Arc::new(RwLock::new(Locale::default())),
         }
     }
 
+    /// The language store and library text is requested in
+    pub fn locale(&self) -> Locale {
+        self.locale.read().unwrap().clone()
+    }
+
+    pub fn set_locale(&self, locale: Locale) {
+        *self.locale.write().unwrap() = locale;
+    }
+
     pub async fn request<T, R>(
         &self,
         operation: &ServiceLayerGraphQLRequest,
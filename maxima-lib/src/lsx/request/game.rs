@@ -15,14 +15,25 @@ use crate::{
     make_lsx_handler_response,
 };
 
+/// The language the running game was started in
+async fn installed_language(state: LockedConnectionState) -> String {
+    let arc = state.write().await.maxima_arc();
+    let maxima = arc.lock().await;
+
+    match maxima.playing() {
+        Some(playing) => playing.locale().to_owned(),
+        None => maxima.locale().full_str().to_owned(),
+    }
+}
+
 pub async fn handle_game_info_request(
-    _: LockedConnectionState,
+    state: LockedConnectionState,
     request: LSXGetGameInfo,
 ) -> Result<Option<LSXResponseType>, LSXRequestError> {
     let game_info = match request.attr_GameInfoId {
         LSXGameInfoId::FreeTrial => "false".to_string(),
         LSXGameInfoId::Languages => LANGUAGES.to_string(),
-        LSXGameInfoId::InstalledLanguage => "en_US".to_string(),
+        LSXGameInfoId::InstalledLanguage => installed_language(state).await,
     };
 
     make_lsx_handler_response!(Response, GetGameInfoResponse, { attr_GameInfo: game_info })
@@ -33,9 +44,11 @@ pub async fn handle_game_info_request(
 /// Just realized we're still telling every game that it's titanfall.
 /// Should fix that at some point!
 pub async fn handle_all_game_info_request(
-    _: LockedConnectionState,
+    state: LockedConnectionState,
     _: LSXGetAllGameInfo,
 ) -> Result<Option<LSXResponseType>, LSXRequestError> {
+    let installed_language = installed_language(state).await;
+
     make_lsx_handler_response!(Response, GetAllGameInfoResponse, {
         attr_FullGamePurchased: true,
         attr_FullGameReleased: true,
@@ -49,7 +62,7 @@ pub async fn handle_all_game_info_request(
         attr_AvailableVersion: "1.0.1.3".to_string(),
         attr_DisplayName: "Titanfall® 2 Deluxe Edition".to_string(),
         attr_FreeTrial: false,
-        attr_InstalledLanguage: "en_US".to_string(),
+        attr_InstalledLanguage: installed_language,
         attr_FullGameReleaseDate: "2016-10-28T04:00:00".to_string(),
         attr_SystemTime: "2023-06-22T04:00:00".to_string()
     })
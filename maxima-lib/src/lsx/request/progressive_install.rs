@@ -1,4 +1,5 @@
 use crate::{
+    content::progressive::ProgressiveStatus,
     lsx::{
         connection::LockedConnectionState,
         request::LSXRequestError,
@@ -11,23 +12,56 @@ use crate::{
     make_lsx_handler_response,
 };
 
+/// Download state of the running offer and its ID. Games ask with IDs of their own, but
+/// only the running one can be asking.
+async fn running_offer(state: LockedConnectionState) -> (String, Option<ProgressiveStatus>) {
+    let arc = state.write().await.maxima_arc();
+    let mut maxima = arc.lock().await;
+
+    let offer_id = match maxima.playing().as_ref().and_then(|x| x.offer().as_ref()) {
+        Some(offer) => offer.offer_id().to_owned(),
+        None => return (String::new(), None),
+    };
+
+    let status = maxima.content_manager().progressive_status(&offer_id);
+    (offer_id, status)
+}
+
+fn item_id(requested: String, running: String) -> String {
+    if requested.is_empty() {
+        running
+    } else {
+        requested
+    }
+}
+
 pub async fn handle_pi_availability_request(
-    _: LockedConnectionState,
-    _: LSXIsProgressiveInstallationAvailable,
+    state: LockedConnectionState,
+    request: LSXIsProgressiveInstallationAvailable,
 ) -> Result<Option<LSXResponseType>, LSXRequestError> {
+    let (offer_id, status) = running_offer(state).await;
+
     make_lsx_handler_response!(Response, IsProgressiveInstallationAvailableResponse, {
-        attr_Available: false,
-        attr_ItemId: "Origin.OFR.50.0001456".to_string(),
+        attr_Available: status.is_some(),
+        attr_ItemId: item_id(request.attr_ItemId, offer_id),
     })
 }
 
 pub async fn handle_pi_installed_chunks_request(
-    _: LockedConnectionState,
+    state: LockedConnectionState,
     request: LSXAreChunksInstalled,
 ) -> Result<Option<LSXResponseType>, LSXRequestError> {
+    let (offer_id, status) = running_offer(state).await;
+
+    // Nothing downloading means the game is all there
+    let installed = match status {
+        Some(status) => request.chunk_ids.iter().all(|x| status.chunk_installed(*x)),
+        None => true,
+    };
+
     make_lsx_handler_response!(Response, AreChunksInstalledResponse, {
-        attr_ItemId: "Origin.OFR.50.0001456".to_string(),
-        attr_Installed: true,
+        attr_ItemId: item_id(request.attr_ItemId, offer_id),
+        attr_Installed: installed,
         chunk_ids: request.chunk_ids,
     })
 }
@@ -25,7 +25,7 @@ use xz2::read::XzDecoder;
 use crate::util::{
     github::{fetch_github_release, fetch_github_releases, github_download_asset, GithubRelease},
     native::{maxima_dir, DownloadError, NativeError, SafeParent, SafeStr, WineError},
-    registry::RegistryError,
+    registry::{RegistryError, RegistryValue},
 };
 
 lazy_static! {
@@ -435,6 +435,71 @@ pub async fn setup_wine_registry() -> Result<(), NativeError> {
     Ok(())
 }
 
+/// Deletes `keys` and everything below them from the prefix's registry
+pub async fn delete_wine_registry_keys(keys: &[String]) -> Result<(), NativeError> {
+    let mut reg_content = "Windows Registry Editor Version 5.00\n\n".to_string();
+    for key in keys {
+        reg_content.push_str(&format!("[-{}]\n\n", key));
+    }
+
+    let path = maxima_dir()?.join("temp").join("wine_delete.reg");
+    tokio::fs::create_dir_all(path.safe_parent()?).await?;
+
+    {
+        let mut reg_file = tokio::fs::File::create(&path).await?;
+        reg_file.write_all(reg_content.as_bytes()).await?;
+    }
+
+    run_wine_command(
+        "regedit",
+        Some(vec![path.safe_str()?]),
+        None,
+        false,
+        CommandType::Run,
+    )
+    .await?;
+
+    tokio::fs::remove_file(path).await?;
+    invalidate_mx_wine_registry().await;
+
+    Ok(())
+}
+
+/// Writes string `values` to the prefix's registry, creating their keys as needed
+pub async fn set_wine_registry_values(values: &[RegistryValue]) -> Result<(), NativeError> {
+    let mut reg_content = "Windows Registry Editor Version 5.00\n\n".to_string();
+    for value in values {
+        reg_content.push_str(&format!(
+            "[{}]\n\"{}\"=\"{}\"\n\n",
+            value.key,
+            value.name.replace("\\", "\\\\").replace('"', "\\\""),
+            value.data.replace("\\", "\\\\").replace('"', "\\\"")
+        ));
+    }
+
+    let path = maxima_dir()?.join("temp").join("wine_values.reg");
+    tokio::fs::create_dir_all(path.safe_parent()?).await?;
+
+    {
+        let mut reg_file = tokio::fs::File::create(&path).await?;
+        reg_file.write_all(reg_content.as_bytes()).await?;
+    }
+
+    run_wine_command(
+        "regedit",
+        Some(vec![path.safe_str()?]),
+        None,
+        false,
+        CommandType::Run,
+    )
+    .await?;
+
+    tokio::fs::remove_file(path).await?;
+    invalidate_mx_wine_registry().await;
+
+    Ok(())
+}
+
 pub type WineRegistry = HashMap<String, String>;
 
 lazy_static! {
@@ -488,6 +553,31 @@ pub async fn invalidate_mx_wine_registry() {
     MX_WINE_REGISTRY.lock().await.clear();
 }
 
+/// The prefix user's `LocaleName`, e.g. `de-DE`. Wine takes it from the environment when
+/// it creates the prefix.
+pub async fn wine_prefix_locale() -> Result<Option<String>, NativeError> {
+    let path = wine_prefix_dir()?.join("user.reg");
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let data = tokio::fs::read_to_string(path).await?;
+    let mut international = false;
+    for line in data.lines().map(|x| x.trim()) {
+        if line.starts_with('[') {
+            international = line
+                .to_lowercase()
+                .starts_with("[control panel\\\\international]");
+        } else if international {
+            if let Some(value) = line.strip_prefix("\"LocaleName\"=") {
+                return Ok(Some(value.trim_matches('"').to_owned()));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
 fn normalize_key(key: &str) -> String {
     let lower_key = key.to_lowercase();
     if lower_key.starts_with("hkey_local_machine\\") {
@@ -32,3 +32,24 @@ pub fn hash_file_crc32<P: AsRef<Path>>(path: P) -> io::Result<u32> {
 
     Ok(hasher.finalize())
 }
+
+/// CRC32 and hex encoded SHA-256 of a file, read once for both
+pub fn hash_file_crc32_sha256<P: AsRef<Path>>(path: P) -> io::Result<(u32, String)> {
+    const CHUNK_SIZE: usize = 1_000_000; // 1MB
+
+    let mut crc = Hasher::new();
+    let mut sha = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        crc.update(&buffer[..bytes_read]);
+        sha.update(&buffer[..bytes_read]);
+    }
+
+    Ok((crc.finalize(), hex::encode(sha.finish())))
+}
@@ -5,6 +5,8 @@ pub mod native;
 pub mod registry;
 pub mod simple_crypto;
 pub mod system_profiler_utils;
+#[cfg(test)]
+pub(crate) mod testing;
 pub mod wmi_utils;
 
 #[cfg(windows)]
@@ -122,6 +122,8 @@ pub enum NativeError {
     Pid(String),
     #[error("could not find PID pattern")]
     PidPattern,
+    #[error("could not find the disk `{0:?}` is on")]
+    Disk(Box<Path>),
 
     // Windows
     #[error("failed to elevate `{0}`")]
@@ -258,6 +260,69 @@ pub fn maxima_dir() -> Result<PathBuf, NativeError> {
     Ok(path)
 }
 
+/// Free space on the disk `path` is on, or will be on once it's created
+pub fn available_space<P: AsRef<Path>>(path: P) -> Result<u64, NativeError> {
+    use sysinfo::{DiskExt, System, SystemExt};
+
+    // Install folders usually don't exist yet, look at the closest parent that does
+    let mut path = path.as_ref().to_path_buf();
+    while !path.exists() && path.pop() {}
+
+    let mut system = System::new();
+    system.refresh_disks_list();
+
+    system
+        .disks()
+        .iter()
+        .filter(|x| path.starts_with(x.mount_point()))
+        .max_by_key(|x| x.mount_point().as_os_str().len())
+        .map(|x| x.available_space())
+        .ok_or(NativeError::Disk(path.into_boxed_path()))
+}
+
+/// Has the filesystem set `size` bytes aside for `file`, so it's written in one piece rather
+/// than scattered over the disk as it grows. Doesn't change the file's length everywhere.
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+pub fn reserve_space(file: &std::fs::File, size: u64) -> std::io::Result<()> {
+    use rustix::fs::{fallocate, FallocateFlags};
+
+    fallocate(file, FallocateFlags::empty(), 0, size)?;
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn reserve_space(file: &std::fs::File, size: u64) -> std::io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use winapi::um::{
+        fileapi::SetFileInformationByHandle, minwinbase::FileAllocationInfo,
+        winbase::FILE_ALLOCATION_INFO,
+    };
+
+    let mut info: FILE_ALLOCATION_INFO = unsafe { std::mem::zeroed() };
+    unsafe {
+        *info.AllocationSize.QuadPart_mut() = size as i64;
+    }
+
+    let result = unsafe {
+        SetFileInformationByHandle(
+            file.as_raw_handle() as _,
+            FileAllocationInfo,
+            &mut info as *mut _ as _,
+            std::mem::size_of::<FILE_ALLOCATION_INFO>() as u32,
+        )
+    };
+    if result == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(windows, target_os = "linux", target_os = "freebsd")))]
+pub fn reserve_space(_file: &std::fs::File, _size: u64) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
 #[cfg(unix)]
 pub fn platform_path<P: AsRef<Path>>(path: P) -> PathBuf {
     PathBuf::from(format!("Z:{}", path.as_ref().to_str().unwrap()))
@@ -1,7 +1,7 @@
 #[cfg(windows)]
 extern crate winapi;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 #[cfg(windows)]
@@ -35,7 +35,7 @@ use std::{collections::HashMap, env, fs};
 #[cfg(unix)]
 use crate::unix::fs::case_insensitive_path;
 
-use super::native::{module_path, NativeError, SafeParent, SafeStr};
+use super::native::{module_path, platform_path, NativeError, SafeParent, SafeStr};
 
 #[cfg(target_pointer_width = "64")]
 pub const REG_ARCH_PATH: &str = "SOFTWARE\\WOW6432Node";
@@ -76,6 +76,14 @@ pub enum RegistryError {
     QrcUnregistered,
 }
 
+/// A string value to write, `key` starting at the hive like `HKEY_LOCAL_MACHINE\SOFTWARE\...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistryValue {
+    pub key: String,
+    pub name: String,
+    pub data: String,
+}
+
 #[cfg(windows)]
 pub fn check_registry_validity() -> Result<(), RegistryError> {
     let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
@@ -226,6 +234,163 @@ pub async fn parse_partial_registry_path(key: &str) -> Result<PathBuf, RegistryE
     Ok(path)
 }
 
+/// The key an install check's value lives in, `[HKEY_LOCAL_MACHINE\SOFTWARE\EA Games\Foo\Install Dir]Foo.exe`
+/// gives `HKEY_LOCAL_MACHINE\SOFTWARE\EA Games\Foo`
+fn install_key(install_check: &str) -> Option<&str> {
+    if !install_check.starts_with('[') {
+        return None;
+    }
+
+    let value = install_check
+        .split(|c| c == '[' || c == ']')
+        .find(|s| !s.is_empty())?;
+    value.rfind('\\').map(|x| &value[..x])
+}
+
+/// The same key in the 32-bit view, touchup writes there on 64-bit systems
+fn wow6432_key(key: &str) -> String {
+    let lower = key.to_lowercase();
+    if lower.contains("wow6432node") {
+        return key.to_owned();
+    }
+
+    match lower.find("software\\") {
+        Some(idx) => {
+            let (start, end) = key.split_at(idx + "software\\".len());
+            format!("{}WOW6432Node\\{}", start, end)
+        }
+        None => key.to_owned(),
+    }
+}
+
+/// Deletes the registry key touchup wrote the game's install location to
+pub async fn remove_install_key(install_check: &str) -> Result<(), RegistryError> {
+    match install_key(install_check) {
+        Some(key) => remove_reg_keys(&[key.to_owned()]).await,
+        None => Ok(()),
+    }
+}
+
+/// Deletes `keys` and everything below them, in both views. Keys start at the hive like
+/// `HKEY_CURRENT_USER\SOFTWARE\...`, ones that don't exist are skipped.
+#[cfg(windows)]
+pub async fn remove_reg_keys(keys: &[String]) -> Result<(), RegistryError> {
+    for key in keys {
+        let (hive, sub_key) = open_hive(key)?;
+        for sub_key in [sub_key.to_owned(), wow6432_key(sub_key)] {
+            if let Err(err) = hive.delete_subkey_all(&sub_key) {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    return Err(err.into());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes `keys` and everything below them, in both views. Keys start at the hive like
+/// `HKEY_CURRENT_USER\SOFTWARE\...`, ones that don't exist are skipped.
+#[cfg(unix)]
+pub async fn remove_reg_keys(keys: &[String]) -> Result<(), RegistryError> {
+    use crate::unix::wine::delete_wine_registry_keys;
+
+    let mut all = Vec::new();
+    for key in keys {
+        for key in [key.to_owned(), wow6432_key(key)] {
+            if !all.contains(&key) {
+                all.push(key);
+            }
+        }
+    }
+
+    if all.is_empty() {
+        return Ok(());
+    }
+
+    Ok(delete_wine_registry_keys(&all).await?)
+}
+
+/// The hive `key` starts at and the rest of it, `HKEY_CURRENT_USER\SOFTWARE\Foo` gives
+/// `HKEY_CURRENT_USER` and `SOFTWARE\Foo`
+#[cfg(windows)]
+fn open_hive(key: &str) -> Result<(RegKey, &str), RegistryError> {
+    use winreg::enums::HKEY_CURRENT_USER;
+
+    let (hive, sub_key) = key
+        .split_once('\\')
+        .ok_or_else(|| RegistryError::Key(key.to_owned()))?;
+    let hive = if hive.eq_ignore_ascii_case("HKEY_LOCAL_MACHINE") {
+        RegKey::predef(HKEY_LOCAL_MACHINE)
+    } else if hive.eq_ignore_ascii_case("HKEY_CURRENT_USER") {
+        RegKey::predef(HKEY_CURRENT_USER)
+    } else {
+        return Err(RegistryError::Key(key.to_owned()));
+    };
+
+    Ok((hive, sub_key))
+}
+
+/// The key and value name an install check reads, `[HKEY_LOCAL_MACHINE\SOFTWARE\EA Games\Foo\Install Dir]Foo.exe`
+/// gives `HKEY_LOCAL_MACHINE\SOFTWARE\EA Games\Foo` and `Install Dir`
+fn install_value(install_check: &str) -> Option<(&str, &str)> {
+    let key = install_key(install_check)?;
+    let value = install_check
+        .split(|c| c == '[' || c == ']')
+        .find(|s| !s.is_empty())?;
+    Some((key, &value[key.len() + 1..]))
+}
+
+/// Points the registry values `paths` start from at `install_path`, what touchup does for
+/// DiP games. Written to both views, like touchup on 64-bit systems.
+pub async fn write_install_paths(
+    paths: &[String],
+    install_path: &Path,
+) -> Result<(), RegistryError> {
+    let data = platform_path(install_path).safe_str()?.replace('/', "\\");
+    let data = format!("{}\\", data.trim_end_matches('\\'));
+
+    let mut values = Vec::new();
+    for (key, name) in paths.iter().filter_map(|x| install_value(x)) {
+        for key in [key.to_owned(), wow6432_key(key)] {
+            let value = RegistryValue {
+                key,
+                name: name.to_owned(),
+                data: data.clone(),
+            };
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+    }
+
+    write_reg_values(&values).await
+}
+
+/// Writes string `values`, creating their keys as needed
+#[cfg(windows)]
+pub async fn write_reg_values(values: &[RegistryValue]) -> Result<(), RegistryError> {
+    for value in values {
+        let (hive, sub_key) = open_hive(&value.key)?;
+        let (key, _) = hive.create_subkey_with_flags(sub_key, KEY_WRITE)?;
+        key.set_value(&value.name, &value.data)?;
+    }
+
+    Ok(())
+}
+
+/// Writes string `values`, creating their keys as needed
+#[cfg(unix)]
+pub async fn write_reg_values(values: &[RegistryValue]) -> Result<(), RegistryError> {
+    use crate::unix::wine::set_wine_registry_values;
+
+    if values.is_empty() {
+        return Ok(());
+    }
+
+    Ok(set_wine_registry_values(values).await?)
+}
+
 #[cfg(windows)]
 pub fn read_game_path(name: &str) -> Result<PathBuf, RegistryError> {
     let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
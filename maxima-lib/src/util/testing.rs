@@ -0,0 +1,48 @@
+use std::path::{Path, PathBuf};
+
+/// Every file in `testdata/{dir}` and its contents, sorted by name
+pub(crate) fn fixtures(dir: &str) -> Vec<(PathBuf, Vec<u8>)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata")
+        .join(dir);
+    let mut fixtures: Vec<(PathBuf, Vec<u8>)> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|x| {
+            let path = x.unwrap().path();
+            let bytes = std::fs::read(&path).unwrap();
+            (path, bytes)
+        })
+        .collect();
+    fixtures.sort();
+    fixtures
+}
+
+/// An empty folder under the temp dir, gone once the test is done with it
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `name` has to be unique across the crate's tests, they run in parallel
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("maxima-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Writes `size` zeroes to `name`, creating the folders it's in
+    pub(crate) fn file(&self, name: &str, size: usize) {
+        let path = self.0.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, vec![0; size]).unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
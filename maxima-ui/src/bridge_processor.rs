@@ -1,6 +1,6 @@
 use crate::{
     bridge_thread::{self, BackendError},
-    views::downloads_view::QueuedDownload,
+    views::{downloads_view::QueuedDownload, settings_view::DownloadSettingsForm},
     BackendStallState, GameDetails, GameDetailsWrapper, MaximaEguiApp,
 };
 use log::{error, info, warn};
@@ -77,13 +77,25 @@ pub fn frontend_processor(app: &mut MaximaEguiApp, ctx: &egui::Context) {
                             if dl_ing.offer == offer_id {
                                 dl_ing.downloaded_bytes = progress.bytes;
                                 dl_ing.total_bytes = progress.bytes_total;
+                                dl_ing.phase = progress.phase;
+                                dl_ing.bytes_per_second = progress.bytes_per_second;
+                                dl_ing.eta = progress.eta;
+                                dl_ing.failed_files = progress.failed_files;
                             }
                         }
                     }
+                    DownloadSettingsUpdate(settings) => {
+                        app.download_form = DownloadSettingsForm::new(&settings);
+                        app.download_settings = settings;
+                    }
+                    LocaleUpdate(locale) => {
+                        app.game_language = locale;
+                    }
                     DownloadFinished(_) => {
                         // idk
                     }
-                    DownloadQueueUpdate(current, queue) => {
+                    DownloadQueueUpdate(current, queue, paused) => {
+                        app.downloads_paused = paused;
                         if let Some(current) = current {
                             if !app.installing_now.as_ref().is_some_and(|n| n.offer == current) {
                                 app.installing_now = Some(QueuedDownload {
@@ -102,8 +114,7 @@ pub fn frontend_processor(app: &mut MaximaEguiApp, ctx: &egui::Context) {
                                         rtn
                                     },
                                     offer: current,
-                                    downloaded_bytes: 0,
-                                    total_bytes: 0,
+                                    ..Default::default()
                                 })
                             }
                         } else {
@@ -124,10 +135,9 @@ pub fn frontend_processor(app: &mut MaximaEguiApp, ctx: &egui::Context) {
                                     rtn
                                 },
                                 offer: offer.clone(),
-                                downloaded_bytes: 0,
-                                total_bytes: 0,
+                                ..Default::default()
                             };
-                            app.install_queue.insert(offer, i_fucking_hate_this);
+                            app.install_queue.push(i_fucking_hate_this);
                         }
                     }
                 }
@@ -12,13 +12,20 @@ use crate::{
     GameDetails, GameInfo, GameSettings,
 };
 use maxima::{
-    content::manager::{
-        ContentManager, ContentManagerError, QueuedGameBuilder, QueuedGameBuilderError,
+    content::{
+        concurrency::Concurrency,
+        dedup::DedupMode,
+        manager::{ContentManager, ContentManagerError, QueuedGameBuilder, QueuedGameBuilderError},
+        progress::DownloadPhase,
+        retry::RetryPolicy,
+        schedule::DownloadSchedule,
+        settings::DownloadSettings,
     },
     core::{
         auth::storage::{AuthError, TokenError},
         launch::LaunchError,
         library::LibraryError,
+        locale::Locale,
         manifest::{self, ManifestError, MANIFEST_RELATIVE_PATH},
         service_layer::{
             ServiceGameImagesRequestBuilderError, ServiceHeroBackgroundImageRequestBuilderError,
@@ -73,6 +80,10 @@ pub enum InteractThreadLocateGameResponse {
 pub struct InteractThreadDownloadProgressResponse {
     pub bytes: usize,
     pub bytes_total: usize,
+    pub phase: DownloadPhase,
+    pub bytes_per_second: f64,
+    pub eta: Option<Duration>,
+    pub failed_files: usize,
 }
 
 pub enum MaximaLibRequest {
@@ -84,6 +95,23 @@ pub enum MaximaLibRequest {
     StartGameRequest(GameInfo, Option<GameSettings>),
     InstallGameRequest(String, PathBuf),
     LocateGameRequest(String),
+    /// Bytes per second, `None` for unlimited
+    SetDownloadLimitRequest(Option<u64>),
+    SetPreallocateRequest(bool),
+    SetRetryRequest(RetryPolicy),
+    SetConcurrencyRequest(Concurrency),
+    SetDedupRequest(DedupMode),
+    SetScheduleRequest(DownloadSchedule),
+    /// `None` to detect it
+    SetLocaleRequest(Option<Locale>),
+    PauseDownloadsRequest,
+    ResumeDownloadsRequest,
+    /// Offer ID
+    RemoveDownloadRequest(String),
+    /// Offer ID
+    MoveDownloadToFrontRequest(String),
+    /// Offer ID. Files the game created itself are kept
+    UninstallGameRequest(String),
     ShutdownRequest,
 }
 
@@ -102,7 +130,12 @@ pub enum MaximaLibResponse {
     ActiveGameChanged(Option<String>),
     DownloadProgressChanged(String, InteractThreadDownloadProgressResponse),
     DownloadFinished(String),
-    DownloadQueueUpdate(Option<String>, Vec<String>),
+    /// Current, queued, paused
+    DownloadQueueUpdate(Option<String>, Vec<String>, bool),
+    /// What the downloader goes by, sent on startup and whenever it changes
+    DownloadSettingsUpdate(DownloadSettings),
+    /// The user's pick of game language, `None` when it's detected
+    LocaleUpdate(Option<Locale>),
 }
 pub struct BridgeThread {
     pub backend_listener: Receiver<MaximaLibResponse>,
@@ -157,6 +190,10 @@ pub enum BackendError {
 
     #[error("backend-frontend communication channel disconnected")]
     ChannelDisconnected,
+    #[error("installing `{0}` failed: {1}")]
+    InstallFailed(String, String),
+    #[error("moving `{0}` failed: {1}")]
+    MoveFailed(String, String),
     #[error("tried to perform an action that requires being logged in, but was logged out")]
     LoggedOut,
 }
@@ -179,10 +216,22 @@ impl BridgeThread {
         }
 
         backend_responder
-            .send(MaximaLibResponse::DownloadQueueUpdate(current, queue))
+            .send(MaximaLibResponse::DownloadQueueUpdate(
+                current,
+                queue,
+                *content_manager.queue().paused(),
+            ))
             .unwrap();
     }
 
+    fn update_download_settings(
+        content_manager: &ContentManager,
+        backend_responder: &Sender<MaximaLibResponse>,
+    ) {
+        let settings = content_manager.settings().clone();
+        let _ = backend_responder.send(MaximaLibResponse::DownloadSettingsUpdate(settings));
+    }
+
     pub fn new(ctx: &Context, remote_provider_channel: Sender<UIImageCacheLoaderCommand>) -> Self {
         puffin::profile_function!();
         let (backend_commander, backend_cmd_listener) = std::sync::mpsc::channel();
@@ -287,6 +336,7 @@ impl BridgeThread {
             MaximaOptionsBuilder::default()
                 .dummy_local_user(false)
                 .load_auth_storage(true)
+                .resume_downloads(true)
                 .build()?,
         )
         .await?;
@@ -352,6 +402,13 @@ impl BridgeThread {
             ctx.request_repaint();
         }
 
+        {
+            let mut maxima = maxima_arc.lock().await;
+            Self::update_download_settings(maxima.content_manager(), &backend_responder);
+            let locale = maxima.locale_override().cloned();
+            let _ = backend_responder.send(MaximaLibResponse::LocaleUpdate(locale));
+        }
+
         let _ = EventThread::new(
             &ctx.clone(),
             maxima_arc.clone(),
@@ -388,24 +445,45 @@ impl BridgeThread {
                     };
                 }
 
-                if let Some(dl) = maxima.content_manager().current() {
-                    backend_responder.send(MaximaLibResponse::DownloadProgressChanged(
-                        dl.offer_id().to_string(),
-                        InteractThreadDownloadProgressResponse {
-                            bytes: dl.bytes_downloaded(),
-                            bytes_total: dl.bytes_total(),
-                        },
-                    ))?;
-                }
-
                 for ev in maxima.consume_pending_events() {
                     match ev {
                         maxima::core::MaximaEvent::ReceivedLSXRequest(_, _) => {}
                         maxima::core::MaximaEvent::InstallFinished(offer_id) => {
                             backend_responder
                                 .send(MaximaLibResponse::DownloadFinished(offer_id))?;
+                        }
+                        maxima::core::MaximaEvent::InstallFailed(offer_id, reason) => {
+                            backend_responder.send(MaximaLibResponse::NonFatalError(Box::new(
+                                BackendError::InstallFailed(offer_id, reason),
+                            )))?;
+                        }
+                        maxima::core::MaximaEvent::InstallMoved(offer_id) => {
+                            info!("Finished moving {}", offer_id);
+                        }
+                        maxima::core::MaximaEvent::InstallMoveFailed(offer_id, reason) => {
+                            backend_responder.send(MaximaLibResponse::NonFatalError(Box::new(
+                                BackendError::MoveFailed(offer_id, reason),
+                            )))?;
+                        }
+                        maxima::core::MaximaEvent::BuildActivated(offer_id) => {
+                            info!("Activated a new build of {}", offer_id);
+                        }
+                        maxima::core::MaximaEvent::DownloadQueueChanged => {
                             Self::update_queue(maxima.content_manager(), backend_responder.clone());
                         }
+                        maxima::core::MaximaEvent::DownloadProgress(progress) => {
+                            backend_responder.send(MaximaLibResponse::DownloadProgressChanged(
+                                progress.offer_id().to_owned(),
+                                InteractThreadDownloadProgressResponse {
+                                    bytes: *progress.bytes_downloaded(),
+                                    bytes_total: *progress.bytes_total(),
+                                    phase: *progress.phase(),
+                                    bytes_per_second: *progress.bytes_per_second(),
+                                    eta: *progress.eta(),
+                                    failed_files: progress.failed_files().len(),
+                                },
+                            ))?;
+                        }
                     }
                 }
             }
@@ -449,9 +527,10 @@ impl BridgeThread {
                         path.remove(path.len() - 1);
                     }
                     let path = PathBuf::from(path);
+                    let locale = maxima_arc.lock().await.locale().full_str();
                     let manifest = manifest::read(path.join(MANIFEST_RELATIVE_PATH)).await;
                     if let Ok(manifest) = manifest {
-                        let guh = manifest.run_touchup(&path).await;
+                        let guh = manifest.run_touchup(&path, locale).await;
                         if let Err(err) = guh {
                             let _ = backend_responder.send(MaximaLibResponse::LocateGameResponse(
                                 InteractThreadLocateGameResponse::Error(
@@ -502,12 +581,80 @@ impl BridgeThread {
                         .offer_id(offer)
                         .build_id(build.build_id().to_owned())
                         .path(path.to_owned())
+                        .locale(maxima.locale().full_str())
                         .build()?;
                     Ok(maxima.content_manager().add_install(game).await?)
                 }
                 MaximaLibRequest::StartGameRequest(info, settings) => {
                     Ok(start_game_request(maxima_arc.clone(), info, settings).await?)
                 }
+                MaximaLibRequest::SetDownloadLimitRequest(limit) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    let result = maxima.content_manager().set_bandwidth_limit(limit).await;
+                    Self::update_download_settings(maxima.content_manager(), &backend_responder);
+                    Ok(result?)
+                }
+                MaximaLibRequest::SetPreallocateRequest(preallocate) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    let result = maxima.content_manager().set_preallocate(preallocate).await;
+                    Self::update_download_settings(maxima.content_manager(), &backend_responder);
+                    Ok(result?)
+                }
+                MaximaLibRequest::SetRetryRequest(retry) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    let result = maxima.content_manager().set_retry_policy(retry).await;
+                    Self::update_download_settings(maxima.content_manager(), &backend_responder);
+                    Ok(result?)
+                }
+                MaximaLibRequest::SetConcurrencyRequest(concurrency) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    let result = maxima.content_manager().set_concurrency(concurrency).await;
+                    Self::update_download_settings(maxima.content_manager(), &backend_responder);
+                    Ok(result?)
+                }
+                MaximaLibRequest::SetDedupRequest(mode) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    let result = maxima.content_manager().set_dedup(mode).await;
+                    Self::update_download_settings(maxima.content_manager(), &backend_responder);
+                    Ok(result?)
+                }
+                MaximaLibRequest::SetScheduleRequest(schedule) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    let result = maxima.content_manager().set_schedule(schedule).await;
+                    Self::update_download_settings(maxima.content_manager(), &backend_responder);
+                    Ok(result?)
+                }
+                MaximaLibRequest::SetLocaleRequest(locale) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    let result = maxima.set_locale(locale).await;
+                    let locale = maxima.locale_override().cloned();
+                    let _ = backend_responder.send(MaximaLibResponse::LocaleUpdate(locale));
+                    Ok(result?)
+                }
+                MaximaLibRequest::PauseDownloadsRequest => {
+                    let mut maxima = maxima_arc.lock().await;
+                    Ok(maxima.content_manager().pause().await?)
+                }
+                MaximaLibRequest::ResumeDownloadsRequest => {
+                    let mut maxima = maxima_arc.lock().await;
+                    Ok(maxima.content_manager().resume().await?)
+                }
+                MaximaLibRequest::RemoveDownloadRequest(offer) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    Ok(maxima.content_manager().remove(&offer).await?)
+                }
+                MaximaLibRequest::MoveDownloadToFrontRequest(offer) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    Ok(maxima.content_manager().move_to_front(&offer).await?)
+                }
+                MaximaLibRequest::UninstallGameRequest(offer) => {
+                    let mut maxima = maxima_arc.lock().await;
+                    let game = maxima.mut_library().game_by_base_offer(&offer).await?.cloned();
+                    if let Some(game) = game {
+                        game.uninstall(maxima.content_manager(), true).await?;
+                    }
+                    Ok(())
+                }
                 MaximaLibRequest::ShutdownRequest => break 'outer Ok(()), //TODO: kill the bridge thread
             };
             if let Err(err) = action {
@@ -9,7 +9,11 @@ use egui::{
     Response, Rounding, Stroke, Style, TextureId, Ui, Vec2, ViewportBuilder, Visuals, Widget,
 };
 use log::error;
-use maxima::{core::library::OwnedOffer, util::log::init_logger};
+use maxima::{
+    content::settings::DownloadSettings,
+    core::{library::OwnedOffer, locale::Locale},
+    util::log::init_logger,
+};
 use std::{collections::HashMap, default::Default, ops::RangeInclusive, path::PathBuf};
 use strum_macros::EnumIter;
 use ui_image::{UIImageCache, UIImageType};
@@ -20,7 +24,7 @@ use views::{
         friends_view, FriendsViewBar, FriendsViewBarPage, FriendsViewBarStatusFilter, UIFriend,
     },
     game_view::{games_view, GameViewBar, GameViewBarGenre, GameViewBarPlatform},
-    settings_view::settings_view,
+    settings_view::{settings_view, DownloadSettingsForm},
     undefined_view::{coming_soon_view, undefined_view},
 };
 
@@ -303,8 +307,16 @@ pub struct MaximaEguiApp {
     playing_game: Option<String>,
     /// Currently downloading game
     installing_now: Option<QueuedDownload>,
-    /// Queue of game installs, indexed by offer ID
-    install_queue: HashMap<String, QueuedDownload>,
+    /// Queue of game installs, in download order
+    install_queue: Vec<QueuedDownload>,
+    /// Whether the download queue is paused
+    downloads_paused: bool,
+    /// Downloader settings, as the backend has them
+    download_settings: DownloadSettings,
+    /// Download settings being edited
+    download_form: DownloadSettingsForm,
+    /// Language of games and store text as the backend has it, `None` when it's detected
+    game_language: Option<Locale>,
     /// State for installer modal
     installer_state: InstallModalState,
     /// User Settings for the frontend
@@ -482,7 +494,11 @@ impl MaximaEguiApp {
             backend_state: BackendStallState::Starting,
             playing_game: None,
             installing_now: None,
-            install_queue: HashMap::new(),
+            install_queue: Vec::new(),
+            downloads_paused: false,
+            download_settings: DownloadSettings::default(),
+            download_form: DownloadSettingsForm::default(),
+            game_language: None,
             installer_state: InstallModalState::new(&settings),
             settings,
         }
@@ -821,7 +837,11 @@ impl MaximaEguiApp {
                                     ui.horizontal(|ui| {
                                         ui.label(positional_replace!(self.locale.localization.modals.game_settings.version, "version", &game.version.installed));
                                         ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
-                                            ui.add_enabled(false, egui::Button::new(format!("  {}  ", &self.locale.localization.modals.game_settings.uninstall.to_ascii_uppercase())));
+                                            if ui.add_enabled(self.playing_game.is_none(), egui::Button::new(format!("  {}  ", &self.locale.localization.modals.game_settings.uninstall.to_ascii_uppercase()))).clicked() {
+                                                self.backend.backend_commander.send(bridge_thread::MaximaLibRequest::UninstallGameRequest(game.offer.clone())).unwrap();
+                                                game.installed = false;
+                                                clear = true;
+                                            }
                                         });
                                     });
                                 } else {
@@ -912,9 +932,9 @@ impl MaximaEguiApp {
                                     ui.add_enabled_ui(valid, |ui| {
                                         if ui.add_sized(button_size, egui::Button::new(&self.locale.localization.modals.game_install.fresh_action)).clicked() {
                                             if self.installing_now.is_none() {
-                                                self.installing_now = Some(QueuedDownload { slug: game.slug.clone(), offer: game.offer.clone(), downloaded_bytes: 0, total_bytes: 0 });
+                                                self.installing_now = Some(QueuedDownload { slug: game.slug.clone(), offer: game.offer.clone(), ..Default::default() });
                                             } else {
-                                                self.install_queue.insert(game.offer.clone(),QueuedDownload { slug: game.slug.clone(), offer: game.offer.clone(), downloaded_bytes: 0, total_bytes: 0 });
+                                                self.install_queue.push(QueuedDownload { slug: game.slug.clone(), offer: game.offer.clone(), ..Default::default() });
                                             }
                                             self.backend.backend_commander.send(bridge_thread::MaximaLibRequest::InstallGameRequest(game.offer.clone(), path.join(slug))).unwrap();
 
@@ -21,6 +21,8 @@ pub struct LocalizedStrings {
     pub friends_view: LocalizedFriendsView,
     /// Settings page
     pub settings_view: LocalizedSettingsView,
+    /// Download queue page
+    pub downloads_view: LocalizedDownloadsView,
     /// Names of languages
     pub locale: LocalizedLocaleInfo,
     pub modals: LocalizedModals,
@@ -160,11 +162,45 @@ pub struct LocalizedSettingsView {
     pub performance: LocalizedPerformanceSettings,
 }
 
+#[derive(Deserialize)]
+pub struct LocalizedDownloadsView {
+    /// Shown over a download that hasn't started yet
+    pub queued: String,
+    /// Shown instead of what the current download is doing while downloads are paused
+    pub paused: String,
+    /// What the current download is doing
+    pub phase: LocalizedDownloadPhases,
+    /// Download speed, `{speed}` is an amount of bytes
+    pub speed: String,
+    /// Estimated time until the download is done, `{time}` is hours:minutes:seconds
+    pub time_left: String,
+    /// How many files of the download failed so far
+    pub failed_files: String,
+}
+
+#[derive(Deserialize)]
+pub struct LocalizedDownloadPhases {
+    /// Reading what's in the build
+    pub preparing: String,
+    /// Comparing installed files against the build, for updates
+    pub verifying: String,
+    pub downloading: String,
+    /// Running the game's setup steps, registry and redistributables
+    pub touchup: String,
+    /// Copying an installed game to another folder
+    pub moving: String,
+    pub finished: String,
+    /// The download gave up
+    pub failed: String,
+}
+
 #[derive(Deserialize)]
 pub struct LocalizedInterfaceSettings {
     pub header: String,
     /// Label for a combo box to select the frontend's language
     pub language: String,
+    /// Label for a combo box to select the language of games and store text
+    pub game_language: String,
 }
 
 #[derive(Deserialize)]
@@ -174,6 +210,28 @@ pub struct LocalizedGameInstallationSettings {
     pub default_folder: String,
     /// Checkbox for ignoring the out-of-date launch warning
     pub ignore_ood_warning: String,
+    /// Label for a number box limiting download speed, in KiB/s
+    pub download_limit: String,
+    /// Checkbox for sizing files up front while downloading
+    pub preallocate: String,
+    /// Label for a number box setting how many times a file is tried before giving up
+    pub retry_attempts: String,
+    /// Label for a number box capping the wait between two tries of a file
+    pub retry_max_delay: String,
+    /// Label for a number box setting how many files download in parallel
+    pub download_connections: String,
+    /// Checkbox for scaling parallel downloads with throughput
+    pub adaptive_downloads: String,
+    /// Checkbox for sharing identical files between installed builds
+    pub dedup_files: String,
+    /// Checkbox for falling back to hardlinks when sharing files
+    pub dedup_hardlinks: String,
+    /// Checkbox for only downloading between two hours, followed by the hours
+    pub download_window: String,
+    /// Label between the two hours of the download window
+    pub download_window_to: String,
+    /// Checkbox for pausing downloads while a game runs
+    pub pause_while_playing: String,
 }
 
 #[derive(Deserialize)]
@@ -1,17 +1,66 @@
-use egui::{pos2, vec2, Align2, Color32, FontId, Mesh, Rect, Rounding, Shape, Stroke, Ui, Widget};
+use egui::{pos2, vec2, Align2, Color32, FontId, Mesh, Rect, Rounding, Shape, Stroke, Ui};
 use humansize::DECIMAL;
+use maxima::content::progress::DownloadPhase;
+use std::time::Duration;
 
-use crate::{MaximaEguiApp, APP_MARGIN};
+use crate::{
+    bridge_thread::MaximaLibRequest,
+    translation_manager::{positional_replace, LocalizedDownloadsView},
+    MaximaEguiApp, APP_MARGIN,
+};
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct QueuedDownload {
     pub slug: String,
     pub offer: String,
     pub downloaded_bytes: usize,
     pub total_bytes: usize,
+    pub phase: DownloadPhase,
+    pub bytes_per_second: f64,
+    pub eta: Option<Duration>,
+    pub failed_files: usize,
     // maybe add a thing here for updates? idk there's no real api to hook this up to yet
 }
 
+fn status_text(
+    localization: &LocalizedDownloadsView,
+    game: &QueuedDownload,
+    paused: bool,
+) -> String {
+    if paused {
+        return localization.paused.clone();
+    }
+
+    let phases = &localization.phase;
+    let mut status = match game.phase {
+        DownloadPhase::Preparing => &phases.preparing,
+        DownloadPhase::Verifying => &phases.verifying,
+        DownloadPhase::Downloading => &phases.downloading,
+        DownloadPhase::Touchup => &phases.touchup,
+        DownloadPhase::Moving => &phases.moving,
+        DownloadPhase::Finished => &phases.finished,
+        DownloadPhase::Failed => &phases.failed,
+    }
+    .clone();
+
+    if game.phase == DownloadPhase::Downloading {
+        let speed = humansize::SizeFormatter::new(game.bytes_per_second as u64, DECIMAL);
+        status += " - ";
+        status += &positional_replace!(localization.speed, "speed", speed);
+        if let Some(eta) = game.eta {
+            let secs = eta.as_secs();
+            let time = format!("{}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60);
+            status += " - ";
+            status += &positional_replace!(localization.time_left, "time", time);
+        }
+    }
+    if game.failed_files > 0 {
+        status += " - ";
+        status += &positional_replace!(localization.failed_files, "count", game.failed_files);
+    }
+    status
+}
+
 fn render_queued(app: &mut MaximaEguiApp, ui: &mut Ui, game: &QueuedDownload, is_current: bool) {
     if is_current {
         ui.ctx().request_repaint();
@@ -124,7 +173,7 @@ fn render_queued(app: &mut MaximaEguiApp, ui: &mut Ui, game: &QueuedDownload, is
                 max: pos2(
                     progress_bar_rect.min.x
                         + (progress_bar_rect.width()
-                            * (game_dl.downloaded_bytes as f32 / game_dl.total_bytes as f32)),
+                            * (game_dl.downloaded_bytes as f32 / game_dl.total_bytes.max(1) as f32)),
                     progress_bar_rect.max.y,
                 ),
             };
@@ -137,6 +186,18 @@ fn render_queued(app: &mut MaximaEguiApp, ui: &mut Ui, game: &QueuedDownload, is
 
             ui.painter().rect_filled(progress_bar_progress, Rounding::same(0.0), Color32::WHITE);
 
+            ui.painter().text(
+                pos2(img_response.rect.max.x + 10.0, img_response.rect.min.y + 44.0),
+                Align2::LEFT_TOP,
+                status_text(
+                    &app.locale.localization.downloads_view,
+                    game_dl,
+                    app.downloads_paused,
+                ),
+                FontId::proportional(14.0),
+                Color32::WHITE,
+            );
+
             ui.painter().text(
                 progress_bar_rect.min - vec2(0.0, 8.0),
                 Align2::LEFT_BOTTOM,
@@ -159,25 +220,39 @@ fn render_queued(app: &mut MaximaEguiApp, ui: &mut Ui, game: &QueuedDownload, is
             );
 
             if ui.put(left_button_rect, egui::Button::new("🗙")).clicked() {
-                //TODO: Remove
+                app.backend
+                    .backend_commander
+                    .send(MaximaLibRequest::RemoveDownloadRequest(game_dl.offer.clone()))
+                    .unwrap();
             }
-            if ui.put(right_button_rect, egui::Button::new("⏸")).clicked() {
-                //TODO: Pause
+            let (icon, request) = if app.downloads_paused {
+                ("▶", MaximaLibRequest::ResumeDownloadsRequest)
+            } else {
+                ("⏸", MaximaLibRequest::PauseDownloadsRequest)
+            };
+            if ui.put(right_button_rect, egui::Button::new(icon)).clicked() {
+                app.backend.backend_commander.send(request).unwrap();
             }
         } else {
             ui.painter().text(
                 img_response.rect.max + vec2(18.0, -corner_radius),
                 Align2::LEFT_BOTTOM,
-                "Queued",
+                &app.locale.localization.downloads_view.queued,
                 FontId::proportional(22.0),
                 Color32::WHITE,
             );
 
             if ui.put(left_button_rect, egui::Button::new("🗙")).clicked() {
-                //TODO: Remove
+                app.backend
+                    .backend_commander
+                    .send(MaximaLibRequest::RemoveDownloadRequest(game_dl.offer.clone()))
+                    .unwrap();
             }
             if ui.put(right_button_rect, egui::Button::new("⮉")).clicked() {
-                //TODO: Move to top
+                app.backend
+                    .backend_commander
+                    .send(MaximaLibRequest::MoveDownloadToFrontRequest(game_dl.offer.clone()))
+                    .unwrap();
             }
         }
     });
@@ -188,16 +263,7 @@ pub fn downloads_view(app: &mut MaximaEguiApp, ui: &mut Ui) {
         render_queued(app, ui, &now, true);
         ui.separator();
     }
-    for (_offer, game) in app.install_queue.clone() {
-        if !_offer.eq(&game.offer) {
-            egui::Label::new(egui::RichText::new(format!(
-                "offer mismatch! {} vs {}",
-                _offer, game.offer
-            )))
-            .selectable(false)
-            .ui(ui);
-        } else {
-            render_queued(app, ui, &game, false);
-        }
+    for game in app.install_queue.clone() {
+        render_queued(app, ui, &game, false);
     }
 }
@@ -1,6 +1,65 @@
+use std::time::Duration;
+
 use egui::{vec2, Ui};
+use maxima::{
+    content::{
+        concurrency::Concurrency,
+        dedup::DedupMode,
+        retry::RetryPolicy,
+        schedule::{DownloadSchedule, DownloadWindow},
+        settings::DownloadSettings,
+    },
+    core::locale::Locale,
+};
+
+use crate::{bridge_thread, widgets::enum_dropdown::enum_dropdown, MaximaEguiApp};
 
-use crate::{widgets::enum_dropdown::enum_dropdown, MaximaEguiApp};
+/// The backend's download settings as this page edits them, refreshed whenever the backend
+/// sends them. Number boxes only send their value once the user's done with them, every
+/// change is saved to disk.
+#[derive(Default)]
+pub struct DownloadSettingsForm {
+    limit_kib: u64,
+    preallocate: bool,
+    retry_attempts: u32,
+    retry_max_delay_secs: u64,
+    /// 0 for the default
+    connections: usize,
+    adaptive: bool,
+    dedup: bool,
+    hardlinks: bool,
+    window: bool,
+    /// Hours of the day, the window runs past midnight if it ends before it starts
+    window_start: u16,
+    window_end: u16,
+    pause_while_playing: bool,
+}
+
+impl DownloadSettingsForm {
+    pub fn new(settings: &DownloadSettings) -> Self {
+        let (connections, adaptive) = match *settings.concurrency() {
+            Concurrency::Fixed(count) => (count, false),
+            Concurrency::Adaptive { max, .. } => (max, true),
+        };
+        let window = *settings.schedule().window();
+        let (window_start, window_end) = window.map_or((0, 0), |x| (x.start() / 60, x.end() / 60));
+
+        Self {
+            limit_kib: settings.max_bytes_per_second().unwrap_or(0) / 1024,
+            preallocate: *settings.preallocate(),
+            retry_attempts: *settings.retry().max_attempts(),
+            retry_max_delay_secs: settings.retry().max_delay_ms() / 1000,
+            connections,
+            adaptive,
+            dedup: *settings.dedup() != DedupMode::Off,
+            hardlinks: *settings.dedup() == DedupMode::Hardlink,
+            window: window.is_some(),
+            window_start,
+            window_end,
+            pause_while_playing: *settings.schedule().pause_while_playing(),
+        }
+    }
+}
 
 pub fn settings_view(app: &mut MaximaEguiApp, ui: &mut Ui) {
     let localization = &app.locale.localization.settings_view;
@@ -18,6 +77,37 @@ pub fn settings_view(app: &mut MaximaEguiApp, ui: &mut Ui) {
             &app.locale,
         );
     });
+    ui.horizontal(|ui| {
+        let system_default = &app.locale.localization.locale.default;
+        let selected: &str = match &app.game_language {
+            Some(locale) => locale.full_str(),
+            None => system_default,
+        };
+        let mut changed = false;
+        egui::ComboBox::new(
+            "Settings_GameLanguageComboBox",
+            &localization.interface.game_language,
+        )
+        .width(150.0)
+        .selected_text(selected)
+        .show_ui(ui, |ui| {
+            let language = &mut app.game_language;
+            changed |= ui.selectable_value(language, None, system_default).changed();
+            for locale in Locale::ALL {
+                changed |= ui
+                    .selectable_value(language, Some(locale.clone()), locale.full_str())
+                    .changed();
+            }
+        });
+        if changed {
+            app.backend
+                .backend_commander
+                .send(bridge_thread::MaximaLibRequest::SetLocaleRequest(
+                    app.game_language.clone(),
+                ))
+                .unwrap();
+        }
+    });
 
     ui.heading("");
     ui.heading(&localization.game_installation.header);
@@ -38,7 +128,9 @@ pub fn settings_view(app: &mut MaximaEguiApp, ui: &mut Ui) {
         &mut app.settings.ignore_ood_games,
         &app.locale.localization.settings_view.game_installation.ignore_ood_warning,
     );
+    download_settings(app, ui);
 
+    let localization = &app.locale.localization.settings_view;
     ui.heading("");
     ui.heading(&localization.performance.header);
     ui.separator();
@@ -47,3 +139,112 @@ pub fn settings_view(app: &mut MaximaEguiApp, ui: &mut Ui) {
         &localization.performance.disable_blur,
     );
 }
+
+fn download_settings(app: &mut MaximaEguiApp, ui: &mut Ui) {
+    let localization = &app.locale.localization.settings_view.game_installation;
+    let form = &mut app.download_form;
+    let mut requests = Vec::new();
+
+    ui.horizontal(|ui| {
+        let limit = ui.add(egui::DragValue::new(&mut form.limit_kib).speed(64));
+        if limit.drag_stopped() || limit.lost_focus() {
+            let limit = Some(form.limit_kib * 1024).filter(|x| *x > 0);
+            requests.push(bridge_thread::MaximaLibRequest::SetDownloadLimitRequest(
+                limit,
+            ));
+        }
+        ui.label(&localization.download_limit);
+    });
+    if ui.checkbox(&mut form.preallocate, &localization.preallocate).changed() {
+        requests.push(bridge_thread::MaximaLibRequest::SetPreallocateRequest(
+            form.preallocate,
+        ));
+    }
+    let attempts = ui
+        .horizontal(|ui| {
+            let attempts =
+                ui.add(egui::DragValue::new(&mut form.retry_attempts).clamp_range(1..=20));
+            ui.label(&localization.retry_attempts);
+            attempts
+        })
+        .inner;
+    let max_delay = ui
+        .horizontal(|ui| {
+            let max_delay =
+                ui.add(egui::DragValue::new(&mut form.retry_max_delay_secs).clamp_range(1..=600));
+            ui.label(&localization.retry_max_delay);
+            max_delay
+        })
+        .inner;
+    if [attempts, max_delay].iter().any(|x| x.drag_stopped() || x.lost_focus()) {
+        let current = app.download_settings.retry();
+        let retry = RetryPolicy::new(
+            form.retry_attempts,
+            Duration::from_millis(*current.base_delay_ms()),
+            Duration::from_secs(form.retry_max_delay_secs),
+        );
+        requests.push(bridge_thread::MaximaLibRequest::SetRetryRequest(retry));
+    }
+    let connections = ui
+        .horizontal(|ui| {
+            let connections =
+                ui.add(egui::DragValue::new(&mut form.connections).clamp_range(0..=128));
+            ui.label(&localization.download_connections);
+            connections
+        })
+        .inner;
+    let adaptive = ui.checkbox(&mut form.adaptive, &localization.adaptive_downloads);
+    if connections.drag_stopped() || connections.lost_focus() || adaptive.changed() {
+        let concurrency = Concurrency::from_count(Some(form.connections), form.adaptive);
+        requests.push(bridge_thread::MaximaLibRequest::SetConcurrencyRequest(
+            concurrency,
+        ));
+    }
+    let dedup = ui.checkbox(&mut form.dedup, &localization.dedup_files);
+    let hardlinks = ui.add_enabled(
+        form.dedup,
+        egui::Checkbox::new(&mut form.hardlinks, &localization.dedup_hardlinks),
+    );
+    if dedup.changed() || hardlinks.changed() {
+        let mode = match (form.dedup, form.hardlinks) {
+            (false, _) => DedupMode::Off,
+            (true, false) => DedupMode::Reflink,
+            (true, true) => DedupMode::Hardlink,
+        };
+        requests.push(bridge_thread::MaximaLibRequest::SetDedupRequest(mode));
+    }
+    let window = ui
+        .horizontal(|ui| {
+            let enabled = ui.checkbox(&mut form.window, &localization.download_window);
+            let start = ui.add_enabled(
+                form.window,
+                egui::DragValue::new(&mut form.window_start).clamp_range(0..=23).suffix(":00"),
+            );
+            ui.label(&localization.download_window_to);
+            let end = ui.add_enabled(
+                form.window,
+                egui::DragValue::new(&mut form.window_end).clamp_range(0..=23).suffix(":00"),
+            );
+            enabled.changed() || [start, end].iter().any(|x| x.drag_stopped() || x.lost_focus())
+        })
+        .inner;
+    let playing = ui.checkbox(
+        &mut form.pause_while_playing,
+        &localization.pause_while_playing,
+    );
+    if window || playing.changed() {
+        let window = Some(DownloadWindow::new(
+            form.window_start * 60,
+            form.window_end * 60,
+        ))
+        .filter(|_| form.window);
+        let schedule = DownloadSchedule::new(window, form.pause_while_playing);
+        requests.push(bridge_thread::MaximaLibRequest::SetScheduleRequest(
+            schedule,
+        ));
+    }
+
+    for request in requests {
+        app.backend.backend_commander.send(request).unwrap();
+    }
+}